@@ -4,8 +4,67 @@ use serde::{Deserialize, Serialize};
 pub struct ADSBMessage {
     pub station_id: String,
     pub station_name: String,
+    /// Wall clock time at which the provider received the message, in milliseconds since
+    /// the UNIX epoch
+    pub received_at: u64,
+    /// 48-bit receiver timestamp (12MHz clock), if supplied by the upstream feed
+    pub mlat_timestamp: Option<u64>,
+    /// Signal level in dBFS, if supplied by the upstream feed
+    pub rssi: Option<f64>,
     pub header: MessageHeader,
     pub payload: ADSBMessagePayload,
+    /// Where the receiving station is, if configured
+    pub station_location: Option<StationLocation>,
+}
+
+/// Location of a receiving station's antenna
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationLocation {
+    /// Free-form description of the location, e.g. `Windsor, CT`
+    pub label: String,
+    /// Antenna position, if configured
+    pub position: Option<Position>,
+    /// Antenna altitude in feet above mean sea level, if configured
+    pub altitude: Option<f64>,
+}
+
+/// Messages delivered to the actor in a single call, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ADSBMessageBatch {
+    pub messages: Vec<ADSBMessage>,
+}
+
+/// Connection state of a station's feed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Establishing the first connection to an upstream feed
+    Connecting,
+    /// Receiving frames from an upstream feed or a feeder
+    Connected,
+    /// The connection was lost or couldn't be established, and another attempt is pending
+    Reconnecting,
+    /// Replaying a capture file
+    Replaying,
+    /// The feed ended or the feeder went away
+    Disconnected,
+}
+
+/// Health of a station's feed, sent by the provider whenever its connection state changes
+/// and periodically while the binding is active
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatus {
+    pub station_id: String,
+    pub station_name: String,
+    pub state: ConnectionState,
+    /// Upstream feed, feeder address or capture file the station's frames come from
+    pub upstream: Option<String>,
+    /// Wall clock time of the most recent frame, in milliseconds since the UNIX epoch
+    pub last_frame_at: Option<u64>,
+    /// Frames received per second over the last reporting interval
+    pub message_rate: f64,
+    /// Wall clock time of the report, in milliseconds since the UNIX epoch
+    pub reported_at: u64,
+    pub location: Option<StationLocation>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -29,8 +88,8 @@ pub enum ADSBMessagePayload {
     AirbornePosition {
         /// Altitude in feet
         altitude: u16,
-
-        position: Position,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
     },
     /// Airborne velocity message (TC 19)
     AirborneVelocity {
@@ -42,6 +101,67 @@ pub enum ADSBMessagePayload {
         /// negative values indicate it is descending
         vertical_rate: i16,
     },
+    /// Position already decoded by the upstream feed (SBS transmission types 2 and 3)
+    DecodedPosition {
+        /// Altitude in feet, if the record carried one
+        #[serde(default)]
+        altitude: Option<u16>,
+        position: Position,
+        /// Indicates a surface position report
+        on_ground: bool,
+    },
+    /// Altitude reply to a Mode S surveillance interrogation
+    SurveillanceAltitude {
+        /// Altitude in feet
+        altitude: u16,
+    },
+    /// Identity reply to a Mode S surveillance interrogation
+    SurveillanceIdentity {
+        /// Mode A code as four octal digits, e.g. `7700`
+        squawk: String,
+    },
+    /// Surface position message (TC 5-8)
+    SurfacePosition {
+        /// Ground speed in knots, if available
+        ground_speed: Option<f64>,
+        /// Ground track in degrees, if available
+        track: Option<f64>,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
+    },
+    /// Airborne position message with GNSS height instead of barometric altitude (TC 20-22)
+    AirbornePositionGNSS {
+        /// GNSS height in feet
+        altitude: u16,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
+    },
+    /// Aircraft status message with emergency state and squawk (TC 28, subtype 1)
+    AircraftStatus {
+        emergency_state: EmergencyState,
+        /// Mode A code as four octal digits, e.g. `7700`
+        squawk: String,
+    },
+    /// Target state and status message (TC 29, subtype 1)
+    TargetState {
+        /// Altitude selected on the MCP/FCU or FMS in feet
+        selected_altitude: Option<u16>,
+        /// Barometric pressure setting in millibars
+        barometric_setting: Option<f64>,
+        /// Selected heading in degrees
+        selected_heading: Option<f64>,
+    },
+    /// Aircraft operational status message (TC 31)
+    OperationalStatus {
+        /// ADS-B version number
+        version: u8,
+        /// Indicates a surface (rather than airborne) operational status
+        on_ground: bool,
+        /// Navigational accuracy category for position
+        nac_p: u8,
+        /// Source integrity level
+        sil: u8,
+    },
 }
 
 /// Horizontal coordinates in the geographic coordinate system.
@@ -68,6 +188,25 @@ pub enum Parity {
     Odd,
 }
 
+/// Emergency/priority status broadcast in aircraft status messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EmergencyState {
+    None,
+    General,
+    Medical,
+    MinimumFuel,
+    NoCommunications,
+    UnlawfulInterference,
+    DownedAircraft,
+    Reserved,
+}
+
+impl Default for EmergencyState {
+    fn default() -> Self {
+        EmergencyState::None
+    }
+}
+
 /// Source for vertical rate information.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VerticalRateSource {
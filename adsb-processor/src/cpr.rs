@@ -0,0 +1,211 @@
+//! Compact Position Reporting (CPR) decoding.
+//!
+//! Position messages carry latitude and longitude as 17-bit values relative to a grid of
+//! zones. A real position can be recovered either globally, from a pair of even and odd
+//! frames, or locally, from a single frame and a reference position close to the aircraft.
+//! Surface position messages use the same encoding over a quarter of the span, giving four
+//! times the resolution.
+
+use crate::adsbtypes::{CPRFrame, Parity, Position};
+use std::f64::consts::PI;
+
+/// Number of latitude zones between the equator and a pole
+const NZ: f64 = 15.0;
+/// Scale of the 17-bit encoded latitude and longitude values
+const CPR_MAX: f64 = 131_072.0;
+/// Degrees covered by the zone grid of airborne position messages
+const AIRBORNE_SPAN: f64 = 360.0;
+/// Degrees covered by the zone grid of surface position messages
+const SURFACE_SPAN: f64 = 90.0;
+
+/// Decodes an airborne position from an even and an odd frame. The position is resolved
+/// for whichever frame is the most recent, as indicated by `latest`. Returns `None` if the
+/// frames were encoded in different longitude zones and cannot be combined.
+pub fn decode_global(even: &CPRFrame, odd: &CPRFrame, latest: &Parity) -> Option<Position> {
+    let lat_even = even.position.latitude / CPR_MAX;
+    let lon_even = even.position.longitude / CPR_MAX;
+    let lat_odd = odd.position.latitude / CPR_MAX;
+    let lon_odd = odd.position.longitude / CPR_MAX;
+
+    let j = (59.0 * lat_even - 60.0 * lat_odd + 0.5).floor();
+    let rlat_even =
+        wrap_latitude(d_lat(&Parity::Even, AIRBORNE_SPAN) * (modulo(j, 60.0) + lat_even));
+    let rlat_odd = wrap_latitude(d_lat(&Parity::Odd, AIRBORNE_SPAN) * (modulo(j, 59.0) + lat_odd));
+    if rlat_even.abs() > 90.0 || rlat_odd.abs() > 90.0 || nl(rlat_even) != nl(rlat_odd) {
+        return None;
+    }
+
+    let (latitude, lon_cpr) = match latest {
+        Parity::Even => (rlat_even, lon_even),
+        Parity::Odd => (rlat_odd, lon_odd),
+    };
+    let nl_lat = nl(latitude);
+    let ni = n_lon(nl_lat, latest);
+    let m = (lon_even * (nl_lat - 1.0) - lon_odd * nl_lat + 0.5).floor();
+    let longitude = wrap_longitude(360.0 / ni * (modulo(m, ni) + lon_cpr));
+
+    Some(Position {
+        latitude,
+        longitude,
+    })
+}
+
+/// Decodes an airborne position from a single frame using a reference position (the last
+/// known aircraft position or the receiving station) that lies within half a zone
+/// (roughly 180 NM) of the aircraft.
+pub fn decode_local(frame: &CPRFrame, reference: &Position) -> Position {
+    local(frame, reference, AIRBORNE_SPAN)
+}
+
+/// Decodes a surface position from a single frame. Surface zones are a quarter of the size
+/// of airborne zones, so the reference must lie within roughly 45 NM of the aircraft.
+pub fn decode_local_surface(frame: &CPRFrame, reference: &Position) -> Position {
+    local(frame, reference, SURFACE_SPAN)
+}
+
+fn local(frame: &CPRFrame, reference: &Position, span: f64) -> Position {
+    let lat_cpr = frame.position.latitude / CPR_MAX;
+    let lon_cpr = frame.position.longitude / CPR_MAX;
+
+    let d_lat = d_lat(&frame.parity, span);
+    let j = (reference.latitude / d_lat).floor()
+        + (0.5 + modulo(reference.latitude, d_lat) / d_lat - lat_cpr).floor();
+    let latitude = d_lat * (j + lat_cpr);
+
+    let d_lon = span / n_lon(nl(latitude), &frame.parity);
+    let m = (reference.longitude / d_lon).floor()
+        + (0.5 + modulo(reference.longitude, d_lon) / d_lon - lon_cpr).floor();
+    let longitude = wrap_longitude(d_lon * (m + lon_cpr));
+
+    Position {
+        latitude,
+        longitude,
+    }
+}
+
+/// Latitude zone size in degrees for the given frame parity and grid span
+fn d_lat(parity: &Parity, span: f64) -> f64 {
+    match parity {
+        Parity::Even => span / (4.0 * NZ),
+        Parity::Odd => span / (4.0 * NZ - 1.0),
+    }
+}
+
+/// Number of longitude zones for the given frame parity, never less than one
+fn n_lon(nl: f64, parity: &Parity) -> f64 {
+    match parity {
+        Parity::Even => nl.max(1.0),
+        Parity::Odd => (nl - 1.0).max(1.0),
+    }
+}
+
+/// The "NL" function: number of longitude zones at the given latitude
+fn nl(latitude: f64) -> f64 {
+    let latitude = latitude.abs();
+    if latitude < 1e-9 {
+        59.0
+    } else if latitude == 87.0 {
+        2.0
+    } else if latitude > 87.0 {
+        1.0
+    } else {
+        let a = 1.0 - (PI / (2.0 * NZ)).cos();
+        let b = latitude.to_radians().cos().powi(2);
+        (2.0 * PI / (1.0 - a / b).acos()).floor()
+    }
+}
+
+fn modulo(a: f64, b: f64) -> f64 {
+    a - b * (a / b).floor()
+}
+
+fn wrap_latitude(latitude: f64) -> f64 {
+    if latitude >= 270.0 {
+        latitude - 360.0
+    } else {
+        latitude
+    }
+}
+
+fn wrap_longitude(longitude: f64) -> f64 {
+    if longitude >= 180.0 {
+        longitude - 360.0
+    } else {
+        longitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(latitude: f64, longitude: f64, parity: Parity) -> CPRFrame {
+        CPRFrame {
+            position: Position {
+                latitude,
+                longitude,
+            },
+            parity,
+        }
+    }
+
+    fn assert_position(position: &Position, latitude: f64, longitude: f64) {
+        assert!(
+            (position.latitude - latitude).abs() < 1e-5
+                && (position.longitude - longitude).abs() < 1e-5,
+            "{:?} isn't {}, {}",
+            position,
+            latitude,
+            longitude
+        );
+    }
+
+    #[test]
+    fn decodes_global_position_for_latest_frame() {
+        let even = frame(93000.0, 51372.0, Parity::Even);
+        let odd = frame(74158.0, 50194.0, Parity::Odd);
+        let position = decode_global(&even, &odd, &Parity::Even).unwrap();
+        assert_position(&position, 52.25720, 3.91937);
+        let position = decode_global(&even, &odd, &Parity::Odd).unwrap();
+        assert_position(&position, 52.26578, 3.93891);
+    }
+
+    #[test]
+    fn rejects_global_pair_from_different_zones() {
+        let even = frame(93000.0, 51372.0, Parity::Even);
+        let odd = frame(1000.0, 50194.0, Parity::Odd);
+        assert!(decode_global(&even, &odd, &Parity::Odd).is_none());
+    }
+
+    #[test]
+    fn decodes_local_position_near_reference() {
+        let reference = Position {
+            latitude: 52.258,
+            longitude: 3.918,
+        };
+        let position = decode_local(&frame(93000.0, 51372.0, Parity::Even), &reference);
+        assert_position(&position, 52.25720, 3.91937);
+        let position = decode_local(&frame(74158.0, 50194.0, Parity::Odd), &reference);
+        assert_position(&position, 52.26578, 3.93891);
+    }
+
+    #[test]
+    fn decodes_local_surface_position() {
+        let reference = Position {
+            latitude: 51.990,
+            longitude: 4.375,
+        };
+        let position = decode_local_surface(&frame(39199.0, 110269.0, Parity::Odd), &reference);
+        assert_position(&position, 52.32061, 4.73473);
+    }
+
+    #[test]
+    fn nl_matches_zone_boundaries() {
+        assert_eq!(nl(0.0), 59.0);
+        assert_eq!(nl(10.47), 59.0);
+        assert_eq!(nl(10.48), 58.0);
+        assert_eq!(nl(-52.2572), 36.0);
+        assert_eq!(nl(87.0), 2.0);
+        assert_eq!(nl(87.5), 1.0);
+    }
+}
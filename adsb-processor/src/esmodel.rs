@@ -1,8 +1,44 @@
+use crate::adsbtypes::{
+    ADSBMessage, ADSBMessagePayload, CPRFrame, ConnectionState, EmergencyState, Parity, Position,
+    StationLocation, StationStatus,
+};
+use crate::{cpr, geo};
 use eventsourcing::{Aggregate, AggregateState};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 const DOMAIN_VERSION: &str = "1.0";
+/// Even and odd frames further apart than this may have been encoded in different zones
+/// and are not combined for global decoding
+const CPR_PAIR_MAX_AGE_MS: u64 = 10_000;
+/// A previous position is only used as the reference for local decoding this long after it
+/// was decoded
+const POSITION_REFERENCE_MAX_AGE_MS: u64 = 30_000;
+/// Fastest ground speed in knots an aircraft is expected to reach, which bounds how far it
+/// can have moved from its previous position
+const MAX_GROUND_SPEED_KT: f64 = 1_000.0;
+/// Airborne positions decoded locally relative to a station are only trusted within this
+/// range in nautical miles, well inside the half zone beyond which the decode is ambiguous
+const MAX_AIRBORNE_STATION_RANGE_NM: f64 = 150.0;
+/// Surface positions decoded locally relative to a station are only trusted within this
+/// range in nautical miles, as surface zones are a quarter of the size
+const MAX_SURFACE_STATION_RANGE_NM: f64 = 35.0;
+/// The stored state of an aircraft expires once it hasn't been heard from for this long
+pub const AIRCRAFT_EXPIRATION_SECONDS: u32 = 10 * 60;
+/// A flight ends once its aircraft hasn't been heard from for this long. This must stay
+/// below the expiry of the aircraft's state, or the flight would be forgotten unended.
+pub const FLIGHT_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+/// Aircraft first seen climbing below this altitude in feet are considered to have just
+/// taken off
+const TAKEOFF_ALTITUDE: u16 = 2_000;
+/// Stations that haven't received an aircraft for this long no longer count as seeing it
+const SEEN_BY_TIMEOUT_MS: u64 = 60_000;
+/// Stations that haven't received an aircraft for this long aren't considered as its source
+const SOURCE_TIMEOUT_MS: u64 = 10_000;
+/// How much stronger in dB another station must receive an aircraft to replace its source,
+/// which keeps the source from flipping between stations with similar reception
+const SOURCE_RSSI_MARGIN_DB: f64 = 3.0;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Event)]
 #[event_type_version(DOMAIN_VERSION)]
@@ -11,22 +47,189 @@ pub enum AdsbUpdateEvent {
     AircraftIdentified {
         icao_address: String,
         source_station: Station,
+        reception: Reception,
         emitter_category: u8,
         callsign: String,
     },
+    CPRFrameReceived {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        altitude: u16,
+        cpr_frame: CPRFrame,
+    },
+    /// An airborne position frame that reports GNSS height rather than barometric altitude
+    GNSSFrameReceived {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        /// GNSS height in feet
+        gnss_height: u16,
+        cpr_frame: CPRFrame,
+    },
+    SurfaceFrameReceived {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        ground_speed: Option<f64>,
+        track: Option<f64>,
+        cpr_frame: CPRFrame,
+    },
     PositionUpdated {
         icao_address: String,
         source_station: Station,
+        reception: Reception,
         altitude: u16,
-        position: crate::adsbtypes::Position,
+        position: Position,
+        #[serde(default)]
+        on_ground: bool,
+        /// Distance from the reporting station in nautical miles, if its location is known
+        #[serde(default)]
+        range: Option<f64>,
+        /// GNSS height in feet, if the position came from a GNSS position message. The
+        /// barometric `altitude` is then the last one known.
+        #[serde(default)]
+        gnss_height: Option<u16>,
     },
     VelocityUpdated {
         icao_address: String,
         source_station: Station,
+        reception: Reception,
         heading: f64,
         ground_speed: f64,
         vertical_rate: i16,
     },
+    AltitudeUpdated {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        altitude: u16,
+    },
+    SquawkUpdated {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        squawk: String,
+    },
+    AircraftStatusUpdated {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        emergency_state: EmergencyState,
+        squawk: String,
+    },
+    TargetStateUpdated {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        selected_altitude: Option<u16>,
+        barometric_setting: Option<f64>,
+        selected_heading: Option<f64>,
+    },
+    OperationalStatusUpdated {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        version: u8,
+        on_ground: bool,
+        nac_p: u8,
+        sil: u8,
+    },
+    StationStatusChanged {
+        source_station: Station,
+        status: FeedStatus,
+    },
+    FlightStarted {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        flight_id: String,
+        /// Callsign known when the flight started, which may still be empty
+        callsign: String,
+        reason: FlightStartReason,
+    },
+    FlightEnded {
+        icao_address: String,
+        flight_id: String,
+        callsign: String,
+        /// Wall clock start and end of the flight, in milliseconds since the UNIX epoch
+        started_at: u64,
+        ended_at: u64,
+        duration_seconds: u64,
+        reason: FlightEndReason,
+    },
+    EmergencyDeclared {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        callsign: String,
+        squawk: String,
+        /// The emergency, as broadcast or as implied by the squawk
+        emergency_state: EmergencyState,
+        altitude: u16,
+        /// Last known position of the aircraft, if any
+        position: Option<Position>,
+    },
+    EmergencyCleared {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        callsign: String,
+        squawk: String,
+    },
+    GeofenceEntered {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        geofence_id: String,
+        callsign: String,
+        /// Position and altitude in feet at which the aircraft was found within the fence
+        position: Position,
+        altitude: u16,
+    },
+    GeofenceExited {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+        geofence_id: String,
+        callsign: String,
+        /// Position and altitude in feet at which the aircraft was found outside the fence
+        position: Position,
+        altitude: u16,
+    },
+    /// A message received before the latest one applied to its aircraft, typically relayed
+    /// late by another station. Only its reception is recorded, so that older data doesn't
+    /// overwrite newer.
+    StaleMessageRejected {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+    },
+    /// A message from a station other than the aircraft's source while the source still
+    /// receives it. Every station relays the same broadcasts, so only its reception is
+    /// recorded.
+    RedundantMessageIgnored {
+        icao_address: String,
+        source_station: Station,
+        reception: Reception,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FlightStartReason {
+    /// The aircraft came into view already airborne
+    FirstSeen,
+    /// The aircraft went from the ground to airborne, or was first seen climbing from a low
+    /// altitude
+    Takeoff,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FlightEndReason {
+    /// The aircraft went from airborne to the ground
+    Landed,
+    /// The aircraft wasn't heard from for `FLIGHT_TIMEOUT_MS`
+    Timeout,
 }
 
 impl AdsbUpdateEvent {
@@ -34,51 +237,271 @@ impl AdsbUpdateEvent {
         match self {
             AdsbUpdateEvent::VelocityUpdated { icao_address, .. }
             | AdsbUpdateEvent::AircraftIdentified { icao_address, .. }
-            | AdsbUpdateEvent::PositionUpdated { icao_address, .. } => icao_address.to_string(),
+            | AdsbUpdateEvent::CPRFrameReceived { icao_address, .. }
+            | AdsbUpdateEvent::GNSSFrameReceived { icao_address, .. }
+            | AdsbUpdateEvent::PositionUpdated { icao_address, .. }
+            | AdsbUpdateEvent::AltitudeUpdated { icao_address, .. }
+            | AdsbUpdateEvent::SquawkUpdated { icao_address, .. }
+            | AdsbUpdateEvent::SurfaceFrameReceived { icao_address, .. }
+            | AdsbUpdateEvent::AircraftStatusUpdated { icao_address, .. }
+            | AdsbUpdateEvent::TargetStateUpdated { icao_address, .. }
+            | AdsbUpdateEvent::OperationalStatusUpdated { icao_address, .. }
+            | AdsbUpdateEvent::FlightStarted { icao_address, .. }
+            | AdsbUpdateEvent::FlightEnded { icao_address, .. }
+            | AdsbUpdateEvent::EmergencyDeclared { icao_address, .. }
+            | AdsbUpdateEvent::EmergencyCleared { icao_address, .. }
+            | AdsbUpdateEvent::GeofenceEntered { icao_address, .. }
+            | AdsbUpdateEvent::GeofenceExited { icao_address, .. }
+            | AdsbUpdateEvent::StaleMessageRejected { icao_address, .. }
+            | AdsbUpdateEvent::RedundantMessageIgnored { icao_address, .. } => {
+                icao_address.to_string()
+            }
+            AdsbUpdateEvent::StationStatusChanged { source_station, .. } => {
+                source_station.id.to_string()
+            }
+        }
+    }
+
+    /// The station that received the message behind an event and how, for events that
+    /// stand for a received message
+    pub fn reception(&self) -> Option<(&Station, &Reception)> {
+        match self {
+            AdsbUpdateEvent::AircraftIdentified {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::CPRFrameReceived {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::GNSSFrameReceived {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::PositionUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::VelocityUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::AltitudeUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::SquawkUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::SurfaceFrameReceived {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::AircraftStatusUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::TargetStateUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::OperationalStatusUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::StaleMessageRejected {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::RedundantMessageIgnored {
+                source_station,
+                reception,
+                ..
+            } => Some((source_station, reception)),
+            _ => None,
+        }
+    }
+}
+
+impl From<StationStatus> for AdsbUpdateEvent {
+    fn from(source: StationStatus) -> Self {
+        AdsbUpdateEvent::StationStatusChanged {
+            source_station: Station {
+                id: source.station_id,
+                name: source.station_name,
+                status: None,
+                location: source.location,
+            },
+            status: FeedStatus {
+                state: source.state,
+                upstream: source.upstream,
+                last_frame_at: source.last_frame_at,
+                message_rate: source.message_rate,
+                reported_at: source.reported_at,
+            },
+        }
+    }
+}
+
+impl From<&ADSBMessage> for Station {
+    fn from(source: &ADSBMessage) -> Self {
+        Station {
+            id: source.station_id.to_string(),
+            name: source.station_name.to_string(),
+            status: None,
+            location: source.station_location.clone(),
+        }
+    }
+}
+
+impl From<&ADSBMessage> for Reception {
+    fn from(source: &ADSBMessage) -> Self {
+        Reception {
+            received_at: source.received_at,
+            mlat_timestamp: source.mlat_timestamp,
+            rssi: source.rssi,
         }
     }
 }
 
-impl From<crate::adsbtypes::ADSBMessage> for AdsbUpdateEvent {
-    fn from(source: crate::adsbtypes::ADSBMessage) -> Self {
+impl From<ADSBMessage> for AdsbUpdateEvent {
+    fn from(source: ADSBMessage) -> Self {
+        let icao_address = source.header.icao_address.to_string();
+        let source_station = Station::from(&source);
+        let reception = Reception::from(&source);
         match source.payload {
-            crate::adsbtypes::ADSBMessagePayload::AircraftIdentification {
+            ADSBMessagePayload::AircraftIdentification {
                 emitter_category,
                 callsign,
             } => AdsbUpdateEvent::AircraftIdentified {
-                icao_address: source.header.icao_address.to_string(),
-                source_station: Station {
-                    id: source.station_id.to_string(),
-                    name: source.station_name.to_string(),
-                },
+                icao_address,
+                source_station,
+                reception,
                 emitter_category,
                 callsign: callsign.to_string(),
             },
-            crate::adsbtypes::ADSBMessagePayload::AirbornePosition { altitude, position } => {
-                AdsbUpdateEvent::PositionUpdated {
-                    icao_address: source.header.icao_address.to_string(),
-                    source_station: Station {
-                        id: source.station_id.to_string(),
-                        name: source.station_name.to_string(),
-                    },
-                    altitude,
-                    position,
-                }
-            }
-            crate::adsbtypes::ADSBMessagePayload::AirborneVelocity {
+            ADSBMessagePayload::AirbornePosition {
+                altitude,
+                cpr_frame,
+            } => AdsbUpdateEvent::CPRFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                altitude,
+                cpr_frame,
+            },
+            ADSBMessagePayload::AirborneVelocity {
                 heading,
                 ground_speed,
                 vertical_rate,
             } => AdsbUpdateEvent::VelocityUpdated {
-                icao_address: source.header.icao_address.to_string(),
-                source_station: Station {
-                    id: source.station_id.to_string(),
-                    name: source.station_name.to_string(),
-                },
+                icao_address,
+                source_station,
+                reception,
                 heading,
                 ground_speed,
                 vertical_rate,
             },
+            ADSBMessagePayload::AirbornePositionGNSS {
+                altitude,
+                cpr_frame,
+            } => AdsbUpdateEvent::GNSSFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                gnss_height: altitude,
+                cpr_frame,
+            },
+            ADSBMessagePayload::SurfacePosition {
+                ground_speed,
+                track,
+                cpr_frame,
+            } => AdsbUpdateEvent::SurfaceFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                ground_speed,
+                track,
+                cpr_frame,
+            },
+            // A missing altitude is filled in with the last one known by `handle_command`
+            ADSBMessagePayload::DecodedPosition {
+                altitude,
+                position,
+                on_ground,
+            } => AdsbUpdateEvent::PositionUpdated {
+                range: source_station.range_to(&position),
+                icao_address,
+                source_station,
+                reception,
+                altitude: altitude.unwrap_or_default(),
+                position,
+                on_ground,
+                gnss_height: None,
+            },
+            ADSBMessagePayload::SurveillanceAltitude { altitude } => {
+                AdsbUpdateEvent::AltitudeUpdated {
+                    icao_address,
+                    source_station,
+                    reception,
+                    altitude,
+                }
+            }
+            ADSBMessagePayload::SurveillanceIdentity { squawk } => AdsbUpdateEvent::SquawkUpdated {
+                icao_address,
+                source_station,
+                reception,
+                squawk,
+            },
+            ADSBMessagePayload::AircraftStatus {
+                emergency_state,
+                squawk,
+            } => AdsbUpdateEvent::AircraftStatusUpdated {
+                icao_address,
+                source_station,
+                reception,
+                emergency_state,
+                squawk,
+            },
+            ADSBMessagePayload::TargetState {
+                selected_altitude,
+                barometric_setting,
+                selected_heading,
+            } => AdsbUpdateEvent::TargetStateUpdated {
+                icao_address,
+                source_station,
+                reception,
+                selected_altitude,
+                barometric_setting,
+                selected_heading,
+            },
+            ADSBMessagePayload::OperationalStatus {
+                version,
+                on_ground,
+                nac_p,
+                sil,
+            } => AdsbUpdateEvent::OperationalStatusUpdated {
+                icao_address,
+                source_station,
+                reception,
+                version,
+                on_ground,
+                nac_p,
+                sil,
+            },
         }
     }
 }
@@ -90,11 +513,133 @@ pub struct AircraftState {
     pub emitter_category: u8,
     pub callsign: String,
     pub altitude: u16,
-    pub position: crate::adsbtypes::Position,
+    /// Height above the WGS-84 ellipsoid in feet, if the aircraft sends GNSS position
+    /// messages
+    #[serde(default)]
+    pub gnss_height: Option<u16>,
+    pub position: Position,
+    /// Indicates whether `position` holds a decoded position rather than the default
+    #[serde(default)]
+    pub position_known: bool,
+    /// Wall clock time `position` was decoded at, in milliseconds since the UNIX epoch
+    #[serde(default)]
+    pub position_updated_at: u64,
+    #[serde(default)]
+    pub on_ground: bool,
+    /// Distance from the last station that reported the position in nautical miles, if
+    /// its location is known
+    #[serde(default)]
+    pub range: Option<f64>,
     pub heading: f64,
     pub ground_speed: f64,
     pub vertical_rate: i16,
+    /// Mode A code as four octal digits
+    #[serde(default)]
+    pub squawk: String,
+    #[serde(default)]
+    pub emergency_state: EmergencyState,
+    /// Altitude selected on the MCP/FCU or FMS in feet
+    #[serde(default)]
+    pub selected_altitude: Option<u16>,
+    /// Selected heading in degrees
+    #[serde(default)]
+    pub selected_heading: Option<f64>,
+    /// Barometric pressure setting in millibars
+    #[serde(default)]
+    pub barometric_setting: Option<f64>,
+    /// ADS-B version reported in the aircraft's operational status
+    #[serde(default)]
+    pub adsb_version: u8,
+    /// Navigational accuracy category for position
+    #[serde(default)]
+    pub nac_p: u8,
+    /// Source integrity level
+    #[serde(default)]
+    pub sil: u8,
+    /// Wall clock time of the most recent message, in milliseconds since the UNIX epoch
+    #[serde(default)]
+    pub last_seen: u64,
     pub last_reporting_station_id: String,
+    #[serde(default)]
+    pub pending_frames: PendingFrames,
+    /// The flight in progress, if the aircraft is airborne
+    #[serde(default)]
+    pub flight: Option<FlightSession>,
+    /// Ids of the geofences the aircraft was last found within
+    #[serde(default)]
+    pub geofences: Vec<String>,
+    /// Stations that currently receive the aircraft, by station id. The one preferred as
+    /// the aircraft's source is `last_reporting_station_id`.
+    #[serde(default)]
+    pub seen_by: HashMap<String, StationReception>,
+}
+
+/// How a station last received an aircraft
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StationReception {
+    /// Wall clock receive time on the station's clock, in milliseconds since the UNIX epoch
+    pub last_seen: u64,
+    /// Signal level in dBFS, if supplied by the station's feed
+    pub rssi: Option<f64>,
+    /// Receive times of the other stations' latest receptions of the aircraft, each on its
+    /// station's clock, as of this station's reception. How far those stations have moved
+    /// on since tells how long ago that was without comparing different stations' clocks.
+    #[serde(default)]
+    pub others_at: HashMap<String, u64>,
+}
+
+impl StationReception {
+    /// How long ago in milliseconds the station last received the aircraft, going by the
+    /// stations that received it since
+    fn age(&self, seen_by: &HashMap<String, StationReception>) -> u64 {
+        self.others_at
+            .iter()
+            .filter_map(|(id, at)| {
+                seen_by
+                    .get(id)
+                    .map(|seen| seen.last_seen.saturating_sub(*at))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlightSession {
+    pub flight_id: String,
+    /// Wall clock start of the flight, in milliseconds since the UNIX epoch
+    pub started_at: u64,
+}
+
+/// The most recent even and odd CPR frames received for an aircraft, retained so that
+/// the next frame of the opposite parity can be combined with them to decode a position
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PendingFrames {
+    pub even: Option<PendingFrame>,
+    pub odd: Option<PendingFrame>,
+}
+
+impl PendingFrames {
+    /// The pending frames with a newly received frame replacing the one of its parity
+    pub fn with(&self, cpr_frame: &CPRFrame, received_at: u64) -> PendingFrames {
+        let mut pending_frames = self.clone();
+        let pending = Some(PendingFrame {
+            cpr_frame: cpr_frame.clone(),
+            received_at,
+        });
+        match cpr_frame.parity {
+            Parity::Even => pending_frames.even = pending,
+            Parity::Odd => pending_frames.odd = pending,
+        }
+        pending_frames
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingFrame {
+    pub cpr_frame: CPRFrame,
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub received_at: u64,
 }
 
 impl AggregateState for AircraftState {
@@ -103,6 +648,23 @@ impl AggregateState for AircraftState {
     }
 }
 
+impl AircraftState {
+    /// The emergency the aircraft is in, if any. The emergency state broadcast in status
+    /// messages takes precedence over the squawk, which is all that transponders without
+    /// ADS-B report.
+    pub fn emergency(&self) -> Option<EmergencyState> {
+        match &self.emergency_state {
+            EmergencyState::None | EmergencyState::Reserved => match self.squawk.as_str() {
+                "7500" => Some(EmergencyState::UnlawfulInterference),
+                "7600" => Some(EmergencyState::NoCommunications),
+                "7700" => Some(EmergencyState::General),
+                _ => None,
+            },
+            emergency_state => Some(emergency_state.clone()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct StationListState {
     pub generation: u64,
@@ -119,6 +681,53 @@ impl AggregateState for StationListState {
 pub struct Station {
     pub id: String,
     pub name: String,
+    /// Health of the station's feed as last reported by its provider. Only kept in the
+    /// station list, the stations attached to events leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<FeedStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<StationLocation>,
+}
+
+impl Station {
+    /// Position of the station's antenna, if configured
+    pub fn position(&self) -> Option<&Position> {
+        self.location
+            .as_ref()
+            .and_then(|location| location.position.as_ref())
+    }
+
+    /// Distance from the station to a position in nautical miles, if the station's
+    /// position is known
+    pub fn range_to(&self, position: &Position) -> Option<f64> {
+        self.position()
+            .map(|station| geo::distance_nm(station, position))
+    }
+}
+
+/// Health of a station's feed as reported by its provider
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedStatus {
+    pub state: ConnectionState,
+    /// Upstream feed, feeder address or capture file the station's frames come from
+    pub upstream: Option<String>,
+    /// Wall clock time of the most recent frame, in milliseconds since the UNIX epoch
+    pub last_frame_at: Option<u64>,
+    /// Frames received per second over the provider's last reporting interval
+    pub message_rate: f64,
+    /// Wall clock time of the report, in milliseconds since the UNIX epoch
+    pub reported_at: u64,
+}
+
+/// When and how well the message behind an event was received by its station
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Reception {
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub received_at: u64,
+    /// 48-bit receiver timestamp (12MHz clock), if supplied by the station's feed
+    pub mlat_timestamp: Option<u64>,
+    /// Signal level in dBFS, if supplied by the station's feed
+    pub rssi: Option<f64>,
 }
 
 pub struct Aircraft;
@@ -132,15 +741,55 @@ impl Aggregate for StationList {
     fn apply_event(state: &Self::State, evt: &Self::Event) -> eventsourcing::Result<Self::State> {
         match evt {
             AdsbUpdateEvent::AircraftIdentified { source_station, .. }
+            | AdsbUpdateEvent::CPRFrameReceived { source_station, .. }
+            | AdsbUpdateEvent::GNSSFrameReceived { source_station, .. }
             | AdsbUpdateEvent::PositionUpdated { source_station, .. }
-            | AdsbUpdateEvent::VelocityUpdated { source_station, .. } => {
+            | AdsbUpdateEvent::VelocityUpdated { source_station, .. }
+            | AdsbUpdateEvent::AltitudeUpdated { source_station, .. }
+            | AdsbUpdateEvent::SquawkUpdated { source_station, .. }
+            | AdsbUpdateEvent::SurfaceFrameReceived { source_station, .. }
+            | AdsbUpdateEvent::AircraftStatusUpdated { source_station, .. }
+            | AdsbUpdateEvent::TargetStateUpdated { source_station, .. }
+            | AdsbUpdateEvent::OperationalStatusUpdated { source_station, .. }
+            | AdsbUpdateEvent::StaleMessageRejected { source_station, .. }
+            | AdsbUpdateEvent::RedundantMessageIgnored { source_station, .. } => {
+                // Aircraft events don't carry the station's status, keep the last reported one
                 let mut state = state.clone();
-                state
+                let status = state
                     .stations
-                    .insert(source_station.id.to_string(), source_station.clone());
+                    .get(&source_station.id)
+                    .and_then(|station| station.status.clone());
+                state.stations.insert(
+                    source_station.id.to_string(),
+                    Station {
+                        status,
+                        ..source_station.clone()
+                    },
+                );
+                state.generation = state.generation + 1;
+                Ok(state)
+            }
+            AdsbUpdateEvent::StationStatusChanged {
+                source_station,
+                status,
+            } => {
+                let mut state = state.clone();
+                state.stations.insert(
+                    source_station.id.to_string(),
+                    Station {
+                        status: Some(status.clone()),
+                        ..source_station.clone()
+                    },
+                );
                 state.generation = state.generation + 1;
                 Ok(state)
             }
+            AdsbUpdateEvent::FlightStarted { .. }
+            | AdsbUpdateEvent::FlightEnded { .. }
+            | AdsbUpdateEvent::EmergencyDeclared { .. }
+            | AdsbUpdateEvent::EmergencyCleared { .. }
+            | AdsbUpdateEvent::GeofenceEntered { .. }
+            | AdsbUpdateEvent::GeofenceExited { .. } => Ok(state.clone()),
         }
     }
 
@@ -154,34 +803,90 @@ impl Aggregate for StationList {
 
 impl Aggregate for Aircraft {
     type Event = AdsbUpdateEvent;
-    type Command = ();
+    type Command = ADSBMessage;
     type State = AircraftState;
 
     fn apply_event(state: &Self::State, evt: &Self::Event) -> eventsourcing::Result<Self::State> {
-        match evt {
+        let next = match evt {
             AdsbUpdateEvent::AircraftIdentified {
-                source_station,
+                reception,
                 emitter_category,
                 callsign,
                 icao_address,
+                ..
             } => Ok(AircraftState {
                 icao_address: icao_address.to_string(),
-                last_reporting_station_id: source_station.id.to_string(),
                 emitter_category: *emitter_category,
                 callsign: callsign.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::CPRFrameReceived {
+                altitude,
+                cpr_frame,
+                reception,
+                icao_address,
+                ..
+            } => Ok(AircraftState {
+                altitude: *altitude,
+                icao_address: icao_address.to_string(),
+                pending_frames: state.pending_frames.with(cpr_frame, reception.received_at),
+                on_ground: false,
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::GNSSFrameReceived {
+                gnss_height,
+                cpr_frame,
+                reception,
+                icao_address,
+                ..
+            } => Ok(AircraftState {
+                gnss_height: Some(*gnss_height),
+                icao_address: icao_address.to_string(),
+                pending_frames: state.pending_frames.with(cpr_frame, reception.received_at),
+                on_ground: false,
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::SurfaceFrameReceived {
+                ground_speed,
+                track,
+                reception,
+                icao_address,
+                ..
+            } => Ok(AircraftState {
+                icao_address: icao_address.to_string(),
+                ground_speed: ground_speed.unwrap_or(state.ground_speed),
+                heading: track.unwrap_or(state.heading),
+                vertical_rate: 0,
+                on_ground: true,
+                last_seen: reception.received_at,
                 generation: state.generation + 1,
                 ..state.clone()
             }),
             AdsbUpdateEvent::PositionUpdated {
                 altitude,
                 position,
-                source_station,
+                on_ground,
+                range,
+                gnss_height,
+                reception,
                 icao_address,
+                ..
             } => Ok(AircraftState {
                 altitude: *altitude,
+                gnss_height: gnss_height.or(state.gnss_height),
                 icao_address: icao_address.to_string(),
                 position: position.clone(),
-                last_reporting_station_id: source_station.id.to_string(),
+                position_known: true,
+                position_updated_at: reception.received_at,
+                on_ground: *on_ground,
+                range: *range,
+                last_seen: reception.received_at,
                 generation: state.generation + 1,
                 ..state.clone()
             }),
@@ -189,24 +894,963 @@ impl Aggregate for Aircraft {
                 ground_speed,
                 heading,
                 icao_address,
-                source_station,
+                reception,
                 vertical_rate,
+                ..
             } => Ok(AircraftState {
                 ground_speed: *ground_speed,
                 heading: *heading,
                 icao_address: icao_address.to_string(),
-                last_reporting_station_id: source_station.id.to_string(),
                 vertical_rate: *vertical_rate,
+                last_seen: reception.received_at,
                 generation: state.generation + 1,
                 ..state.clone()
             }),
-        }
+            AdsbUpdateEvent::AltitudeUpdated {
+                altitude,
+                icao_address,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                altitude: *altitude,
+                icao_address: icao_address.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::SquawkUpdated {
+                squawk,
+                icao_address,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                squawk: squawk.to_string(),
+                icao_address: icao_address.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::AircraftStatusUpdated {
+                emergency_state,
+                squawk,
+                icao_address,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                emergency_state: emergency_state.clone(),
+                squawk: squawk.to_string(),
+                icao_address: icao_address.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::TargetStateUpdated {
+                selected_altitude,
+                barometric_setting,
+                selected_heading,
+                icao_address,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                selected_altitude: *selected_altitude,
+                barometric_setting: *barometric_setting,
+                selected_heading: *selected_heading,
+                icao_address: icao_address.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::OperationalStatusUpdated {
+                version,
+                on_ground,
+                nac_p,
+                sil,
+                icao_address,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                adsb_version: *version,
+                on_ground: *on_ground,
+                nac_p: *nac_p,
+                sil: *sil,
+                icao_address: icao_address.to_string(),
+                last_seen: reception.received_at,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::StationStatusChanged { .. } => Ok(state.clone()),
+            AdsbUpdateEvent::FlightStarted {
+                flight_id,
+                reception,
+                ..
+            } => Ok(AircraftState {
+                flight: Some(FlightSession {
+                    flight_id: flight_id.to_string(),
+                    started_at: reception.received_at,
+                }),
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            AdsbUpdateEvent::FlightEnded { .. } => Ok(AircraftState {
+                flight: None,
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+            // The squawk and emergency state behind these were applied by the events that
+            // raised them
+            AdsbUpdateEvent::EmergencyDeclared { .. }
+            | AdsbUpdateEvent::EmergencyCleared { .. } => Ok(state.clone()),
+            AdsbUpdateEvent::GeofenceEntered { geofence_id, .. } => {
+                let mut state = state.clone();
+                if !state.geofences.contains(geofence_id) {
+                    state.geofences.push(geofence_id.to_string());
+                }
+                state.generation = state.generation + 1;
+                Ok(state)
+            }
+            AdsbUpdateEvent::GeofenceExited { geofence_id, .. } => {
+                let mut state = state.clone();
+                state.geofences.retain(|id| id != geofence_id);
+                state.generation = state.generation + 1;
+                Ok(state)
+            }
+            AdsbUpdateEvent::StaleMessageRejected { .. }
+            | AdsbUpdateEvent::RedundantMessageIgnored { .. } => Ok(AircraftState {
+                generation: state.generation + 1,
+                ..state.clone()
+            }),
+        }?;
+        Ok(record_reception(next, evt))
     }
 
     fn handle_command(
-        _state: &Self::State,
-        _cmd: &Self::Command,
+        state: &Self::State,
+        cmd: &Self::Command,
     ) -> eventsourcing::Result<Vec<Self::Event>> {
-        todo!()
+        // Receive times are only comparable between messages of the same station, as each
+        // station has a clock of its own
+        let stale = state
+            .seen_by
+            .get(&cmd.station_id)
+            .map_or(false, |seen| cmd.received_at < seen.last_seen);
+        if stale {
+            return Ok(vec![AdsbUpdateEvent::StaleMessageRejected {
+                icao_address: cmd.header.icao_address.to_string(),
+                source_station: Station::from(cmd),
+                reception: Reception::from(cmd),
+            }]);
+        }
+        // Only the source's messages are applied, so that stations relaying with different
+        // delays don't interleave. Another station takes over once it becomes the preferred
+        // source, by receiving the aircraft markedly stronger or because the source stopped
+        // receiving it.
+        let redundant = AdsbUpdateEvent::RedundantMessageIgnored {
+            icao_address: cmd.header.icao_address.to_string(),
+            source_station: Station::from(cmd),
+            reception: Reception::from(cmd),
+        };
+        if record_reception(state.clone(), &redundant).last_reporting_station_id != cmd.station_id {
+            return Ok(vec![redundant]);
+        }
+        let mut event = AdsbUpdateEvent::from(cmd.clone());
+        // Decoded positions may come without an altitude, which must not wipe the known one
+        if let (
+            ADSBMessagePayload::DecodedPosition { altitude: None, .. },
+            AdsbUpdateEvent::PositionUpdated { altitude, .. },
+        ) = (&cmd.payload, &mut event)
+        {
+            *altitude = state.altitude;
+        }
+        let decoded = match &event {
+            AdsbUpdateEvent::CPRFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                altitude,
+                cpr_frame,
+            } => decode_position(
+                state,
+                cpr_frame,
+                reception.received_at,
+                source_station.position(),
+            )
+            .map(|position| AdsbUpdateEvent::PositionUpdated {
+                icao_address: icao_address.to_string(),
+                source_station: source_station.clone(),
+                reception: reception.clone(),
+                altitude: *altitude,
+                range: source_station.range_to(&position),
+                position,
+                on_ground: false,
+                gnss_height: None,
+            }),
+            AdsbUpdateEvent::GNSSFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                gnss_height,
+                cpr_frame,
+            } => decode_position(
+                state,
+                cpr_frame,
+                reception.received_at,
+                source_station.position(),
+            )
+            .map(|position| AdsbUpdateEvent::PositionUpdated {
+                icao_address: icao_address.to_string(),
+                source_station: source_station.clone(),
+                reception: reception.clone(),
+                altitude: state.altitude,
+                range: source_station.range_to(&position),
+                position,
+                on_ground: false,
+                gnss_height: Some(*gnss_height),
+            }),
+            // Surface frames can only be resolved locally, against the last known position
+            // or, failing that, the receiving station
+            AdsbUpdateEvent::SurfaceFrameReceived {
+                icao_address,
+                source_station,
+                reception,
+                cpr_frame,
+                ..
+            } => decode_local(
+                state,
+                cpr_frame,
+                reception.received_at,
+                source_station.position(),
+                cpr::decode_local_surface,
+                MAX_SURFACE_STATION_RANGE_NM,
+            )
+            .map(|position| AdsbUpdateEvent::PositionUpdated {
+                icao_address: icao_address.to_string(),
+                source_station: source_station.clone(),
+                reception: reception.clone(),
+                altitude: 0,
+                range: source_station.range_to(&position),
+                position,
+                on_ground: true,
+                gnss_height: None,
+            }),
+            _ => None,
+        };
+
+        let mut events = vec![event];
+        events.extend(decoded);
+        let alerts = emergency_events(state, &events, cmd)?;
+        let flight = flight_events(state, &events, cmd)?;
+        events.extend(alerts);
+        events.extend(flight);
+        Ok(events)
+    }
+}
+
+/// Raises an alert when the emergency an aircraft is in, by squawk or by the emergency
+/// state it broadcasts, is declared, changes or is cleared
+fn emergency_events(
+    state: &AircraftState,
+    events: &[AdsbUpdateEvent],
+    cmd: &ADSBMessage,
+) -> eventsourcing::Result<Vec<AdsbUpdateEvent>> {
+    let mut next = state.clone();
+    for event in events {
+        next = Aircraft::apply_event(&next, event)?;
+    }
+    let emergency = next.emergency();
+    if emergency == state.emergency() {
+        return Ok(Vec::new());
+    }
+    let event = match emergency {
+        Some(emergency_state) => AdsbUpdateEvent::EmergencyDeclared {
+            icao_address: cmd.header.icao_address.to_string(),
+            source_station: Station::from(cmd),
+            reception: Reception::from(cmd),
+            callsign: next.callsign.to_string(),
+            squawk: next.squawk.to_string(),
+            emergency_state,
+            altitude: next.altitude,
+            position: if next.position_known {
+                Some(next.position.clone())
+            } else {
+                None
+            },
+        },
+        None => AdsbUpdateEvent::EmergencyCleared {
+            icao_address: cmd.header.icao_address.to_string(),
+            source_station: Station::from(cmd),
+            reception: Reception::from(cmd),
+            callsign: next.callsign.to_string(),
+            squawk: next.squawk.to_string(),
+        },
+    };
+    Ok(vec![event])
+}
+
+/// Records which station received the message behind an event and how, forgets stations
+/// that stopped receiving the aircraft, and picks the aircraft's source among the others
+fn record_reception(mut state: AircraftState, evt: &AdsbUpdateEvent) -> AircraftState {
+    let (source_station, reception) = match evt.reception() {
+        Some(reception) => reception,
+        None => return state,
+    };
+    let station_id = &source_station.id;
+    // Stale messages don't roll the station's reception back
+    let newer = state
+        .seen_by
+        .get(station_id)
+        .map_or(true, |seen| seen.last_seen <= reception.received_at);
+    if newer {
+        let others_at = state
+            .seen_by
+            .iter()
+            .filter(|(id, _)| *id != station_id)
+            .map(|(id, seen)| (id.to_string(), seen.last_seen))
+            .collect();
+        // Stations that received the aircraft before this one did start aging on its clock
+        for (id, seen) in state.seen_by.iter_mut() {
+            if id != station_id {
+                seen.others_at
+                    .entry(station_id.to_string())
+                    .or_insert(reception.received_at);
+            }
+        }
+        state.seen_by.insert(
+            station_id.to_string(),
+            StationReception {
+                last_seen: reception.received_at,
+                rssi: reception.rssi,
+                others_at,
+            },
+        );
+    }
+    let ages = reception_ages(&state);
+    let current = |id: &String| ages.get(id).map_or(false, |age| *age <= SEEN_BY_TIMEOUT_MS);
+    state.seen_by.retain(|id, _| current(id));
+    for seen in state.seen_by.values_mut() {
+        seen.others_at.retain(|id, _| current(id));
+    }
+    if let Some(source) = preferred_source(&state, &ages) {
+        state.last_reporting_station_id = source;
+    }
+    state
+}
+
+/// How long ago in milliseconds each station last received the aircraft, by station id
+fn reception_ages(state: &AircraftState) -> HashMap<String, u64> {
+    state
+        .seen_by
+        .iter()
+        .map(|(id, seen)| (id.to_string(), seen.age(&state.seen_by)))
+        .collect()
+}
+
+/// The station that receives the aircraft best, by signal level and then by how recently it
+/// did. The current source is kept while it still receives the aircraft, unless another
+/// station receives it stronger by `SOURCE_RSSI_MARGIN_DB`.
+fn preferred_source(state: &AircraftState, ages: &HashMap<String, u64>) -> Option<String> {
+    let age = |id: &String| ages.get(id).copied().unwrap_or(0);
+    let fresh = |id: &String| age(id) <= SOURCE_TIMEOUT_MS;
+    let signal = |seen: &StationReception| seen.rssi.unwrap_or(std::f64::NEG_INFINITY);
+    let (best_id, best) =
+        state
+            .seen_by
+            .iter()
+            .filter(|(id, _)| fresh(id))
+            .max_by(|(a_id, a), (b_id, b)| {
+                signal(a)
+                    .partial_cmp(&signal(b))
+                    .unwrap_or(Ordering::Equal)
+                    .then(age(b_id).cmp(&age(a_id)))
+            })?;
+    let current_id = &state.last_reporting_station_id;
+    match state.seen_by.get(current_id).filter(|_| fresh(current_id)) {
+        Some(current) if signal(best) <= signal(current) + SOURCE_RSSI_MARGIN_DB => {
+            Some(current_id.to_string())
+        }
+        _ => Some(best_id.to_string()),
+    }
+}
+
+/// Ends the aircraft's flight if it hasn't been heard from for `FLIGHT_TIMEOUT_MS` as of
+/// `now` (milliseconds since the UNIX epoch). Aircraft that go out of range send nothing
+/// more, so this is also checked independently of their messages.
+pub fn flight_timeout(state: &AircraftState, now: u64) -> Option<AdsbUpdateEvent> {
+    let flight = state.flight.as_ref()?;
+    if now.saturating_sub(state.last_seen) <= FLIGHT_TIMEOUT_MS {
+        return None;
+    }
+    Some(flight_ended(
+        state,
+        flight,
+        state.last_seen,
+        FlightEndReason::Timeout,
+    ))
+}
+
+/// How many seconds the aircraft's state has left as of `now` before it expires, counting
+/// from its most recent message
+pub fn expiration_seconds(state: &AircraftState, now: u64) -> u32 {
+    let expiration_ms = u64::from(AIRCRAFT_EXPIRATION_SECONDS) * 1000;
+    let left_ms = expiration_ms.saturating_sub(now.saturating_sub(state.last_seen));
+    // Round up, so that the state is never stored without any time left
+    ((left_ms + 999) / 1000).max(1) as u32
+}
+
+/// Derives the start and end of flights from the events produced for a message. A flight
+/// starts when an aircraft that isn't flying is reported airborne, and ends when it is
+/// reported on the ground or after a prolonged silence.
+fn flight_events(
+    state: &AircraftState,
+    events: &[AdsbUpdateEvent],
+    cmd: &ADSBMessage,
+) -> eventsourcing::Result<Vec<AdsbUpdateEvent>> {
+    let mut flight_events = Vec::new();
+    let mut state = state.clone();
+    // Whatever the aircraft was doing before a prolonged silence is no longer known
+    let silent = cmd.received_at.saturating_sub(state.last_seen) > FLIGHT_TIMEOUT_MS;
+    let was_on_ground = state.on_ground && !silent;
+    if let Some(ended) = flight_timeout(&state, cmd.received_at) {
+        state = Aircraft::apply_event(&state, &ended)?;
+        flight_events.push(ended);
+    }
+
+    let on_ground = match events.iter().filter_map(reported_on_ground).last() {
+        Some(on_ground) => on_ground,
+        None => return Ok(flight_events),
+    };
+    let mut next = state.clone();
+    for event in events {
+        next = Aircraft::apply_event(&next, event)?;
+    }
+    match &state.flight {
+        Some(flight) if on_ground => flight_events.push(flight_ended(
+            &next,
+            flight,
+            cmd.received_at,
+            FlightEndReason::Landed,
+        )),
+        None if !on_ground => {
+            let climbing_out =
+                next.altitude > 0 && next.altitude < TAKEOFF_ALTITUDE && next.vertical_rate > 0;
+            let reason = if was_on_ground || climbing_out {
+                FlightStartReason::Takeoff
+            } else {
+                FlightStartReason::FirstSeen
+            };
+            flight_events.push(AdsbUpdateEvent::FlightStarted {
+                icao_address: cmd.header.icao_address.to_string(),
+                source_station: Station::from(cmd),
+                reception: Reception::from(cmd),
+                flight_id: format!("{}-{}", cmd.header.icao_address, cmd.received_at),
+                callsign: next.callsign.to_string(),
+                reason,
+            });
+        }
+        _ => {}
+    }
+    Ok(flight_events)
+}
+
+fn flight_ended(
+    state: &AircraftState,
+    flight: &FlightSession,
+    ended_at: u64,
+    reason: FlightEndReason,
+) -> AdsbUpdateEvent {
+    AdsbUpdateEvent::FlightEnded {
+        icao_address: state.icao_address.to_string(),
+        flight_id: flight.flight_id.to_string(),
+        callsign: state.callsign.to_string(),
+        started_at: flight.started_at,
+        ended_at,
+        duration_seconds: ended_at.saturating_sub(flight.started_at) / 1000,
+        reason,
+    }
+}
+
+/// Whether an event tells that its aircraft is on the ground or airborne, if it does
+fn reported_on_ground(event: &AdsbUpdateEvent) -> Option<bool> {
+    match event {
+        AdsbUpdateEvent::SurfaceFrameReceived { .. } => Some(true),
+        AdsbUpdateEvent::CPRFrameReceived { .. }
+        | AdsbUpdateEvent::GNSSFrameReceived { .. }
+        | AdsbUpdateEvent::VelocityUpdated { .. }
+        | AdsbUpdateEvent::TargetStateUpdated { .. } => Some(false),
+        AdsbUpdateEvent::PositionUpdated { on_ground, .. }
+        | AdsbUpdateEvent::OperationalStatusUpdated { on_ground, .. } => Some(*on_ground),
+        _ => None,
+    }
+}
+
+/// Resolves a newly received CPR frame into a real position. The frame is paired with a
+/// recent pending frame of the opposite parity for a global decode when there is one, and
+/// is otherwise decoded locally.
+fn decode_position(
+    state: &AircraftState,
+    frame: &CPRFrame,
+    received_at: u64,
+    station: Option<&Position>,
+) -> Option<Position> {
+    let opposite = match frame.parity {
+        Parity::Even => state.pending_frames.odd.as_ref(),
+        Parity::Odd => state.pending_frames.even.as_ref(),
+    }
+    .filter(|pending| received_at.saturating_sub(pending.received_at) <= CPR_PAIR_MAX_AGE_MS);
+    let global = opposite.and_then(|opposite| match frame.parity {
+        Parity::Even => cpr::decode_global(frame, &opposite.cpr_frame, &frame.parity),
+        Parity::Odd => cpr::decode_global(&opposite.cpr_frame, frame, &frame.parity),
+    });
+    global.or_else(|| {
+        decode_local(
+            state,
+            frame,
+            received_at,
+            station,
+            cpr::decode_local,
+            MAX_AIRBORNE_STATION_RANGE_NM,
+        )
+    })
+}
+
+/// Decodes a CPR frame relative to the aircraft's previous position while that is recent,
+/// or else relative to the receiving station's position, if configured. A local decode
+/// silently picks the wrong zone when the reference is too far off, so the position is
+/// dropped if the aircraft can't have flown there since its previous position, or if it
+/// lies beyond `station_range` nautical miles of the station.
+fn decode_local(
+    state: &AircraftState,
+    frame: &CPRFrame,
+    received_at: u64,
+    station: Option<&Position>,
+    decode: fn(&CPRFrame, &Position) -> Position,
+    station_range: f64,
+) -> Option<Position> {
+    let elapsed = received_at.saturating_sub(state.position_updated_at);
+    if state.position_known && elapsed <= POSITION_REFERENCE_MAX_AGE_MS {
+        // Allow an extra second for stations timestamping the same aircraft differently
+        let reachable = MAX_GROUND_SPEED_KT * (elapsed + 1_000) as f64 / 3_600_000.0;
+        let position = decode(frame, &state.position);
+        return Some(position)
+            .filter(|position| geo::distance_nm(&state.position, position) <= reachable);
+    }
+    station
+        .map(|station| (station, decode(frame, station)))
+        .filter(|(station, position)| geo::distance_nm(station, position) <= station_range)
+        .map(|(_, position)| position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsbtypes::MessageHeader;
+
+    fn message(station_id: &str, received_at: u64, rssi: f64) -> ADSBMessage {
+        message_with(
+            station_id,
+            received_at,
+            rssi,
+            ADSBMessagePayload::AirborneVelocity {
+                heading: 90.0,
+                ground_speed: 450.0,
+                vertical_rate: 0,
+            },
+        )
+    }
+
+    fn message_with(
+        station_id: &str,
+        received_at: u64,
+        rssi: f64,
+        payload: ADSBMessagePayload,
+    ) -> ADSBMessage {
+        ADSBMessage {
+            station_id: station_id.to_string(),
+            station_name: station_id.to_string(),
+            received_at,
+            mlat_timestamp: None,
+            rssi: Some(rssi),
+            header: MessageHeader {
+                downlink_format: 17,
+                capability: 5,
+                icao_address: "4840D6".to_string(),
+                type_code: 19,
+            },
+            payload,
+            station_location: None,
+        }
+    }
+
+    /// Handles a message and applies the resulting events, as the actor does
+    fn process(state: &AircraftState, cmd: &ADSBMessage) -> (AircraftState, Vec<AdsbUpdateEvent>) {
+        let events = Aircraft::handle_command(state, cmd).unwrap();
+        let mut next = state.clone();
+        for event in events.iter() {
+            next = Aircraft::apply_event(&next, event).unwrap();
+        }
+        (next, events)
+    }
+
+    fn redundant(events: &[AdsbUpdateEvent]) -> bool {
+        matches!(events, [AdsbUpdateEvent::RedundantMessageIgnored { .. }])
+    }
+
+    #[test]
+    fn applies_only_the_sources_messages() {
+        let (state, events) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        assert!(!redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "a");
+
+        // The other station's clock is well ahead, which doesn't matter
+        let (state, events) = process(&state, &message("b", 3_600_000, -11.0));
+        assert!(redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "a");
+        assert_eq!(state.seen_by.len(), 2);
+        assert_eq!(state.seen_by["b"].last_seen, 3_600_000);
+        assert_eq!(state.last_seen, 1_000);
+    }
+
+    #[test]
+    fn switches_to_a_markedly_stronger_station() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        let (state, events) = process(&state, &message("b", 500, -8.0));
+        assert!(redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "a");
+
+        let (state, events) = process(&state, &message("b", 600, -5.0));
+        assert!(!redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "b");
+        assert_eq!(state.last_seen, 600);
+    }
+
+    #[test]
+    fn switches_when_the_source_stops_receiving() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        let (state, _) = process(&state, &message("b", 50_000, -20.0));
+        let (state, events) = process(&state, &message("b", 50_000 + SOURCE_TIMEOUT_MS, -20.0));
+        assert!(redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "a");
+
+        let (state, events) = process(&state, &message("b", 50_001 + SOURCE_TIMEOUT_MS, -20.0));
+        assert!(!redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "b");
+        assert!(state.seen_by.contains_key("a"));
+    }
+
+    #[test]
+    fn ages_stations_on_their_own_clocks() {
+        // A station whose clock runs an hour ahead receives the aircraft after the source
+        // last did, which doesn't make the source's reception an hour old
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        let (state, _) = process(&state, &message("fast", 3_601_000, -20.0));
+        let (state, events) = process(&state, &message("a", 2_000, -10.0));
+        assert!(!redundant(&events));
+        let (state, events) = process(&state, &message("fast", 3_602_000, -20.0));
+        assert!(redundant(&events));
+        assert_eq!(state.last_reporting_station_id, "a");
+        assert_eq!(state.seen_by.len(), 2);
+        assert_eq!(reception_ages(&state)["a"], 1_000);
+    }
+
+    #[test]
+    fn forgets_stations_that_stopped_receiving() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        let (state, _) = process(&state, &message("b", 20_000, -20.0));
+        let (state, _) = process(&state, &message("b", 20_000 + SEEN_BY_TIMEOUT_MS, -20.0));
+        assert!(state.seen_by.contains_key("a"));
+
+        let (state, _) = process(&state, &message("b", 20_001 + SEEN_BY_TIMEOUT_MS, -20.0));
+        assert!(!state.seen_by.contains_key("a"));
+        assert!(state.seen_by["b"].others_at.is_empty());
+        assert_eq!(state.last_reporting_station_id, "b");
+
+        // A station heard from again starts over
+        let (state, events) = process(&state, &message("a", 70_000, -20.0));
+        assert!(redundant(&events));
+        assert_eq!(
+            state.seen_by["a"].others_at["b"],
+            20_001 + SEEN_BY_TIMEOUT_MS
+        );
+    }
+
+    #[test]
+    fn rejects_messages_older_than_the_stations_latest() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 2_000, -10.0));
+        let (state, events) = process(&state, &message("a", 1_500, -10.0));
+        match events.as_slice() {
+            [AdsbUpdateEvent::StaleMessageRejected { reception, .. }] => {
+                assert_eq!(reception.received_at, 1_500)
+            }
+            events => panic!("unexpected events {:?}", events),
+        }
+        assert_eq!(state.seen_by["a"].last_seen, 2_000);
+        assert_eq!(state.last_seen, 2_000);
+
+        // Only a station's own earlier messages make one stale
+        let (_, events) = process(&state, &message("b", 1_000, -20.0));
+        assert!(redundant(&events));
+    }
+
+    #[test]
+    fn applies_messages_of_a_single_station_regardless_of_gaps() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        let (state, events) = process(&state, &message("a", 1_000 + 2 * SEEN_BY_TIMEOUT_MS, -30.0));
+        assert!(!redundant(&events));
+        assert_eq!(state.seen_by.len(), 1);
+        assert_eq!(state.last_reporting_station_id, "a");
+    }
+
+    fn surface(station_id: &str, received_at: u64) -> ADSBMessage {
+        message_with(
+            station_id,
+            received_at,
+            -10.0,
+            ADSBMessagePayload::SurfacePosition {
+                ground_speed: Some(12.0),
+                track: None,
+                cpr_frame: CPRFrame {
+                    position: Position {
+                        latitude: 93000.0,
+                        longitude: 51372.0,
+                    },
+                    parity: Parity::Even,
+                },
+            },
+        )
+    }
+
+    fn flight_started(events: &[AdsbUpdateEvent]) -> Option<(&str, &FlightStartReason)> {
+        events.iter().find_map(|event| match event {
+            AdsbUpdateEvent::FlightStarted {
+                flight_id, reason, ..
+            } => Some((flight_id.as_str(), reason)),
+            _ => None,
+        })
+    }
+
+    fn flight_ended(events: &[AdsbUpdateEvent]) -> Option<(u64, u64, &FlightEndReason)> {
+        events.iter().find_map(|event| match event {
+            AdsbUpdateEvent::FlightEnded {
+                ended_at,
+                duration_seconds,
+                reason,
+                ..
+            } => Some((*ended_at, *duration_seconds, reason)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn starts_a_flight_when_first_seen_airborne() {
+        let (state, events) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        match flight_started(&events) {
+            Some((flight_id, FlightStartReason::FirstSeen)) => assert_eq!(flight_id, "4840D6-1000"),
+            started => panic!("unexpected flight start {:?}", started),
+        }
+        let flight = state.flight.unwrap();
+        assert_eq!(flight.flight_id, "4840D6-1000");
+        assert_eq!(flight.started_at, 1_000);
+
+        let (_, events) = process(&AircraftState::default(), &surface("a", 1_000));
+        assert!(flight_started(&events).is_none());
+    }
+
+    #[test]
+    fn starts_a_flight_on_takeoff_and_ends_it_on_landing() {
+        let (state, _) = process(&AircraftState::default(), &surface("a", 1_000));
+        assert!(state.on_ground);
+        let (state, events) = process(&state, &message("a", 61_000, -10.0));
+        match flight_started(&events) {
+            Some((flight_id, FlightStartReason::Takeoff)) => assert_eq!(flight_id, "4840D6-61000"),
+            started => panic!("unexpected flight start {:?}", started),
+        }
+        let (state, _) = process(&state, &message("a", 62_000, -10.0));
+        assert_eq!(state.flight.as_ref().unwrap().flight_id, "4840D6-61000");
+
+        let (state, events) = process(&state, &surface("a", 361_000));
+        assert_eq!(
+            flight_ended(&events),
+            Some((361_000, 300, &FlightEndReason::Landed))
+        );
+        assert!(state.flight.is_none());
+    }
+
+    #[test]
+    fn ends_a_flight_after_a_silence() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        assert!(flight_timeout(&state, 1_000 + FLIGHT_TIMEOUT_MS).is_none());
+        let ended = flight_timeout(&state, 1_001 + FLIGHT_TIMEOUT_MS).unwrap();
+        assert_eq!(
+            flight_ended(std::slice::from_ref(&ended)),
+            Some((1_000, 0, &FlightEndReason::Timeout))
+        );
+
+        // Once stored, the ended flight isn't ended again
+        let ended_state = Aircraft::apply_event(&state, &ended).unwrap();
+        assert!(ended_state.flight.is_none());
+        assert!(flight_timeout(&ended_state, 2_000 + FLIGHT_TIMEOUT_MS).is_none());
+
+        // The next message after the silence ends the flight if the sweep didn't, and starts
+        // a new one, as what the aircraft did in the meantime isn't known
+        let resumed_at = 1_001 + FLIGHT_TIMEOUT_MS;
+        let (state, events) = process(&state, &message("a", resumed_at, -10.0));
+        assert_eq!(
+            flight_ended(&events),
+            Some((1_000, 0, &FlightEndReason::Timeout))
+        );
+        match flight_started(&events) {
+            Some((flight_id, FlightStartReason::FirstSeen)) => {
+                assert_eq!(flight_id, format!("4840D6-{}", resumed_at))
+            }
+            started => panic!("unexpected flight start {:?}", started),
+        }
+        assert_eq!(state.flight.unwrap().started_at, resumed_at);
+    }
+
+    #[test]
+    fn keeps_the_expiration_of_silent_aircraft() {
+        let (state, _) = process(&AircraftState::default(), &message("a", 1_000, -10.0));
+        assert_eq!(
+            expiration_seconds(&state, 1_000),
+            AIRCRAFT_EXPIRATION_SECONDS
+        );
+        assert_eq!(
+            expiration_seconds(&state, 31_000 + FLIGHT_TIMEOUT_MS),
+            AIRCRAFT_EXPIRATION_SECONDS - 330
+        );
+        assert_eq!(
+            expiration_seconds(&state, 1_500),
+            AIRCRAFT_EXPIRATION_SECONDS
+        );
+        assert_eq!(expiration_seconds(&state, 3_600_000), 1);
+
+        // Ending the flight doesn't count as hearing from the aircraft
+        let ended = flight_timeout(&state, 31_000 + FLIGHT_TIMEOUT_MS).unwrap();
+        let ended_state = Aircraft::apply_event(&state, &ended).unwrap();
+        assert_eq!(ended_state.last_seen, 1_000);
+    }
+
+    fn squawk(received_at: u64, squawk: &str) -> ADSBMessage {
+        message_with(
+            "a",
+            received_at,
+            -10.0,
+            ADSBMessagePayload::SurveillanceIdentity {
+                squawk: squawk.to_string(),
+            },
+        )
+    }
+
+    fn status(received_at: u64, emergency_state: EmergencyState, squawk: &str) -> ADSBMessage {
+        message_with(
+            "a",
+            received_at,
+            -10.0,
+            ADSBMessagePayload::AircraftStatus {
+                emergency_state,
+                squawk: squawk.to_string(),
+            },
+        )
+    }
+
+    /// The emergency declared by the events, `Some(None)` if it was cleared instead
+    fn alert(events: &[AdsbUpdateEvent]) -> Option<Option<EmergencyState>> {
+        events.iter().find_map(|event| match event {
+            AdsbUpdateEvent::EmergencyDeclared {
+                emergency_state, ..
+            } => Some(Some(emergency_state.clone())),
+            AdsbUpdateEvent::EmergencyCleared { .. } => Some(None),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn declares_and_clears_emergencies_by_squawk() {
+        let state = AircraftState::default();
+        let (state, events) = process(&state, &squawk(1_000, "1200"));
+        assert_eq!(alert(&events), None);
+
+        let (state, events) = process(&state, &squawk(2_000, "7700"));
+        assert_eq!(alert(&events), Some(Some(EmergencyState::General)));
+        let (state, events) = process(&state, &squawk(3_000, "7700"));
+        assert_eq!(alert(&events), None);
+
+        let (state, events) = process(&state, &squawk(4_000, "7600"));
+        assert_eq!(alert(&events), Some(Some(EmergencyState::NoCommunications)));
+        let (state, events) = process(&state, &squawk(5_000, "7500"));
+        assert_eq!(
+            alert(&events),
+            Some(Some(EmergencyState::UnlawfulInterference))
+        );
+
+        let (state, events) = process(&state, &squawk(6_000, "1200"));
+        assert_eq!(alert(&events), Some(None));
+        assert_eq!(state.emergency(), None);
+    }
+
+    #[test]
+    fn declares_and_clears_broadcast_emergencies() {
+        let state = AircraftState::default();
+        let (state, events) = process(&state, &status(1_000, EmergencyState::Medical, "1200"));
+        assert_eq!(alert(&events), Some(Some(EmergencyState::Medical)));
+        match events
+            .iter()
+            .find(|event| matches!(event, AdsbUpdateEvent::EmergencyDeclared { .. }))
+        {
+            Some(AdsbUpdateEvent::EmergencyDeclared {
+                icao_address,
+                squawk,
+                reception,
+                ..
+            }) => {
+                assert_eq!(icao_address, "4840D6");
+                assert_eq!(squawk, "1200");
+                assert_eq!(reception.received_at, 1_000);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // The broadcast emergency takes precedence over the squawk
+        let (state, events) = process(&state, &status(2_000, EmergencyState::Medical, "7700"));
+        assert_eq!(alert(&events), None);
+        assert_eq!(state.emergency(), Some(EmergencyState::Medical));
+
+        // Without a broadcast emergency the squawk still tells of one
+        let (state, events) = process(&state, &status(3_000, EmergencyState::None, "7700"));
+        assert_eq!(alert(&events), Some(Some(EmergencyState::General)));
+
+        let (_, events) = process(&state, &status(4_000, EmergencyState::None, "1200"));
+        assert_eq!(alert(&events), Some(None));
+    }
+
+    #[test]
+    fn keeps_the_altitude_of_decoded_positions_without_one() {
+        let decoded = |received_at: u64, altitude: Option<u16>| {
+            message_with(
+                "a",
+                received_at,
+                -10.0,
+                ADSBMessagePayload::DecodedPosition {
+                    altitude,
+                    position: Position {
+                        latitude: 52.2572,
+                        longitude: 3.91937,
+                    },
+                    on_ground: false,
+                },
+            )
+        };
+        let (state, _) = process(&AircraftState::default(), &decoded(1_000, Some(38000)));
+        assert_eq!(state.altitude, 38000);
+
+        let (state, events) = process(&state, &decoded(2_000, None));
+        assert_eq!(state.altitude, 38000);
+        assert!(state.position_known);
+        assert!(matches!(
+            events.as_slice(),
+            [AdsbUpdateEvent::PositionUpdated {
+                altitude: 38000,
+                ..
+            }]
+        ));
     }
 }
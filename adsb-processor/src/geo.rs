@@ -0,0 +1,138 @@
+//! Distances between positions on the earth's surface, and whether positions lie within areas.
+
+use crate::adsbtypes::Position;
+
+/// Mean radius of the earth in nautical miles
+const EARTH_RADIUS_NM: f64 = 3440.065;
+
+/// Great-circle distance between two positions in nautical miles, using the haversine
+/// formula
+pub fn distance_nm(a: &Position, b: &Position) -> f64 {
+    let lat_a = a.latitude.to_radians();
+    let lat_b = b.latitude.to_radians();
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_NM * h.sqrt().min(1.0).asin()
+}
+
+/// Whether a position lies within a polygon, by the even-odd rule. Edges are taken as
+/// straight lines in latitude and longitude, which is accurate enough for areas spanning
+/// tens of miles. Longitudes are measured from the first vertex the short way round, so
+/// polygons may cross the antimeridian as long as they span less than half the globe.
+pub fn within_polygon(position: &Position, polygon: &[Position]) -> bool {
+    let reference = match polygon.first() {
+        Some(vertex) => vertex.longitude,
+        None => return false,
+    };
+    let longitude = |position: &Position| {
+        reference + (position.longitude - reference + 180.0).rem_euclid(360.0) - 180.0
+    };
+    let position_longitude = longitude(position);
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        if (a.latitude > position.latitude) != (b.latitude > position.latitude) {
+            let (a_longitude, b_longitude) = (longitude(a), longitude(b));
+            let crossing = a_longitude
+                + (position.latitude - a.latitude) / (b.latitude - a.latitude)
+                    * (b_longitude - a_longitude);
+            if position_longitude < crossing {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(latitude: f64, longitude: f64) -> Position {
+        Position {
+            latitude,
+            longitude,
+        }
+    }
+
+    fn square() -> Vec<Position> {
+        vec![
+            position(41.0, -73.0),
+            position(42.0, -73.0),
+            position(42.0, -72.0),
+            position(41.0, -72.0),
+        ]
+    }
+
+    #[test]
+    fn finds_positions_within_a_polygon() {
+        assert!(within_polygon(&position(41.5, -72.5), &square()));
+        assert!(!within_polygon(&position(42.5, -72.5), &square()));
+        assert!(!within_polygon(&position(41.5, -71.5), &square()));
+        assert!(!within_polygon(&position(41.5, -73.5), &square()));
+        assert!(!within_polygon(&position(40.5, -72.5), &square()));
+    }
+
+    #[test]
+    fn tells_positions_just_either_side_of_edges_apart() {
+        let epsilon = 1e-7;
+        for (latitude, longitude) in &[(41.0, -72.5), (42.0, -72.5)] {
+            let sign = if *latitude > 41.5 { 1.0 } else { -1.0 };
+            assert!(within_polygon(
+                &position(latitude - sign * epsilon, *longitude),
+                &square()
+            ));
+            assert!(!within_polygon(
+                &position(latitude + sign * epsilon, *longitude),
+                &square()
+            ));
+        }
+        for (latitude, longitude) in &[(41.5, -73.0), (41.5, -72.0)] {
+            let sign = if *longitude > -72.5 { 1.0 } else { -1.0 };
+            assert!(within_polygon(
+                &position(*latitude, longitude - sign * epsilon),
+                &square()
+            ));
+            assert!(!within_polygon(
+                &position(*latitude, longitude + sign * epsilon),
+                &square()
+            ));
+        }
+    }
+
+    #[test]
+    fn counts_edges_meeting_at_the_positions_latitude_once() {
+        let diamond = vec![
+            position(41.0, -72.5),
+            position(41.5, -73.0),
+            position(42.0, -72.5),
+            position(41.5, -72.0),
+        ];
+        assert!(within_polygon(&position(41.5, -72.5), &diamond));
+        assert!(!within_polygon(&position(41.5, -73.5), &diamond));
+        assert!(!within_polygon(&position(41.5, -71.5), &diamond));
+    }
+
+    #[test]
+    fn finds_positions_within_polygons_across_the_antimeridian() {
+        let pacific = vec![
+            position(-20.0, 175.0),
+            position(-15.0, 175.0),
+            position(-15.0, -175.0),
+            position(-20.0, -175.0),
+        ];
+        assert!(within_polygon(&position(-17.5, 179.5), &pacific));
+        assert!(within_polygon(&position(-17.5, -179.5), &pacific));
+        assert!(within_polygon(&position(-17.5, 180.0), &pacific));
+        assert!(!within_polygon(&position(-17.5, 170.0), &pacific));
+        assert!(!within_polygon(&position(-17.5, -170.0), &pacific));
+        assert!(!within_polygon(&position(-17.5, 0.0), &pacific));
+    }
+
+    #[test]
+    fn finds_nothing_within_an_empty_polygon() {
+        assert!(!within_polygon(&position(41.5, -72.5), &[]));
+    }
+}
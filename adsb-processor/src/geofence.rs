@@ -0,0 +1,246 @@
+//! Named areas, each a polygon with an optional altitude band, that aircraft are tracked
+//! entering and leaving. The fences are managed through the REST actor.
+
+use crate::adsbtypes::Position;
+use crate::esmodel::{AdsbUpdateEvent, AircraftState};
+use crate::geo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GeofenceList {
+    pub geofences: HashMap<String, Geofence>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Geofence {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// Vertices in order, the last one being joined back to the first
+    pub polygon: Vec<Position>,
+    /// Lower bound of the altitude band in feet, if any
+    #[serde(default)]
+    pub min_altitude: Option<u16>,
+    /// Upper bound of the altitude band in feet, if any
+    #[serde(default)]
+    pub max_altitude: Option<u16>,
+}
+
+impl Geofence {
+    pub fn contains(&self, position: &Position, altitude: u16) -> bool {
+        self.min_altitude.map_or(true, |min| altitude >= min)
+            && self.max_altitude.map_or(true, |max| altitude <= max)
+            && geo::within_polygon(position, &self.polygon)
+    }
+}
+
+impl GeofenceList {
+    /// Events for the fences an aircraft entered or left with the position decoded by an
+    /// event, given the fences its state has it within. Fences that have been removed count
+    /// as left. Other events cross no fences.
+    pub fn crossings(
+        &self,
+        event: &AdsbUpdateEvent,
+        state: &AircraftState,
+    ) -> Vec<AdsbUpdateEvent> {
+        let (icao_address, source_station, reception, position, altitude) = match event {
+            AdsbUpdateEvent::PositionUpdated {
+                icao_address,
+                source_station,
+                reception,
+                position,
+                altitude,
+                ..
+            } => (icao_address, source_station, reception, position, *altitude),
+            _ => return Vec::new(),
+        };
+
+        let mut ids: Vec<&String> = self.geofences.keys().collect();
+        ids.sort();
+        let entered = ids.into_iter().filter(|id| {
+            !state.geofences.contains(id) && self.geofences[*id].contains(position, altitude)
+        });
+        let left = state.geofences.iter().filter(|id| {
+            self.geofences
+                .get(*id)
+                .map_or(true, |geofence| !geofence.contains(position, altitude))
+        });
+
+        let mut crossings: Vec<AdsbUpdateEvent> = left
+            .map(|id| AdsbUpdateEvent::GeofenceExited {
+                icao_address: icao_address.to_string(),
+                source_station: source_station.clone(),
+                reception: reception.clone(),
+                geofence_id: id.to_string(),
+                callsign: state.callsign.to_string(),
+                position: position.clone(),
+                altitude,
+            })
+            .collect();
+        crossings.extend(entered.map(|id| AdsbUpdateEvent::GeofenceEntered {
+            icao_address: icao_address.to_string(),
+            source_station: source_station.clone(),
+            reception: reception.clone(),
+            geofence_id: id.to_string(),
+            callsign: state.callsign.to_string(),
+            position: position.clone(),
+            altitude,
+        }));
+        crossings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esmodel::{Reception, Station};
+
+    fn position(latitude: f64, longitude: f64) -> Position {
+        Position {
+            latitude,
+            longitude,
+        }
+    }
+
+    fn geofence(id: &str, min_altitude: Option<u16>, max_altitude: Option<u16>) -> Geofence {
+        Geofence {
+            id: id.to_string(),
+            name: id.to_string(),
+            polygon: vec![
+                position(41.0, -73.0),
+                position(42.0, -73.0),
+                position(42.0, -72.0),
+                position(41.0, -72.0),
+            ],
+            min_altitude,
+            max_altitude,
+        }
+    }
+
+    fn geofences(geofences: Vec<Geofence>) -> GeofenceList {
+        GeofenceList {
+            geofences: geofences
+                .into_iter()
+                .map(|geofence| (geofence.id.to_string(), geofence))
+                .collect(),
+        }
+    }
+
+    fn position_updated(position: Position, altitude: u16) -> AdsbUpdateEvent {
+        AdsbUpdateEvent::PositionUpdated {
+            icao_address: "4840D6".to_string(),
+            source_station: Station {
+                id: "station001".to_string(),
+                name: "Station".to_string(),
+                status: None,
+                location: None,
+            },
+            reception: Reception::default(),
+            altitude,
+            position,
+            on_ground: false,
+            range: None,
+            gnss_height: None,
+        }
+    }
+
+    fn within(ids: &[&str]) -> AircraftState {
+        AircraftState {
+            geofences: ids.iter().map(|id| id.to_string()).collect(),
+            ..AircraftState::default()
+        }
+    }
+
+    /// The crossings as `(entered, geofence id)`
+    fn crossed(events: &[AdsbUpdateEvent]) -> Vec<(bool, &str)> {
+        events
+            .iter()
+            .map(|event| match event {
+                AdsbUpdateEvent::GeofenceEntered { geofence_id, .. } => {
+                    (true, geofence_id.as_str())
+                }
+                AdsbUpdateEvent::GeofenceExited { geofence_id, .. } => {
+                    (false, geofence_id.as_str())
+                }
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enters_and_exits_fences() {
+        let list = geofences(vec![geofence("bdl", None, None)]);
+        let inside = position_updated(position(41.5, -72.5), 3000);
+        let outside = position_updated(position(42.5, -72.5), 3000);
+
+        assert_eq!(
+            crossed(&list.crossings(&inside, &within(&[]))),
+            vec![(true, "bdl")]
+        );
+        assert!(list.crossings(&inside, &within(&["bdl"])).is_empty());
+        assert_eq!(
+            crossed(&list.crossings(&outside, &within(&["bdl"]))),
+            vec![(false, "bdl")]
+        );
+        assert!(list.crossings(&outside, &within(&[])).is_empty());
+    }
+
+    #[test]
+    fn enters_and_exits_altitude_bands() {
+        let list = geofences(vec![geofence("low", Some(1000), Some(5000))]);
+        let at = |altitude| position_updated(position(41.5, -72.5), altitude);
+
+        assert!(list.crossings(&at(999), &within(&[])).is_empty());
+        assert_eq!(
+            crossed(&list.crossings(&at(1000), &within(&[]))),
+            vec![(true, "low")]
+        );
+        assert!(list.crossings(&at(5000), &within(&["low"])).is_empty());
+        assert_eq!(
+            crossed(&list.crossings(&at(5001), &within(&["low"]))),
+            vec![(false, "low")]
+        );
+    }
+
+    #[test]
+    fn exits_before_entering_in_order_of_fence_id() {
+        let list = geofences(vec![
+            geofence("c", None, None),
+            geofence("a", None, None),
+            geofence("b", Some(10000), None),
+        ]);
+        let event = position_updated(position(41.5, -72.5), 3000);
+        assert_eq!(
+            crossed(&list.crossings(&event, &within(&["b"]))),
+            vec![(false, "b"), (true, "a"), (true, "c")]
+        );
+    }
+
+    #[test]
+    fn exits_removed_fences() {
+        let list = geofences(vec![geofence("bdl", None, None)]);
+        let event = position_updated(position(41.5, -72.5), 3000);
+        assert_eq!(
+            crossed(&list.crossings(&event, &within(&["bdl", "removed"]))),
+            vec![(false, "removed")]
+        );
+    }
+
+    #[test]
+    fn crosses_nothing_without_a_position() {
+        let list = geofences(vec![geofence("bdl", None, None)]);
+        let event = AdsbUpdateEvent::AltitudeUpdated {
+            icao_address: "4840D6".to_string(),
+            source_station: Station {
+                id: "station001".to_string(),
+                name: "Station".to_string(),
+                status: None,
+                location: None,
+            },
+            reception: Reception::default(),
+            altitude: 3000,
+        };
+        assert!(list.crossings(&event, &within(&[])).is_empty());
+    }
+}
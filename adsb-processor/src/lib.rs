@@ -4,37 +4,152 @@ extern crate wascc_actor as actor;
 extern crate eventsourcing_derive;
 
 mod adsbtypes;
+mod cpr;
 mod esmodel;
+mod geo;
+mod geofence;
+mod track;
 
 const OP_MESSAGE_RECEIVED: &str = "MessageReceived";
+const OP_MESSAGES_RECEIVED: &str = "MessagesReceived";
+const OP_STATION_STATUS_CHANGED: &str = "StationStatusChanged";
 const EVENTS_SUBJECT: &str = "adsb.events";
+const ALERTS_SUBJECT: &str = "adsb.alerts";
 const STATION_LIST_KEY: &str = "adsb:stations";
 const AIRCRAFT_SET_KEY: &str = "adsb:aircraft";
-const AIRCRAFT_EXPIRATION_SECONDS: u32 = 10 * 60; // 10 minutes
+const FLIGHTS_SET_KEY: &str = "adsb:flights";
+const FLIGHTS_SWEPT_AT_KEY: &str = "adsb:flights:swept_at";
+const GEOFENCES_KEY: &str = "adsb:geofences";
+const FLIGHT_SWEEP_INTERVAL_MS: u64 = 30 * 1000; // 30 seconds
 
 use actor::prelude::*;
-use adsbtypes::ADSBMessage;
-use esmodel::{AdsbUpdateEvent, Aircraft, AircraftState, StationList, StationListState};
+use adsbtypes::{ADSBMessage, ADSBMessageBatch, StationStatus};
+use esmodel::{
+    AdsbUpdateEvent, Aircraft, AircraftState, StationList, StationListState,
+    AIRCRAFT_EXPIRATION_SECONDS,
+};
 use eventsourcing::Aggregate;
+use geofence::GeofenceList;
+use std::collections::{HashMap, VecDeque};
+use track::Track;
 
 actor_handlers! {
     OP_MESSAGE_RECEIVED => process_adsb_message,
+    OP_MESSAGES_RECEIVED => process_adsb_messages,
+    OP_STATION_STATUS_CHANGED => process_station_status,
     codec::core::OP_HEALTH_REQUEST => health
 }
 
 fn process_adsb_message(payload: ADSBMessage) -> HandlerResult<()> {
-    let event = AdsbUpdateEvent::from(payload);
+    process_messages(vec![payload])
+}
+
+fn process_adsb_messages(payload: ADSBMessageBatch) -> HandlerResult<()> {
+    process_messages(payload.messages)
+}
+
+/// Applies the messages in order. Each aircraft's state and track, the station list and the
+/// geofences are loaded and stored once per call rather than once per message, which is what
+/// makes batches cheaper.
+fn process_messages(messages: Vec<ADSBMessage>) -> HandlerResult<()> {
+    let mut aircraft: HashMap<String, AircraftState> = HashMap::new();
+    let mut tracks: HashMap<String, Track> = HashMap::new();
+    let mut stations_list = get_stations_list()?;
+    let geofences = get_geofences()?;
 
-    let state = load_state(&event.key())?;
-    let new_state = Aircraft::apply_event(&state, &event)?;
-    put_aircraft_state(&new_state)?;
+    for message in &messages {
+        let icao_address = &message.header.icao_address;
+        let mut state = match aircraft.remove(icao_address) {
+            Some(state) => state,
+            None => load_state(icao_address)?,
+        };
+        let mut events: VecDeque<AdsbUpdateEvent> =
+            Aircraft::handle_command(&state, message)?.into();
+        while let Some(event) = events.pop_front() {
+            state = Aircraft::apply_event(&state, &event)?;
+            stations_list = StationList::apply_event(&stations_list, &event)?;
+            if let AdsbUpdateEvent::PositionUpdated { .. } = event {
+                let mut track = match tracks.remove(icao_address) {
+                    Some(track) => track,
+                    None => load_track(icao_address)?,
+                };
+                track.record(&event, &state);
+                tracks.insert(icao_address.to_string(), track);
+                events.extend(geofences.crossings(&event, &state));
+            }
+            index_flight(&event)?;
+            emit_event(&event)?;
+        }
+        aircraft.insert(icao_address.to_string(), state);
+    }
 
-    let stations_list = get_stations_list()?;
-    let new_stations = StationList::apply_event(&stations_list, &event)?;
-    put_stations_state(&new_stations)?;
+    for state in aircraft.values() {
+        put_aircraft_state(state, AIRCRAFT_EXPIRATION_SECONDS)?;
+    }
+    for track in tracks.values() {
+        put_track(track)?;
+    }
+    put_stations_state(&stations_list)?;
 
+    match messages.iter().map(|message| message.received_at).max() {
+        Some(now) => end_silent_flights(now),
+        None => Ok(()),
+    }
+}
+
+fn process_station_status(payload: StationStatus) -> HandlerResult<()> {
+    let reported_at = payload.reported_at;
+    let event = AdsbUpdateEvent::from(payload);
+    let stations_list = StationList::apply_event(&get_stations_list()?, &event)?;
     emit_event(&event)?;
+    put_stations_state(&stations_list)?;
+
+    end_silent_flights(reported_at)
+}
+
+/// Ends the flights of aircraft that went silent. Aircraft that go out of range send nothing
+/// more, so the aircraft in flight are checked whenever messages of any aircraft or station
+/// status reports come in, at most once every `FLIGHT_SWEEP_INTERVAL_MS`.
+fn end_silent_flights(now: u64) -> HandlerResult<()> {
+    let swept_at: u64 = match keyvalue::default().get(FLIGHTS_SWEPT_AT_KEY) {
+        Ok(Some(s)) => s.parse().unwrap_or(0),
+        Ok(None) => 0,
+        Err(_) => 0,
+    };
+    if now.saturating_sub(swept_at) < FLIGHT_SWEEP_INTERVAL_MS {
+        return Ok(());
+    }
+    keyvalue::default().set(FLIGHTS_SWEPT_AT_KEY, &now.to_string(), None)?;
 
+    for icao_address in keyvalue::default().set_members(FLIGHTS_SET_KEY)? {
+        let state = load_state(&icao_address)?;
+        if state.flight.is_none() {
+            // The aircraft's state expired along with its flight
+            keyvalue::default().set_remove(FLIGHTS_SET_KEY, &icao_address)?;
+        } else if let Some(event) = esmodel::flight_timeout(&state, now) {
+            let state = Aircraft::apply_event(&state, &event)?;
+            index_flight(&event)?;
+            emit_event(&event)?;
+            // The ended flight must be stored so that it isn't ended again, without keeping
+            // the silent aircraft around for longer than its last message warrants
+            put_aircraft_state(&state, esmodel::expiration_seconds(&state, now))?;
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the set of aircraft in flight, which is all the sweep for silent aircraft goes
+/// through
+fn index_flight(event: &AdsbUpdateEvent) -> HandlerResult<()> {
+    match event {
+        AdsbUpdateEvent::FlightStarted { icao_address, .. } => {
+            keyvalue::default().set_add(FLIGHTS_SET_KEY, icao_address)?;
+        }
+        AdsbUpdateEvent::FlightEnded { icao_address, .. } => {
+            keyvalue::default().set_remove(FLIGHTS_SET_KEY, icao_address)?;
+        }
+        _ => {}
+    }
     Ok(())
 }
 
@@ -55,12 +170,48 @@ fn get_stations_list() -> HandlerResult<StationListState> {
     })
 }
 
+/// Reads the geofences maintained through the REST actor
+fn get_geofences() -> HandlerResult<GeofenceList> {
+    Ok(match keyvalue::default().get(GEOFENCES_KEY) {
+        Ok(Some(s)) => serde_json::from_str(&s)?,
+        Ok(None) => GeofenceList::default(),
+        Err(_) => GeofenceList::default(),
+    })
+}
+
 fn emit_event(event: &AdsbUpdateEvent) -> HandlerResult<()> {
+    let subjects = event_subjects(event);
+    if subjects.is_empty() {
+        return Ok(());
+    }
     // Submit post-processed event to downstream consumers
-    messaging::default().publish(EVENTS_SUBJECT, None, &serde_json::to_vec(&event)?)?;
+    let payload = serde_json::to_vec(&event)?;
+    for subject in subjects {
+        messaging::default().publish(subject, None, &payload)?;
+    }
     Ok(())
 }
 
+/// The subjects an event is published on, if any
+fn event_subjects(event: &AdsbUpdateEvent) -> &'static [&'static str] {
+    match event {
+        // Raw CPR frames are only an input to position decoding, and consumers get the
+        // decoded PositionUpdated instead. Messages ignored in favor of the aircraft's
+        // source or for being older than their station's latest would only repeat what
+        // was already reported.
+        AdsbUpdateEvent::CPRFrameReceived { .. }
+        | AdsbUpdateEvent::GNSSFrameReceived { .. }
+        | AdsbUpdateEvent::RedundantMessageIgnored { .. }
+        | AdsbUpdateEvent::StaleMessageRejected { .. } => &[],
+        // Alerts also go to a subject of their own, so that consumers such as pagers don't
+        // have to sift through all traffic
+        AdsbUpdateEvent::EmergencyDeclared { .. } | AdsbUpdateEvent::EmergencyCleared { .. } => {
+            &[EVENTS_SUBJECT, ALERTS_SUBJECT]
+        }
+        _ => &[EVENTS_SUBJECT],
+    }
+}
+
 fn load_state(key: &str) -> HandlerResult<AircraftState> {
     let key = format!("adsb:aircraft:{}", key);
     let state: AircraftState = match keyvalue::default().get(&key) {
@@ -71,20 +222,104 @@ fn load_state(key: &str) -> HandlerResult<AircraftState> {
     Ok(state)
 }
 
-fn put_aircraft_state(state: &AircraftState) -> HandlerResult<()> {
+fn put_aircraft_state(state: &AircraftState, expiration_seconds: u32) -> HandlerResult<()> {
     let key = format!("adsb:aircraft:{}", state.icao_address);
     keyvalue::default().set(
         &key,
         &serde_json::to_string(&state)?,
-        Some(AIRCRAFT_EXPIRATION_SECONDS),
+        Some(expiration_seconds),
     )?;
     // Put the ICAO address of the event's aircraft in a set so we have it for querying
-    keyvalue::default().set_add(AIRCRAFT_SET_KEY, &key)?;
+    keyvalue::default().set_add(AIRCRAFT_SET_KEY, &state.icao_address)?;
 
+    Ok(())
+}
+
+fn load_track(icao_address: &str) -> HandlerResult<Track> {
+    let key = format!("adsb:aircraft:{}:track", icao_address);
+    let track = match keyvalue::default().get(&key) {
+        Ok(Some(s)) => serde_json::from_str(&s)?,
+        Ok(None) => Track::new(icao_address),
+        Err(_) => Track::new(icao_address),
+    };
+    Ok(track)
+}
 
+/// Stores the track with an expiry of its own, so it outlives the aircraft's state and trails
+/// remain available for a while after the aircraft went out of range
+fn put_track(track: &Track) -> HandlerResult<()> {
+    let key = format!("adsb:aircraft:{}:track", track.icao_address);
+    keyvalue::default().set(
+        &key,
+        &serde_json::to_string(&track)?,
+        Some(track::MAX_AGE_SECONDS),
+    )?;
     Ok(())
 }
 
 fn health(_req: codec::core::HealthRequest) -> HandlerResult<()> {
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use esmodel::{Reception, Station};
+
+    fn station() -> Station {
+        Station {
+            id: "station001".to_string(),
+            name: "Station".to_string(),
+            status: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn publishes_alerts_on_both_subjects() {
+        let declared = AdsbUpdateEvent::EmergencyDeclared {
+            icao_address: "4840D6".to_string(),
+            source_station: station(),
+            reception: Reception::default(),
+            callsign: "KLM1023".to_string(),
+            squawk: "7700".to_string(),
+            emergency_state: adsbtypes::EmergencyState::General,
+            altitude: 38000,
+            position: None,
+        };
+        let cleared = AdsbUpdateEvent::EmergencyCleared {
+            icao_address: "4840D6".to_string(),
+            source_station: station(),
+            reception: Reception::default(),
+            callsign: "KLM1023".to_string(),
+            squawk: "1200".to_string(),
+        };
+        for event in &[declared, cleared] {
+            assert_eq!(event_subjects(event), &[EVENTS_SUBJECT, ALERTS_SUBJECT]);
+        }
+    }
+
+    #[test]
+    fn publishes_other_events_on_the_events_subject_only() {
+        let squawk = AdsbUpdateEvent::SquawkUpdated {
+            icao_address: "4840D6".to_string(),
+            source_station: station(),
+            reception: Reception::default(),
+            squawk: "1200".to_string(),
+        };
+        assert_eq!(event_subjects(&squawk), &[EVENTS_SUBJECT]);
+
+        let redundant = AdsbUpdateEvent::RedundantMessageIgnored {
+            icao_address: "4840D6".to_string(),
+            source_station: station(),
+            reception: Reception::default(),
+        };
+        let stale = AdsbUpdateEvent::StaleMessageRejected {
+            icao_address: "4840D6".to_string(),
+            source_station: station(),
+            reception: Reception::default(),
+        };
+        assert!(event_subjects(&redundant).is_empty());
+        assert!(event_subjects(&stale).is_empty());
+    }
+}
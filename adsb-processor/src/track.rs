@@ -0,0 +1,152 @@
+//! Position history of each aircraft, kept next to its state so that trails can be drawn.
+
+use crate::esmodel::{AdsbUpdateEvent, AircraftState};
+use serde::{Deserialize, Serialize};
+
+/// Most recent points kept per aircraft
+const MAX_POINTS: usize = 500;
+/// Points older than this are dropped, and a track that isn't extended for this long expires
+pub const MAX_AGE_SECONDS: u32 = 60 * 60; // 1 hour
+/// Positions decoded within this long of the previous point aren't recorded, which keeps
+/// roughly 40 minutes of history within `MAX_POINTS` for an aircraft in constant view
+const MIN_INTERVAL_MS: u64 = 5_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Track {
+    pub icao_address: String,
+    /// Oldest first
+    pub points: Vec<TrackPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackPoint {
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub time: u64,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Altitude in feet
+    pub altitude: u16,
+    /// Ground speed in knots
+    pub ground_speed: f64,
+    /// Heading in degrees
+    pub heading: f64,
+    /// Station that reported the position
+    pub station_id: String,
+}
+
+impl Track {
+    pub fn new(icao_address: &str) -> Self {
+        Track {
+            icao_address: icao_address.to_string(),
+            points: Vec::new(),
+        }
+    }
+
+    /// Records the position decoded by an event, along with the speed and heading of the
+    /// aircraft's state after the event was applied. Other events are ignored, as are
+    /// positions received too soon after, or before, the last point.
+    pub fn record(&mut self, event: &AdsbUpdateEvent, state: &AircraftState) {
+        let (reception, position, altitude, source_station) = match event {
+            AdsbUpdateEvent::PositionUpdated {
+                reception,
+                position,
+                altitude,
+                source_station,
+                ..
+            } => (reception, position, altitude, source_station),
+            _ => return,
+        };
+        let time = reception.received_at;
+        if let Some(last) = self.points.last() {
+            if time < last.time + MIN_INTERVAL_MS {
+                return;
+            }
+        }
+        let oldest = time.saturating_sub(u64::from(MAX_AGE_SECONDS) * 1000);
+        self.points.retain(|point| point.time >= oldest);
+        if self.points.len() >= MAX_POINTS {
+            self.points.drain(..=self.points.len() - MAX_POINTS);
+        }
+        self.points.push(TrackPoint {
+            time,
+            latitude: position.latitude,
+            longitude: position.longitude,
+            altitude: *altitude,
+            ground_speed: state.ground_speed,
+            heading: state.heading,
+            station_id: source_station.id.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsbtypes::Position;
+    use crate::esmodel::{Reception, Station};
+
+    fn position_updated(received_at: u64) -> AdsbUpdateEvent {
+        AdsbUpdateEvent::PositionUpdated {
+            icao_address: "4840D6".to_string(),
+            source_station: Station {
+                id: "station001".to_string(),
+                name: "Station".to_string(),
+                status: None,
+                location: None,
+            },
+            reception: Reception {
+                received_at,
+                mlat_timestamp: None,
+                rssi: None,
+            },
+            altitude: 38000,
+            position: Position {
+                latitude: 52.2572,
+                longitude: 3.9194,
+            },
+            on_ground: false,
+            range: None,
+            gnss_height: None,
+        }
+    }
+
+    #[test]
+    fn skips_positions_received_too_soon() {
+        let mut track = Track::new("4840D6");
+        let state = AircraftState::default();
+        track.record(&position_updated(1_000_000), &state);
+        track.record(&position_updated(1_000_000 + MIN_INTERVAL_MS - 1), &state);
+        track.record(&position_updated(999_000), &state);
+        track.record(&position_updated(1_000_000 + MIN_INTERVAL_MS), &state);
+        let times: Vec<u64> = track.points.iter().map(|point| point.time).collect();
+        assert_eq!(times, vec![1_000_000, 1_000_000 + MIN_INTERVAL_MS]);
+    }
+
+    #[test]
+    fn keeps_at_most_max_points() {
+        let mut track = Track::new("4840D6");
+        let state = AircraftState::default();
+        let start = 10_000_000_000;
+        for i in 0..MAX_POINTS as u64 + 10 {
+            track.record(&position_updated(start + i * MIN_INTERVAL_MS), &state);
+        }
+        assert_eq!(track.points.len(), MAX_POINTS);
+        assert_eq!(track.points[0].time, start + 10 * MIN_INTERVAL_MS);
+    }
+
+    #[test]
+    fn drops_points_older_than_max_age() {
+        let mut track = Track::new("4840D6");
+        let state = AircraftState::default();
+        let max_age = u64::from(MAX_AGE_SECONDS) * 1000;
+        let start = 1_000_000;
+        track.record(&position_updated(start), &state);
+        track.record(&position_updated(start + MIN_INTERVAL_MS), &state);
+        track.record(&position_updated(start + MIN_INTERVAL_MS + max_age), &state);
+        let times: Vec<u64> = track.points.iter().map(|point| point.time).collect();
+        assert_eq!(
+            times,
+            vec![start + MIN_INTERVAL_MS, start + MIN_INTERVAL_MS + max_age]
+        );
+    }
+}
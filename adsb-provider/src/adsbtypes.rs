@@ -5,8 +5,28 @@ use std::convert::TryFrom;
 pub struct ADSBMessage {
     pub station_id: String,
     pub station_name: String,
+    /// Wall clock time at which the provider received the message, in milliseconds since
+    /// the UNIX epoch
+    pub received_at: u64,
+    /// 48-bit receiver timestamp (12MHz clock), if supplied by the upstream feed
+    pub mlat_timestamp: Option<u64>,
+    /// Signal level in dBFS, if supplied by the upstream feed
+    pub rssi: Option<f64>,
     pub header: MessageHeader,
     pub payload: ADSBMessagePayload,
+    /// Where the receiving station is, if configured
+    pub station_location: Option<StationLocation>,
+}
+
+/// Location of a receiving station's antenna
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationLocation {
+    /// Free-form description of the location, e.g. `Windsor, CT`
+    pub label: String,
+    /// Antenna position, if configured
+    pub position: Option<Position>,
+    /// Antenna altitude in feet above mean sea level, if configured
+    pub altitude: Option<f64>,
 }
 
 impl TryFrom<Message> for ADSBMessage {
@@ -23,6 +43,9 @@ impl TryFrom<Message> for ADSBMessage {
             Ok(ADSBMessage {
                 station_id: "TBD".to_string(),
                 station_name: "TBD".to_string(),
+                received_at: 0,
+                mlat_timestamp: None,
+                rssi: None,
                 header: MessageHeader {
                     downlink_format: source.downlink_format,
                     capability,
@@ -30,6 +53,7 @@ impl TryFrom<Message> for ADSBMessage {
                     type_code,
                 },
                 payload: ADSBMessagePayload::from(kind),
+                station_location: None,
             })
         } else {
             Err("Unsupported message format / kind")
@@ -37,6 +61,12 @@ impl TryFrom<Message> for ADSBMessage {
     }
 }
 
+/// Messages delivered to the actor in a single call, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ADSBMessageBatch {
+    pub messages: Vec<ADSBMessage>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MessageHeader {
     pub downlink_format: u8,
@@ -45,6 +75,75 @@ pub struct MessageHeader {
     pub type_code: u8,
 }
 
+/// Frame counters for a station fed through a binding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatistics {
+    pub station_id: String,
+    pub station_name: String,
+    /// Frames read from the upstream feed, including those later discarded
+    pub frames_received: u64,
+    /// Frames discarded because their parity check failed
+    pub crc_failures: u64,
+    /// Frames with a single-bit error that was corrected
+    pub corrected: u64,
+    /// Intact frames of a downlink format or type code that isn't decoded
+    pub unsupported: u64,
+    /// Address/parity replies discarded because their address wasn't recently announced
+    pub unknown_address: u64,
+    /// Messages held back because an equivalent message from the aircraft was delivered
+    /// within the throttle interval
+    pub throttled: u64,
+    /// Messages discarded because the queue to the actor was full
+    pub dropped: u64,
+    /// Messages the actor failed to accept
+    pub dispatch_failures: u64,
+}
+
+/// Frame counters of every station fed through a binding, returned from a statistics query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatisticsList {
+    pub stations: Vec<StationStatistics>,
+}
+
+/// Connection state of a station's feed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Establishing the first connection to an upstream feed
+    Connecting,
+    /// Receiving frames from an upstream feed or a feeder
+    Connected,
+    /// The connection was lost or couldn't be established, and another attempt is pending
+    Reconnecting,
+    /// Replaying a capture file
+    Replaying,
+    /// The feed ended or the feeder went away
+    Disconnected,
+}
+
+/// Health of a station's feed, sent to the bound actor whenever its connection state
+/// changes and periodically while the binding is active
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatus {
+    pub station_id: String,
+    pub station_name: String,
+    pub state: ConnectionState,
+    /// Upstream feed, feeder address or capture file the station's frames come from
+    pub upstream: Option<String>,
+    /// Wall clock time of the most recent frame, in milliseconds since the UNIX epoch
+    pub last_frame_at: Option<u64>,
+    /// Frames received per second over the last reporting interval
+    pub message_rate: f64,
+    /// Wall clock time of the report, in milliseconds since the UNIX epoch
+    pub reported_at: u64,
+    pub location: Option<StationLocation>,
+}
+
+/// Status of every station fed through a binding, returned from a status query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationStatusList {
+    pub stations: Vec<StationStatus>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ADSBMessagePayload {
     /// Aicraft identification and category message (TC 1-4)
@@ -58,8 +157,8 @@ pub enum ADSBMessagePayload {
     AirbornePosition {
         /// Altitude in feet
         altitude: u16,
-
-        position: Position,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
     },
     /// Airborne velocity message (TC 19)
     AirborneVelocity {
@@ -71,6 +170,67 @@ pub enum ADSBMessagePayload {
         /// negative values indicate it is descending
         vertical_rate: i16,
     },
+    /// Position already decoded by the upstream feed (SBS transmission types 2 and 3)
+    DecodedPosition {
+        /// Altitude in feet, if the record carried one
+        #[serde(default)]
+        altitude: Option<u16>,
+        position: Position,
+        /// Indicates a surface position report
+        on_ground: bool,
+    },
+    /// Altitude reply to a Mode S surveillance interrogation
+    SurveillanceAltitude {
+        /// Altitude in feet
+        altitude: u16,
+    },
+    /// Identity reply to a Mode S surveillance interrogation
+    SurveillanceIdentity {
+        /// Mode A code as four octal digits, e.g. `7700`
+        squawk: String,
+    },
+    /// Surface position message (TC 5-8)
+    SurfacePosition {
+        /// Ground speed in knots, if available
+        ground_speed: Option<f64>,
+        /// Ground track in degrees, if available
+        track: Option<f64>,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
+    },
+    /// Airborne position message with GNSS height instead of barometric altitude (TC 20-22)
+    AirbornePositionGNSS {
+        /// GNSS height in feet
+        altitude: u16,
+        /// Aircraft position in CPR format, decoded into a real position by the processor
+        cpr_frame: CPRFrame,
+    },
+    /// Aircraft status message with emergency state and squawk (TC 28, subtype 1)
+    AircraftStatus {
+        emergency_state: EmergencyState,
+        /// Mode A code as four octal digits, e.g. `7700`
+        squawk: String,
+    },
+    /// Target state and status message (TC 29, subtype 1)
+    TargetState {
+        /// Altitude selected on the MCP/FCU or FMS in feet
+        selected_altitude: Option<u16>,
+        /// Barometric pressure setting in millibars
+        barometric_setting: Option<f64>,
+        /// Selected heading in degrees
+        selected_heading: Option<f64>,
+    },
+    /// Aircraft operational status message (TC 31)
+    OperationalStatus {
+        /// ADS-B version number
+        version: u8,
+        /// Indicates a surface (rather than airborne) operational status
+        on_ground: bool,
+        /// Navigational accuracy category for position
+        nac_p: u8,
+        /// Source integrity level
+        sil: u8,
+    },
 }
 
 impl From<ADSBMessageKind> for ADSBMessagePayload {
@@ -81,10 +241,7 @@ impl From<ADSBMessageKind> for ADSBMessagePayload {
                 cpr_frame,
             } => ADSBMessagePayload::AirbornePosition {
                 altitude,
-                position: Position {
-                    latitude: cpr_frame.position.latitude,
-                    longitude: cpr_frame.position.longitude,
-                },
+                cpr_frame: CPRFrame::from(cpr_frame),
             },
             ADSBMessageKind::AircraftIdentification {
                 emitter_category,
@@ -124,6 +281,18 @@ pub struct CPRFrame {
     pub parity: Parity,
 }
 
+impl From<adsb::CPRFrame> for CPRFrame {
+    fn from(source: adsb::CPRFrame) -> Self {
+        CPRFrame {
+            position: Position {
+                latitude: source.position.latitude,
+                longitude: source.position.longitude,
+            },
+            parity: Parity::from(source.parity),
+        }
+    }
+}
+
 /// Frame parity.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Parity {
@@ -131,6 +300,43 @@ pub enum Parity {
     Odd,
 }
 
+impl From<adsb::Parity> for Parity {
+    fn from(source: adsb::Parity) -> Self {
+        match source {
+            adsb::Parity::Even => Parity::Even,
+            adsb::Parity::Odd => Parity::Odd,
+        }
+    }
+}
+
+/// Emergency/priority status broadcast in aircraft status messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EmergencyState {
+    None,
+    General,
+    Medical,
+    MinimumFuel,
+    NoCommunications,
+    UnlawfulInterference,
+    DownedAircraft,
+    Reserved,
+}
+
+impl From<u8> for EmergencyState {
+    fn from(source: u8) -> Self {
+        match source {
+            0 => EmergencyState::None,
+            1 => EmergencyState::General,
+            2 => EmergencyState::Medical,
+            3 => EmergencyState::MinimumFuel,
+            4 => EmergencyState::NoCommunications,
+            5 => EmergencyState::UnlawfulInterference,
+            6 => EmergencyState::DownedAircraft,
+            _ => EmergencyState::Reserved,
+        }
+    }
+}
+
 /// Source for vertical rate information.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VerticalRateSource {
@@ -0,0 +1,123 @@
+use crate::frame::ModeSFrame;
+use std::io::{self, BufRead, BufReader, Lines, Read};
+
+/// Reads frames from an AVR text feed. Both plain (`*8D4840D6202CC371C32CE0576098;`) and
+/// timestamped (`@` followed by a 12 digit hex timestamp) frames are supported. Mode A/C
+/// replies and malformed lines are skipped.
+pub struct AvrReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+}
+
+impl<R: Read> AvrReader<R> {
+    pub fn new(source: R) -> Self {
+        AvrReader {
+            lines: BufReader::new(source).lines(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for AvrReader<R> {
+    type Item = io::Result<ModeSFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Skipping undecodable line: {}", e);
+                    continue;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            match parse_line(&line) {
+                Some(frame) => return Some(Ok(frame)),
+                None => {
+                    let line = line.trim();
+                    // Short lines are Mode A/C replies, which carry nothing we decode
+                    if line.len() > 6 {
+                        warn!("Skipping malformed AVR line: {}", line);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parses a single AVR line into a Mode S frame
+pub fn parse_line(line: &str) -> Option<ModeSFrame> {
+    let line = line.trim();
+    if !line.is_ascii() || !line.ends_with(';') || line.len() < 2 {
+        return None;
+    }
+    let body = &line[1..line.len() - 1];
+    let (mlat_timestamp, hex) = match &line[..1] {
+        "*" => (None, body),
+        "@" if body.len() > 12 => (
+            Some(u64::from_str_radix(&body[..12], 16).ok()?),
+            &body[12..],
+        ),
+        _ => return None,
+    };
+    let data = decode_hex(hex)?;
+    if data.len() != 7 && data.len() != 14 {
+        return None;
+    }
+    Some(ModeSFrame {
+        data,
+        mlat_timestamp,
+        rssi: None,
+    })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Formats a Mode S frame as an AVR line, with a timestamp if the frame has one
+pub fn format_frame(frame: &ModeSFrame) -> String {
+    let hex: String = frame.data.iter().map(|b| format!("{:02X}", b)).collect();
+    match frame.mlat_timestamp {
+        Some(timestamp) => format!("@{:012X}{};", timestamp, hex),
+        None => format!("*{};", hex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_timestamped_lines() {
+        let frame = parse_line("*8D4840D6202CC371C32CE0576098;").unwrap();
+        assert_eq!(frame.data.len(), 14);
+        assert_eq!(frame.mlat_timestamp, None);
+        let frame = parse_line("@0123456789AB8D4840D6202CC371C32CE0576098;\r\n").unwrap();
+        assert_eq!(frame.data[0], 0x8D);
+        assert_eq!(frame.mlat_timestamp, Some(0x0123_4567_89AB));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(parse_line("*8D4840D6202CC371C32CE0576098"), None);
+        assert_eq!(parse_line("*8D4840D6202CC371C32CE057609;"), None);
+        assert_eq!(parse_line("*8D4840D6;"), None);
+        assert_eq!(parse_line("*0A00;"), None);
+        assert_eq!(parse_line("#8D4840D6202CC371C32CE0576098;"), None);
+    }
+
+    #[test]
+    fn formats_frames_as_parsed() {
+        for line in &[
+            "*8D4840D6202CC371C32CE0576098;",
+            "@0123456789AB8D4840D6202CC371C32CE0576098;",
+        ] {
+            assert_eq!(format_frame(&parse_line(line).unwrap()), *line);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::frame::ModeSFrame;
+use std::io::{self, BufReader, Bytes, Read};
+
+/// Marks the start of every Beast frame. Occurrences inside a frame are doubled.
+const ESCAPE: u8 = 0x1a;
+const TYPE_MODE_AC: u8 = b'1';
+const TYPE_MODE_S_SHORT: u8 = b'2';
+const TYPE_MODE_S_LONG: u8 = b'3';
+/// Length of the timestamp and signal level preceding the frame data
+const HEADER_LEN: usize = 7;
+
+/// Reads frames from a Beast binary feed. Each frame carries a 48-bit MLAT timestamp and a
+/// signal level byte ahead of the Mode S data. Mode A/C and status frames are skipped.
+pub struct BeastReader<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    /// Type of a frame whose start was found while reading a truncated previous frame
+    pending_type: Option<u8>,
+}
+
+impl<R: Read> BeastReader<R> {
+    pub fn new(source: R) -> Self {
+        BeastReader {
+            bytes: BufReader::new(source).bytes(),
+            pending_type: None,
+        }
+    }
+
+    /// Reads the next Mode S frame, returning `None` once the source is exhausted
+    pub fn next_frame(&mut self) -> io::Result<Option<ModeSFrame>> {
+        loop {
+            let frame_type = match self.pending_type.take() {
+                Some(frame_type) => frame_type,
+                None => {
+                    match self.next_byte()? {
+                        Some(ESCAPE) => {}
+                        Some(_) => continue,
+                        None => return Ok(None),
+                    }
+                    match self.next_byte()? {
+                        Some(frame_type) => frame_type,
+                        None => return Ok(None),
+                    }
+                }
+            };
+            let data_len = match frame_type {
+                TYPE_MODE_AC => 2,
+                TYPE_MODE_S_SHORT => 7,
+                TYPE_MODE_S_LONG => 14,
+                _ => continue,
+            };
+
+            let mut buf = vec![0u8; HEADER_LEN + data_len];
+            if !self.read_escaped(&mut buf)? {
+                warn!("Discarding truncated Beast frame");
+                continue;
+            }
+            if frame_type == TYPE_MODE_AC {
+                continue;
+            }
+
+            let mlat_timestamp = buf[..6]
+                .iter()
+                .fold(0u64, |ts, b| (ts << 8) | u64::from(*b));
+            let signal = buf[6];
+            return Ok(Some(ModeSFrame {
+                data: buf[HEADER_LEN..].to_vec(),
+                mlat_timestamp: Some(mlat_timestamp),
+                rssi: rssi(signal),
+            }));
+        }
+    }
+
+    /// Fills `buf` with unescaped frame bytes. Returns `false` if the start of a new frame
+    /// was found before `buf` could be filled.
+    fn read_escaped(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        for slot in buf.iter_mut() {
+            let b = self.expect_byte()?;
+            if b == ESCAPE {
+                let next = self.expect_byte()?;
+                if next != ESCAPE {
+                    self.pending_type = Some(next);
+                    return Ok(false);
+                }
+            }
+            *slot = b;
+        }
+        Ok(true)
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        self.bytes.next().transpose()
+    }
+
+    fn expect_byte(&mut self) -> io::Result<u8> {
+        self.next_byte()?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "Beast feed ended mid-frame")
+        })
+    }
+}
+
+impl<R: Read> Iterator for BeastReader<R> {
+    type Item = io::Result<ModeSFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Converts a Beast signal level byte (the square root of the signal power) to dBFS
+fn rssi(signal: u8) -> Option<f64> {
+    if signal == 0 {
+        None
+    } else {
+        Some(20.0 * (f64::from(signal) / 255.0).log10())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUITTER: [u8; 14] = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+
+    fn read_all(bytes: &[u8]) -> Vec<ModeSFrame> {
+        BeastReader::new(bytes)
+            .collect::<io::Result<Vec<ModeSFrame>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn unescapes_frame_bytes() {
+        let mut bytes = vec![ESCAPE, TYPE_MODE_S_LONG];
+        // Timestamp 0x00001A1A0001 and signal level 0x1A, escaped
+        bytes.extend_from_slice(&[0x00, 0x00, 0x1A, 0x1A, 0x1A, 0x1A, 0x00, 0x01, 0x1A, 0x1A]);
+        bytes.extend_from_slice(&SQUITTER);
+        let frames = read_all(&bytes);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, SQUITTER.to_vec());
+        assert_eq!(frames[0].mlat_timestamp, Some(0x0000_1A1A_0001));
+        assert!((frames[0].rssi.unwrap() - 20.0 * (26.0_f64 / 255.0).log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn skips_mode_ac_frames_and_noise() {
+        let mut bytes = vec![0x00, 0xFF, ESCAPE, TYPE_MODE_AC];
+        bytes.extend_from_slice(&[0; HEADER_LEN + 2]);
+        bytes.extend_from_slice(&[ESCAPE, TYPE_MODE_S_SHORT]);
+        bytes.extend_from_slice(&[0; HEADER_LEN]);
+        bytes.extend_from_slice(&[0x5D, 0x48, 0x40, 0xD6, 0x00, 0x00, 0x00]);
+        let frames = read_all(&bytes);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            frames[0].data,
+            vec![0x5D, 0x48, 0x40, 0xD6, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(frames[0].rssi, None);
+    }
+
+    #[test]
+    fn resumes_at_frame_interrupting_truncated_one() {
+        let mut bytes = vec![ESCAPE, TYPE_MODE_S_LONG];
+        bytes.extend_from_slice(&[0; HEADER_LEN]);
+        bytes.extend_from_slice(&SQUITTER[..5]);
+        bytes.extend_from_slice(&[ESCAPE, TYPE_MODE_S_LONG]);
+        bytes.extend_from_slice(&[0; HEADER_LEN]);
+        bytes.extend_from_slice(&SQUITTER);
+        let frames = read_all(&bytes);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, SQUITTER.to_vec());
+    }
+
+    #[test]
+    fn reports_feed_ending_mid_frame() {
+        let mut bytes = vec![ESCAPE, TYPE_MODE_S_LONG];
+        bytes.extend_from_slice(&[0; HEADER_LEN]);
+        bytes.extend_from_slice(&SQUITTER[..5]);
+        let error = BeastReader::new(&bytes[..]).next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::dispatch::DispatchQueue;
+use crate::stats::Statistics;
+use crate::status::StatusReporter;
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Resources claimed on behalf of a bound actor: the consumer thread feeding it, the
+/// token used to ask that thread to stop, the thread's frame counters, the queue of
+/// messages waiting to be dispatched and the status of the stations it feeds
+pub struct Binding {
+    handle: JoinHandle<()>,
+    cancel: Arc<CancelToken>,
+    stats: Arc<Statistics>,
+    queue: Arc<DispatchQueue>,
+    status: Arc<StatusReporter>,
+}
+
+impl Binding {
+    pub fn new(
+        handle: JoinHandle<()>,
+        cancel: Arc<CancelToken>,
+        stats: Arc<Statistics>,
+        queue: Arc<DispatchQueue>,
+        status: Arc<StatusReporter>,
+    ) -> Self {
+        Binding {
+            handle,
+            cancel,
+            stats,
+            queue,
+            status,
+        }
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.stats
+    }
+
+    pub fn status(&self) -> &StatusReporter {
+        &self.status
+    }
+
+    /// Signals the consumer thread to stop and waits for it to exit. Messages that haven't
+    /// been dispatched yet are discarded.
+    pub fn stop(self) {
+        self.cancel.cancel();
+        self.queue.abort();
+        if self.handle.join().is_err() {
+            error!("Consumer thread panicked before shutdown");
+        }
+    }
+}
+
+/// Shared cancellation flag for a consumer thread and any connection threads it spawns. It
+/// also holds handles to the sockets currently being read so that blocking reads can be
+/// interrupted on cancellation.
+#[derive(Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    sockets: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(mut sockets) = self.sockets.lock() {
+            for (_, socket) in sockets.drain() {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Remembers a socket being read so `cancel` can shut it down, until the returned
+    /// registration is dropped
+    pub fn register(&self, stream: &TcpStream) -> Registration<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if let (Ok(mut sockets), Ok(clone)) = (self.sockets.lock(), stream.try_clone()) {
+            sockets.insert(id, clone);
+        }
+        // Cancellation may have raced with the connection being established
+        if self.is_cancelled() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        Registration { token: self, id }
+    }
+
+    /// Sleeps for the given duration, waking early if cancelled. Returns `false` if the
+    /// token was cancelled.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_cancelled() {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            thread::sleep(CANCEL_POLL_INTERVAL.min(deadline - now));
+        }
+        false
+    }
+}
+
+/// Keeps a socket registered with a `CancelToken` while it is being read
+pub struct Registration<'a> {
+    token: &'a CancelToken,
+    id: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut sockets) = self.token.sockets.lock() {
+            sockets.remove(&self.id);
+        }
+    }
+}
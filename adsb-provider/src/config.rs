@@ -0,0 +1,695 @@
+use crate::adsbtypes::{Position, StationLocation};
+use crate::dispatch::DropPolicy;
+use crate::frame::Format;
+use crate::upstream::Upstream;
+use codec::core::CapabilityConfiguration;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+const CONFIG_SOURCE: &str = "SOURCE";
+const CONFIG_FORMAT: &str = "FORMAT";
+const CONFIG_HOST: &str = "HOST";
+const CONFIG_PORT: &str = "PORT";
+const CONFIG_TIMEOUT: &str = "TIMEOUT";
+const CONFIG_IDLE_TIMEOUT_MS: &str = "IDLE_TIMEOUT_MS";
+const CONFIG_STATION_ID: &str = "STATION_ID";
+const CONFIG_STATION_NAME: &str = "STATION_NAME";
+const CONFIG_STATION_LAT: &str = "STATION_LAT";
+const CONFIG_STATION_LON: &str = "STATION_LON";
+const CONFIG_STATION_ALT: &str = "STATION_ALT";
+const CONFIG_STATION_LOCATION: &str = "STATION_LOCATION";
+const CONFIG_RECONNECT_MIN_MS: &str = "RECONNECT_MIN_MS";
+const CONFIG_RECONNECT_MAX_MS: &str = "RECONNECT_MAX_MS";
+const CONFIG_CORRECT_ERRORS: &str = "CORRECT_ERRORS";
+const CONFIG_ICAO_FILTER_TTL_MS: &str = "ICAO_FILTER_TTL_MS";
+const CONFIG_FILE: &str = "FILE";
+const CONFIG_REPLAY_SPEED: &str = "REPLAY_SPEED";
+const CONFIG_RECORD_DIR: &str = "RECORD_DIR";
+const CONFIG_RECORD_MAX_BYTES: &str = "RECORD_MAX_BYTES";
+const CONFIG_RECORD_MAX_FILES: &str = "RECORD_MAX_FILES";
+const CONFIG_STATION_MAP: &str = "STATION_MAP";
+const CONFIG_MAX_FEEDERS: &str = "MAX_FEEDERS";
+const CONFIG_UPSTREAMS: &str = "UPSTREAMS";
+const CONFIG_FAILBACK_INTERVAL_MS: &str = "FAILBACK_INTERVAL_MS";
+const CONFIG_QUEUE_SIZE: &str = "QUEUE_SIZE";
+const CONFIG_DROP_POLICY: &str = "DROP_POLICY";
+const CONFIG_BATCH_SIZE: &str = "BATCH_SIZE";
+const CONFIG_THROTTLE_MS: &str = "THROTTLE_MS";
+const CONFIG_STATUS_INTERVAL_MS: &str = "STATUS_INTERVAL_MS";
+const CONFIG_TLS: &str = "TLS";
+const CONFIG_TLS_CA_FILE: &str = "TLS_CA_FILE";
+const CONFIG_TLS_PIN: &str = "TLS_PIN";
+const CONFIG_TLS_SERVER_NAME: &str = "TLS_SERVER_NAME";
+const CONFIG_AUTH_TOKEN: &str = "AUTH_TOKEN";
+const CONFIG_SIM_AIRCRAFT: &str = "SIM_AIRCRAFT";
+const CONFIG_SIM_RADIUS_NM: &str = "SIM_RADIUS_NM";
+const CONFIG_SIM_SEED: &str = "SIM_SEED";
+
+/// Longest station id accepted from a feeder
+const MAX_STATION_ID_LEN: usize = 64;
+
+/// Shortest reconnect delay in milliseconds, which keeps a failing upstream from being
+/// retried in a tight loop
+const MIN_RECONNECT_MS: u64 = 100;
+
+/// Most aircraft the simulator flies at once. Each needs an address of its own, drawn from
+/// the 2^20 the simulator uses, and this is already far more traffic than any station
+/// receives.
+const MAX_SIM_AIRCRAFT: usize = 10_000;
+
+/// Where a binding's frames come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// Connect to an upstream feed over TCP
+    Network,
+    /// Replay a capture file from disk
+    File,
+    /// Accept TCP connections from feeders pushing frames
+    Listen,
+    /// Receive frames pushed by feeders as UDP datagrams
+    Udp,
+    /// Generate frames for simulated aircraft around the station
+    Simulator,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "network" => Ok(Source::Network),
+            "file" => Ok(Source::File),
+            "listen" => Ok(Source::Listen),
+            "udp" => Ok(Source::Udp),
+            "simulator" => Ok(Source::Simulator),
+            _ => Err(format!("Unknown source: {}", s)),
+        }
+    }
+}
+
+/// Settings for a single actor binding, parsed from the values supplied when the
+/// actor was bound to this provider
+#[derive(Debug, Clone)]
+pub struct BindingConfig {
+    pub actor: String,
+    pub source: Source,
+    pub format: Format,
+    pub host: String,
+    pub port: u16,
+    /// Upstream feeds of the `Network` source in order of preference, the first being the
+    /// primary. Defaults to the configured host and port.
+    pub upstreams: Vec<Upstream>,
+    /// How often the primary upstream is probed while a backup is in use, zero to stay on
+    /// the backup until it fails
+    pub failback_interval: Duration,
+    /// Connect timeout for the upstream socket, which also bounds the TLS handshake and
+    /// authentication
+    pub timeout: Duration,
+    /// How long an upstream or feeder may stay silent before its connection is considered
+    /// lost
+    pub idle_timeout: Duration,
+    /// Connect to upstream feeds over TLS, implied by a CA file or pinned certificate
+    pub tls: bool,
+    /// PEM file of the certificate authorities trusted for upstream feeds instead of the
+    /// built-in web PKI roots, empty to use the built-in roots
+    pub tls_ca_file: String,
+    /// SHA-256 fingerprints of the certificates accepted from upstream feeds in lowercase
+    /// hex. When any are configured they replace certificate chain and name validation.
+    pub tls_pins: Vec<String>,
+    /// Name the upstream's certificate is validated against, empty to use the upstream's
+    /// host
+    pub tls_server_name: String,
+    /// Pre-shared token sent to upstream feeds before reading frames, and required from
+    /// feeders in listen mode and when they register in UDP mode, empty to send and require
+    /// none
+    pub auth_token: String,
+    /// Delay before the first reconnect attempt after a connection is lost
+    pub reconnect_min: Duration,
+    /// Upper bound for the exponentially growing reconnect delay
+    pub reconnect_max: Duration,
+    /// The station whose frames are delivered, or the default station for feeders that
+    /// push frames in listen and UDP modes
+    pub station: StationInfo,
+    /// Stations of the feeders pushing frames in listen and UDP modes, by source address
+    pub station_map: HashMap<IpAddr, StationInfo>,
+    /// Number of feeders served at once in listen and UDP modes, beyond which further
+    /// feeders are turned away
+    pub max_feeders: usize,
+    /// Repair single-bit errors in extended squitters instead of discarding them
+    pub correct_errors: bool,
+    /// How long an address announced in a verified frame is trusted for address/parity
+    /// replies, zero to trust every reply
+    pub icao_filter_ttl: Duration,
+    /// Capture file replayed by the `File` source
+    pub file: String,
+    /// Replay speed relative to the capture's embedded timestamps, zero or less to replay
+    /// as fast as possible
+    pub replay_speed: f64,
+    /// Directory to record received frames to, empty to disable recording
+    pub record_dir: String,
+    /// Size at which a new recording file is started
+    pub record_max_bytes: u64,
+    /// Number of recording files kept per station
+    pub record_max_files: usize,
+    /// Number of messages waiting for dispatch to the actor before the drop policy applies
+    pub queue_size: usize,
+    pub drop_policy: DropPolicy,
+    /// Maximum number of messages per `MessagesReceived` call, one to deliver every message
+    /// with its own `MessageReceived` call
+    pub batch_size: usize,
+    /// Minimum time between messages of the same kind from an aircraft, unless their values
+    /// changed meaningfully, zero to deliver every message
+    pub throttle: Duration,
+    /// How often the status of the binding's stations is reported to the actor, in addition
+    /// to every change of their connection state, zero to only report changes
+    pub status_interval: Duration,
+    /// Number of aircraft flown by the `Simulator` source
+    pub sim_aircraft: usize,
+    /// Distance from the station within which simulated aircraft fly, in nautical miles
+    pub sim_radius: f64,
+    /// Seed of the simulation, zero to pick one from the clock
+    pub sim_seed: u64,
+}
+
+impl From<&CapabilityConfiguration> for BindingConfig {
+    fn from(config: &CapabilityConfiguration) -> Self {
+        let reconnect_min: u64 = value(config, CONFIG_RECONNECT_MIN_MS, 1000);
+        let reconnect_min = reconnect_min.max(MIN_RECONNECT_MS);
+        let reconnect_max: u64 = value(config, CONFIG_RECONNECT_MAX_MS, 60000);
+        let format = value(config, CONFIG_FORMAT, Format::Avr);
+        let host = value(config, CONFIG_HOST, "127.0.0.1".to_string());
+        let port = value(config, CONFIG_PORT, format.default_port());
+        let mut upstreams = config
+            .values
+            .get(CONFIG_UPSTREAMS)
+            .map(|v| upstreams(v, format.default_port()))
+            .unwrap_or_default();
+        if upstreams.is_empty() {
+            upstreams.push(Upstream {
+                host: host.to_string(),
+                port,
+            });
+        }
+        let tls_ca_file = value(config, CONFIG_TLS_CA_FILE, String::new());
+        let tls_pins = config
+            .values
+            .get(CONFIG_TLS_PIN)
+            .map(|v| fingerprints(v))
+            .unwrap_or_default();
+        let tls =
+            value(config, CONFIG_TLS, false) || !tls_ca_file.is_empty() || !tls_pins.is_empty();
+        let sim_aircraft = value(config, CONFIG_SIM_AIRCRAFT, 25);
+        if sim_aircraft > MAX_SIM_AIRCRAFT {
+            warn!(
+                "{} is limited to {}, using that",
+                CONFIG_SIM_AIRCRAFT, MAX_SIM_AIRCRAFT
+            );
+        }
+        BindingConfig {
+            actor: config.module.to_string(),
+            source: value(config, CONFIG_SOURCE, Source::Network),
+            format,
+            host,
+            port,
+            upstreams,
+            failback_interval: Duration::from_millis(value(
+                config,
+                CONFIG_FAILBACK_INTERVAL_MS,
+                30000,
+            )),
+            timeout: Duration::from_millis(value(config, CONFIG_TIMEOUT, 30000)),
+            idle_timeout: Duration::from_millis(value(config, CONFIG_IDLE_TIMEOUT_MS, 300000)),
+            tls,
+            tls_ca_file,
+            tls_pins,
+            tls_server_name: value(config, CONFIG_TLS_SERVER_NAME, String::new()),
+            auth_token: value(config, CONFIG_AUTH_TOKEN, String::new()),
+            reconnect_min: Duration::from_millis(reconnect_min),
+            reconnect_max: Duration::from_millis(reconnect_max.max(reconnect_min)),
+            station: StationInfo {
+                id: value(config, CONFIG_STATION_ID, "station001".to_string()),
+                name: value(config, CONFIG_STATION_NAME, "Unnamed Station".to_string()),
+                location: station_location(config),
+            },
+            station_map: config
+                .values
+                .get(CONFIG_STATION_MAP)
+                .map(|v| station_map(v))
+                .unwrap_or_default(),
+            max_feeders: value(config, CONFIG_MAX_FEEDERS, 64_usize).max(1),
+            correct_errors: value(config, CONFIG_CORRECT_ERRORS, true),
+            icao_filter_ttl: Duration::from_millis(value(config, CONFIG_ICAO_FILTER_TTL_MS, 60000)),
+            file: value(config, CONFIG_FILE, String::new()),
+            replay_speed: value(config, CONFIG_REPLAY_SPEED, 1.0),
+            record_dir: value(config, CONFIG_RECORD_DIR, String::new()),
+            record_max_bytes: value(config, CONFIG_RECORD_MAX_BYTES, 64 * 1024 * 1024),
+            record_max_files: value(config, CONFIG_RECORD_MAX_FILES, 10),
+            queue_size: value(config, CONFIG_QUEUE_SIZE, 10000),
+            drop_policy: value(config, CONFIG_DROP_POLICY, DropPolicy::Oldest),
+            batch_size: value(config, CONFIG_BATCH_SIZE, 1),
+            throttle: Duration::from_millis(value(config, CONFIG_THROTTLE_MS, 0)),
+            status_interval: Duration::from_millis(value(config, CONFIG_STATUS_INTERVAL_MS, 10000)),
+            sim_aircraft: sim_aircraft.min(MAX_SIM_AIRCRAFT),
+            sim_radius: value(config, CONFIG_SIM_RADIUS_NM, 150.0),
+            sim_seed: value(config, CONFIG_SIM_SEED, 0),
+        }
+    }
+}
+
+/// Identity of a receiving station
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationInfo {
+    pub id: String,
+    pub name: String,
+    pub location: Option<StationLocation>,
+}
+
+impl StationInfo {
+    /// Whether a station id announced by a feeder or mapped to its address is acceptable: 1
+    /// to 64 ASCII letters, digits, underscores or hyphens, so that it is safe to use in
+    /// file names
+    pub fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_STATION_ID_LEN
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    }
+}
+
+impl FromStr for StationInfo {
+    type Err = String;
+
+    /// Parses `<id>[:<name>][@<lat>/<lon>[/<alt>]]`, where the name defaults to the ID
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '@');
+        let station = parts.next().unwrap_or_default();
+        let location = match parts.next() {
+            Some(location) => Some(location.parse()?),
+            None => None,
+        };
+        let mut parts = station.splitn(2, ':').map(|p| p.trim());
+        match parts.next() {
+            Some(id) if !id.is_empty() => Ok(StationInfo {
+                id: id.to_string(),
+                name: parts.next().unwrap_or(id).to_string(),
+                location,
+            }),
+            _ => Err(format!("Missing station ID: {}", s)),
+        }
+    }
+}
+
+impl FromStr for StationLocation {
+    type Err = String;
+
+    /// Parses `<lat>/<lon>[/<alt>]`, in decimal degrees and feet above mean sea level
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid station location: {}", s);
+        let mut parts = s.split('/').map(|p| p.trim().parse::<f64>());
+        let latitude = match parts.next() {
+            Some(Ok(latitude)) if latitude.abs() <= 90.0 => latitude,
+            _ => return Err(invalid()),
+        };
+        let longitude = match parts.next() {
+            Some(Ok(longitude)) if longitude.abs() <= 180.0 => longitude,
+            _ => return Err(invalid()),
+        };
+        let altitude = match parts.next() {
+            Some(Ok(altitude)) => Some(altitude),
+            Some(Err(_)) => return Err(invalid()),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(StationLocation {
+            label: String::new(),
+            position: Some(Position {
+                latitude,
+                longitude,
+            }),
+            altitude,
+        })
+    }
+}
+
+/// Reads the station's location, which is only known if either a label or both coordinates
+/// are configured
+fn station_location(config: &CapabilityConfiguration) -> Option<StationLocation> {
+    let latitude = optional(config, CONFIG_STATION_LAT).filter(|lat: &f64| lat.abs() <= 90.0);
+    let longitude = optional(config, CONFIG_STATION_LON).filter(|lon: &f64| lon.abs() <= 180.0);
+    let position = match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => Some(Position {
+            latitude,
+            longitude,
+        }),
+        (None, None) => None,
+        _ => {
+            warn!(
+                "Ignoring station position, both {} and {} must be valid",
+                CONFIG_STATION_LAT, CONFIG_STATION_LON
+            );
+            None
+        }
+    };
+    let label = value(config, CONFIG_STATION_LOCATION, String::new());
+    if position.is_none() && label.is_empty() {
+        return None;
+    }
+    Some(StationLocation {
+        label,
+        position,
+        altitude: optional(config, CONFIG_STATION_ALT),
+    })
+}
+
+/// Parses a comma separated list of `<address>=<id>[:<name>][@<lat>/<lon>[/<alt>]]` entries,
+/// skipping invalid ones, including those whose station id a feeder couldn't announce
+fn station_map(value: &str) -> HashMap<IpAddr, StationInfo> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let address = parts.next()?.trim().parse();
+            let station = parts.next().map(|s| s.parse::<StationInfo>());
+            match (address, station) {
+                (Ok(address), Some(Ok(station))) if StationInfo::is_valid_id(&station.id) => {
+                    Some((address, station))
+                }
+                _ => {
+                    warn!("Ignoring invalid {} entry: {}", CONFIG_STATION_MAP, entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of `<host>[:<port>]` entries, skipping invalid ones
+fn upstreams(value: &str, default_port: u16) -> Vec<Upstream> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let upstream = Upstream::parse(entry, default_port);
+            if upstream.is_none() {
+                warn!("Ignoring invalid {} entry: {}", CONFIG_UPSTREAMS, entry);
+            }
+            upstream
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of SHA-256 fingerprints, written as hex with or without
+/// colons between the bytes, skipping invalid ones
+fn fingerprints(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim().replace(':', "").to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .filter(|entry| {
+            let valid = entry.len() == 64 && entry.chars().all(|c| c.is_ascii_hexdigit());
+            if !valid {
+                warn!("Ignoring invalid {} entry: {}", CONFIG_TLS_PIN, entry);
+            }
+            valid
+        })
+        .collect()
+}
+
+/// Reads an optional binding value, treating one that cannot be parsed as missing
+fn optional<T: FromStr>(config: &CapabilityConfiguration, key: &str) -> Option<T> {
+    match config.values.get(key).map(|v| v.trim().parse()) {
+        Some(Ok(v)) => Some(v),
+        Some(Err(_)) => {
+            warn!("Invalid value for {}, ignoring it", key);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Reads a binding value, falling back to the default if it is missing or cannot be parsed
+fn value<T: FromStr>(config: &CapabilityConfiguration, key: &str, default: T) -> T {
+    match config.values.get(key).map(|v| v.trim().parse()) {
+        Some(Ok(v)) => v,
+        Some(Err(_)) => {
+            warn!("Invalid value for {}, using default", key);
+            default
+        }
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Log, Metadata, Record};
+    use std::cell::RefCell;
+    use std::sync::Once;
+
+    const PIN: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    thread_local! {
+        static WARNINGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    }
+
+    /// Collects the warnings logged by each test thread, so that tests can tell which
+    /// values were ignored
+    struct Warnings;
+
+    impl Log for Warnings {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.with(|w| w.borrow_mut().push(record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: Warnings = Warnings;
+    static INIT: Once = Once::new();
+
+    /// Parses a binding's values, returning the warnings logged meanwhile
+    fn parse(values: &[(&str, &str)]) -> (BindingConfig, Vec<String>) {
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Warn);
+        });
+        WARNINGS.with(|w| w.borrow_mut().clear());
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+        (config, WARNINGS.with(|w| w.borrow_mut().split_off(0)))
+    }
+
+    fn location(latitude: f64, longitude: f64, altitude: Option<f64>) -> StationLocation {
+        StationLocation {
+            label: String::new(),
+            position: Some(Position {
+                latitude,
+                longitude,
+            }),
+            altitude,
+        }
+    }
+
+    #[test]
+    fn parses_station_info() {
+        assert_eq!(
+            "station002:Hartford@41.76/-72.68/120".parse(),
+            Ok(StationInfo {
+                id: "station002".to_string(),
+                name: "Hartford".to_string(),
+                location: Some(location(41.76, -72.68, Some(120.0))),
+            })
+        );
+        assert_eq!(
+            " station003 ".parse(),
+            Ok(StationInfo {
+                id: "station003".to_string(),
+                name: "station003".to_string(),
+                location: None,
+            })
+        );
+        assert!(":Hartford".parse::<StationInfo>().is_err());
+        assert!("station002@41.76".parse::<StationInfo>().is_err());
+    }
+
+    #[test]
+    fn parses_station_locations() {
+        assert_eq!("41.76/-72.68".parse(), Ok(location(41.76, -72.68, None)));
+        assert_eq!(
+            " -33.9 / 151.2 / -5 ".parse(),
+            Ok(location(-33.9, 151.2, Some(-5.0)))
+        );
+        for invalid in &["91/0", "0/181", "41.76", "41.76/-72.68/high", "1/2/3/4", ""] {
+            assert!(invalid.parse::<StationLocation>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn reads_the_station_location() {
+        let (config, warnings) = parse(&[
+            ("STATION_LAT", "41.76"),
+            ("STATION_LON", "-72.68"),
+            ("STATION_ALT", "120"),
+            ("STATION_LOCATION", "Hartford"),
+        ]);
+        assert_eq!(
+            config.station.location,
+            Some(StationLocation {
+                label: "Hartford".to_string(),
+                ..location(41.76, -72.68, Some(120.0))
+            })
+        );
+        assert!(warnings.is_empty());
+
+        let (config, warnings) = parse(&[("STATION_LAT", "95"), ("STATION_LON", "-72.68")]);
+        assert_eq!(config.station.location, None);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn parses_the_station_map() {
+        let (config, warnings) = parse(&[(
+            "STATION_MAP",
+            "10.0.0.5=station002:Hartford@41.76/-72.68/120, 10.0.0.6=station003,,\
+             fe80::1=station004,10.0.0.7=station 005,10.0.0.8=../station006,\
+             10.0.0=station007,10.0.0.9",
+        )]);
+        let station = |address: &str| config.station_map.get(&address.parse().unwrap());
+        assert_eq!(config.station_map.len(), 3);
+        assert_eq!(station("10.0.0.5").unwrap().name, "Hartford");
+        assert_eq!(station("10.0.0.6").unwrap().id, "station003");
+        assert_eq!(station("fe80::1").unwrap().id, "station004");
+        assert_eq!(warnings.len(), 4);
+    }
+
+    #[test]
+    fn parses_tls_pins() {
+        let colons = PIN
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_uppercase())
+            .collect::<Vec<String>>()
+            .join(":");
+        let pins = format!("{}, {},abcd,{}zz", PIN, colons, &PIN[..62]);
+        let (config, warnings) = parse(&[("TLS_PIN", &pins)]);
+        assert_eq!(config.tls_pins, vec![PIN, PIN]);
+        assert!(config.tls);
+        assert_eq!(warnings.len(), 2);
+
+        let (config, _) = parse(&[]);
+        assert!(config.tls_pins.is_empty());
+        assert!(!config.tls);
+    }
+
+    #[test]
+    fn parses_the_upstream_list() {
+        let (config, warnings) = parse(&[
+            ("FORMAT", "beast"),
+            ("UPSTREAMS", "receiver, backup:30002,[::1],backup:http"),
+        ]);
+        assert_eq!(
+            config
+                .upstreams
+                .iter()
+                .map(|u| u.to_string())
+                .collect::<Vec<String>>(),
+            vec!["receiver:30005", "backup:30002", "[::1]:30005"]
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn defaults_the_upstream_to_the_host_and_port() {
+        let (config, _) = parse(&[("HOST", "receiver"), ("FORMAT", "sbs")]);
+        assert_eq!(config.upstreams.len(), 1);
+        assert_eq!(config.upstreams[0].to_string(), "receiver:30003");
+
+        let (config, _) = parse(&[("HOST", "receiver"), ("PORT", "1234"), ("UPSTREAMS", ",")]);
+        assert_eq!(config.upstreams[0].to_string(), "receiver:1234");
+    }
+
+    #[test]
+    fn falls_back_to_defaults_with_a_warning() {
+        let (config, warnings) = parse(&[
+            ("QUEUE_SIZE", "lots"),
+            ("DROP_POLICY", "sometimes"),
+            ("SOURCE", "satellite"),
+            ("CORRECT_ERRORS", "maybe"),
+        ]);
+        assert_eq!(config.queue_size, 10000);
+        assert_eq!(config.drop_policy, DropPolicy::Oldest);
+        assert_eq!(config.source, Source::Network);
+        assert!(config.correct_errors);
+        let mut warnings = warnings;
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                "Invalid value for CORRECT_ERRORS, using default",
+                "Invalid value for DROP_POLICY, using default",
+                "Invalid value for QUEUE_SIZE, using default",
+                "Invalid value for SOURCE, using default",
+            ]
+        );
+
+        let (_, warnings) = parse(&[("QUEUE_SIZE", " 500 "), ("SOURCE", "Listen")]);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_reconnect_delays_in_order() {
+        let (config, _) = parse(&[("RECONNECT_MIN_MS", "10"), ("RECONNECT_MAX_MS", "50")]);
+        assert_eq!(
+            config.reconnect_min,
+            Duration::from_millis(MIN_RECONNECT_MS)
+        );
+        assert_eq!(
+            config.reconnect_max,
+            Duration::from_millis(MIN_RECONNECT_MS)
+        );
+    }
+
+    #[test]
+    fn limits_the_simulated_aircraft() {
+        let (config, warnings) = parse(&[("SIM_AIRCRAFT", "2000000")]);
+        assert_eq!(config.sim_aircraft, MAX_SIM_AIRCRAFT);
+        assert_eq!(
+            warnings,
+            vec!["SIM_AIRCRAFT is limited to 10000, using that"]
+        );
+
+        let (config, warnings) = parse(&[("SIM_AIRCRAFT", "500")]);
+        assert_eq!(config.sim_aircraft, 500);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn validates_station_ids() {
+        assert!(StationInfo::is_valid_id("station_001-a"));
+        assert!(StationInfo::is_valid_id(&"a".repeat(MAX_STATION_ID_LEN)));
+        assert!(!StationInfo::is_valid_id(""));
+        assert!(!StationInfo::is_valid_id(
+            &"a".repeat(MAX_STATION_ID_LEN + 1)
+        ));
+        assert!(!StationInfo::is_valid_id("station 001"));
+        assert!(!StationInfo::is_valid_id("../station001"));
+        assert!(!StationInfo::is_valid_id("stätion"));
+    }
+}
@@ -0,0 +1,157 @@
+//! The 24-bit Mode S cyclic redundancy check.
+//!
+//! Every Mode S frame ends with a 24-bit parity field. Extended squitters carry the plain
+//! checksum of the rest of the frame, while surveillance replies overlay it with the
+//! transponder's ICAO address (address/parity), so the address is recovered by computing the
+//! checksum and XOR-ing it with the parity field.
+
+/// Generator polynomial, including the implicit leading bit
+const GENERATOR: u32 = 0x1FF_F409;
+/// Bits holding the downlink format, which are never corrected since flipping them
+/// changes the frame length
+const DF_BITS: usize = 5;
+
+/// Outcome of checking a frame's parity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    /// The frame is intact
+    Valid,
+    /// A single-bit error was found and corrected in place
+    Corrected,
+    /// The parity field is overlaid with an address we can't know in advance, so the
+    /// frame can't be checked on its own
+    Unverifiable,
+    /// The frame is corrupt
+    Failed,
+}
+
+/// Verifies the parity of a frame. Extended squitters (DF17/18) must have a zero syndrome
+/// and, if `correct` is set, a single-bit error anywhere after the downlink format is
+/// repaired. All-call replies (DF11) may only differ in the 7-bit interrogator code.
+/// Address/parity replies are reported as unverifiable.
+pub fn check(data: &mut [u8], correct: bool) -> Check {
+    if data.len() < 4 {
+        return Check::Failed;
+    }
+    let syndrome = syndrome(data);
+    match data[0] >> 3 {
+        17 | 18 if syndrome == 0 => Check::Valid,
+        17 | 18 if correct => match error_bit(data.len(), syndrome) {
+            Some(bit) => {
+                data[bit / 8] ^= 0x80 >> (bit % 8);
+                Check::Corrected
+            }
+            None => Check::Failed,
+        },
+        17 | 18 => Check::Failed,
+        11 if syndrome & 0xFF_FF80 == 0 => Check::Valid,
+        11 => Check::Failed,
+        _ => Check::Unverifiable,
+    }
+}
+
+/// Computes the checksum of the given bytes
+pub fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ (u32::from(*byte) << 16), |crc, _| {
+            let crc = crc << 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^ GENERATOR
+            } else {
+                crc
+            }
+        })
+    })
+}
+
+/// Computes the syndrome of a frame: the checksum of everything but the parity field XOR-ed
+/// with the parity field. This is zero for an intact extended squitter and the ICAO address
+/// for an intact surveillance reply.
+pub fn syndrome(data: &[u8]) -> u32 {
+    let split = data.len() - 3;
+    let parity = data[split..]
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | u32::from(*byte));
+    checksum(&data[..split]) ^ parity
+}
+
+/// Finds the bit whose flipping would produce the given syndrome. The CRC is linear, so
+/// this is the bit whose own syndrome matches.
+fn error_bit(len: usize, syndrome: u32) -> Option<usize> {
+    let mut frame = vec![0; len];
+    (DF_BITS..len * 8).find(|bit| {
+        frame.iter_mut().for_each(|byte| *byte = 0);
+        frame[bit / 8] = 0x80 >> (bit % 8);
+        self::syndrome(&frame) == syndrome
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Identification squitter of KLM1023
+    const SQUITTER: [u8; 14] = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+
+    /// A short reply with the given parity overlay
+    fn reply(head: [u8; 4], overlay: u32) -> Vec<u8> {
+        let mut data = head.to_vec();
+        let parity = checksum(&data) ^ overlay;
+        data.extend_from_slice(&parity.to_be_bytes()[1..]);
+        data
+    }
+
+    #[test]
+    fn intact_squitter_has_zero_syndrome() {
+        assert_eq!(syndrome(&SQUITTER), 0);
+        assert_eq!(check(&mut SQUITTER.clone(), true), Check::Valid);
+    }
+
+    #[test]
+    fn surveillance_reply_syndrome_is_its_address() {
+        let mut data = reply([0x20, 0x00, 0x18, 0x38], 0x48_40D6);
+        assert_eq!(syndrome(&data), 0x48_40D6);
+        assert_eq!(check(&mut data, true), Check::Unverifiable);
+    }
+
+    #[test]
+    fn all_call_reply_may_differ_in_interrogator_code() {
+        assert_eq!(
+            check(&mut reply([0x5D, 0x48, 0x40, 0xD6], 0x11), true),
+            Check::Valid
+        );
+        assert_eq!(
+            check(&mut reply([0x5D, 0x48, 0x40, 0xD6], 0x1100), true),
+            Check::Failed
+        );
+    }
+
+    #[test]
+    fn corrects_single_bit_error() {
+        let mut data = SQUITTER;
+        data[6] ^= 0x10;
+        assert_eq!(check(&mut data.clone(), false), Check::Failed);
+        assert_eq!(check(&mut data, true), Check::Corrected);
+        assert_eq!(data, SQUITTER);
+    }
+
+    #[test]
+    fn rejects_two_bit_error() {
+        let mut data = SQUITTER;
+        data[6] ^= 0x10;
+        data[9] ^= 0x01;
+        assert_eq!(check(&mut data, true), Check::Failed);
+    }
+
+    #[test]
+    fn finds_error_bit_outside_downlink_format() {
+        let mut data = [0; 14];
+        data[10] = 0x08;
+        assert_eq!(error_bit(14, syndrome(&data)), Some(84));
+        let mut data = [0; 14];
+        data[0] = 0x20;
+        assert_eq!(error_bit(14, syndrome(&data)), None);
+    }
+}
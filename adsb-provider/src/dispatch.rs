@@ -0,0 +1,597 @@
+//! Delivery of decoded messages and station status reports to the bound actor. Both are
+//! handed from the threads producing them to a dedicated dispatch thread through a bounded
+//! queue, so a slow actor can't stall reading, the queue sheds load according to the
+//! binding's drop policy and the actor receives everything in the order it was produced.
+
+use crate::adsbtypes::{ADSBMessage, ADSBMessageBatch, StationStatus};
+use crate::config::BindingConfig;
+use crate::stats::Statistics;
+use crate::{OP_MESSAGES_RECEIVED, OP_MESSAGE_RECEIVED, OP_STATION_STATUS_CHANGED};
+use codec::capabilities::Dispatcher;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+
+/// What happens to a message when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropPolicy {
+    /// Discard the oldest queued message to make room
+    Oldest,
+    /// Discard the new message
+    Newest,
+    /// Wait for room, which stops reading from the upstream until the actor catches up
+    Block,
+}
+
+impl FromStr for DropPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "oldest" => Ok(DropPolicy::Oldest),
+            "newest" => Ok(DropPolicy::Newest),
+            "block" => Ok(DropPolicy::Block),
+            _ => Err(format!("Unknown drop policy: {}", s)),
+        }
+    }
+}
+
+/// Something waiting to be dispatched to the actor
+enum Item {
+    Message(ADSBMessage),
+    Status(StationStatus),
+}
+
+#[derive(Default)]
+struct State {
+    items: VecDeque<Item>,
+    /// Number of messages among the items, which is what the capacity limits
+    messages: usize,
+    closed: bool,
+}
+
+/// Bounded queue of messages and status reports waiting to be dispatched to a bound actor
+pub struct DispatchQueue {
+    state: Mutex<State>,
+    /// Signalled when a message is queued or the queue is closed
+    available: Condvar,
+    /// Signalled when messages are taken off the queue or the queue is closed
+    space: Condvar,
+    capacity: usize,
+    policy: DropPolicy,
+    stats: Arc<Statistics>,
+}
+
+impl DispatchQueue {
+    pub fn new(config: &BindingConfig, stats: Arc<Statistics>) -> Self {
+        DispatchQueue {
+            state: Mutex::new(State::default()),
+            available: Condvar::new(),
+            space: Condvar::new(),
+            capacity: config.queue_size.max(1),
+            policy: config.drop_policy,
+            stats,
+        }
+    }
+
+    /// Queues a message for dispatch, applying the drop policy if the queue is full.
+    /// Messages pushed after the queue was closed are discarded.
+    pub fn push(&self, message: ADSBMessage) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => {
+                error!("Dispatch queue lock poisoned, dropping message");
+                return;
+            }
+        };
+        while !state.closed && state.messages >= self.capacity {
+            match self.policy {
+                DropPolicy::Oldest => {
+                    let oldest = state
+                        .items
+                        .iter()
+                        .position(|item| matches!(item, Item::Message(_)));
+                    if let Some(Item::Message(dropped)) =
+                        oldest.and_then(|index| state.items.remove(index))
+                    {
+                        state.messages -= 1;
+                        self.stats.message_dropped(&dropped);
+                    }
+                }
+                DropPolicy::Newest => {
+                    self.stats.message_dropped(&message);
+                    return;
+                }
+                DropPolicy::Block => match self.space.wait(state) {
+                    Ok(s) => state = s,
+                    Err(_) => return,
+                },
+            }
+        }
+        if state.closed {
+            return;
+        }
+        state.items.push_back(Item::Message(message));
+        state.messages += 1;
+        self.available.notify_one();
+    }
+
+    /// Queues a station status report. Reports are rare and tell the actor about changes it
+    /// must not miss, so they are never dropped or held back by a full queue.
+    pub fn push_status(&self, status: StationStatus) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.closed {
+                return;
+            }
+            state.items.push_back(Item::Status(status));
+            self.available.notify_one();
+        }
+    }
+
+    /// Waits for queued items and takes up to `max` of them, oldest first. Returns `None`
+    /// once the queue is closed and empty.
+    fn pop(&self, max: usize) -> Option<Vec<Item>> {
+        let mut state = self.state.lock().ok()?;
+        while state.items.is_empty() {
+            if state.closed {
+                return None;
+            }
+            state = self.available.wait(state).ok()?;
+        }
+        let count = max.max(1).min(state.items.len());
+        let batch: Vec<Item> = state.items.drain(..count).collect();
+        state.messages -= batch
+            .iter()
+            .filter(|item| matches!(item, Item::Message(_)))
+            .count();
+        self.space.notify_all();
+        Some(batch)
+    }
+
+    /// Stops accepting messages. Messages already queued are still dispatched.
+    pub fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.available.notify_all();
+        self.space.notify_all();
+    }
+
+    /// Stops accepting messages and discards those still queued
+    pub fn abort(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+            state.items.clear();
+            state.messages = 0;
+        }
+        self.available.notify_all();
+        self.space.notify_all();
+    }
+
+    /// Empties the queue, returning the messages in it, so that tests can see what a source
+    /// produced without an actor to dispatch to
+    #[cfg(test)]
+    pub fn take_messages(&self) -> Vec<ADSBMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.messages = 0;
+        state
+            .items
+            .drain(..)
+            .filter_map(|item| match item {
+                Item::Message(message) => Some(message),
+                Item::Status(_) => None,
+            })
+            .collect()
+    }
+
+    /// Empties the queue, returning the status reports in it
+    #[cfg(test)]
+    pub fn take_statuses(&self) -> Vec<StationStatus> {
+        let mut state = self.state.lock().unwrap();
+        state.messages = 0;
+        state
+            .items
+            .drain(..)
+            .filter_map(|item| match item {
+                Item::Message(_) => None,
+                Item::Status(status) => Some(status),
+            })
+            .collect()
+    }
+}
+
+/// Dispatches queued messages and status reports to the actor until the queue is closed
+/// and drained. With a batch size above one, the messages that accumulated while the
+/// previous call was in progress are delivered in a single `MessagesReceived` call,
+/// otherwise every message is delivered with its own `MessageReceived` call. Status reports
+/// are delivered with `StationStatusChanged` calls, after the messages queued before them.
+pub fn dispatch_messages(
+    queue: &DispatchQueue,
+    dispatcher: &Arc<RwLock<Box<dyn Dispatcher>>>,
+    actor: &str,
+    batch_size: usize,
+) {
+    let stats = &queue.stats;
+    while let Some(items) = queue.pop(batch_size) {
+        let mut messages = Vec::new();
+        for item in items {
+            match item {
+                Item::Message(message) => messages.push(message),
+                Item::Status(status) => {
+                    let queued = std::mem::take(&mut messages);
+                    deliver(dispatcher, actor, queued, batch_size, stats);
+                    match wascc_codec::serialize(&status) {
+                        Ok(buf) => dispatch(
+                            dispatcher,
+                            actor,
+                            OP_STATION_STATUS_CHANGED,
+                            &buf,
+                            &[],
+                            stats,
+                        ),
+                        Err(e) => error!("Failed to serialize station status: {}", e),
+                    }
+                }
+            }
+        }
+        deliver(dispatcher, actor, messages, batch_size, stats);
+    }
+}
+
+fn deliver(
+    dispatcher: &Arc<RwLock<Box<dyn Dispatcher>>>,
+    actor: &str,
+    messages: Vec<ADSBMessage>,
+    batch_size: usize,
+    stats: &Statistics,
+) {
+    if messages.is_empty() {
+        return;
+    }
+    if batch_size > 1 {
+        let batch = ADSBMessageBatch { messages };
+        match wascc_codec::serialize(&batch) {
+            Ok(buf) => dispatch(
+                dispatcher,
+                actor,
+                OP_MESSAGES_RECEIVED,
+                &buf,
+                &batch.messages,
+                stats,
+            ),
+            Err(e) => {
+                error!("Failed to serialize messages: {}", e);
+                stats.dispatch_failed(&batch.messages);
+            }
+        }
+    } else {
+        for message in messages.iter() {
+            let single = std::slice::from_ref(message);
+            match wascc_codec::serialize(message) {
+                Ok(buf) => dispatch(dispatcher, actor, OP_MESSAGE_RECEIVED, &buf, single, stats),
+                Err(e) => {
+                    error!("Failed to serialize message: {}", e);
+                    stats.dispatch_failed(single);
+                }
+            }
+        }
+    }
+}
+
+fn dispatch(
+    dispatcher: &Arc<RwLock<Box<dyn Dispatcher>>>,
+    actor: &str,
+    op: &str,
+    buf: &[u8],
+    messages: &[ADSBMessage],
+    stats: &Statistics,
+) {
+    match dispatcher.read() {
+        Ok(dispatcher) => {
+            if let Err(e) = dispatcher.dispatch(actor, op, buf) {
+                error!("Failed to dispatch {} to {}: {}", op, actor, e);
+                stats.dispatch_failed(messages);
+            }
+        }
+        Err(_) => {
+            error!("Dispatcher lock poisoned, dropping {}", op);
+            stats.dispatch_failed(messages);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsbtypes::{ADSBMessagePayload, ConnectionState, MessageHeader};
+    use codec::core::CapabilityConfiguration;
+    use std::error::Error;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Operation and payload of a call to the actor
+    type Call = (String, Vec<u8>);
+
+    /// Records the calls made to the actor, failing them if `fail` is set
+    #[derive(Clone, Default)]
+    struct Actor {
+        calls: Arc<Mutex<Vec<Call>>>,
+        fail: bool,
+    }
+
+    impl Dispatcher for Actor {
+        fn dispatch(
+            &self,
+            _actor: &str,
+            op: &str,
+            msg: &[u8],
+        ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+            if self.fail {
+                return Err("actor failed".into());
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push((op.to_string(), msg.to_vec()));
+            Ok(vec![])
+        }
+    }
+
+    impl Actor {
+        /// Returns each call's operation along with the messages or station it carried
+        fn calls(&self) -> Vec<(String, Vec<u64>)> {
+            self.calls
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(op, buf)| {
+                    let ids = match op.as_str() {
+                        OP_MESSAGE_RECEIVED => {
+                            let message: ADSBMessage = codec::deserialize(buf).unwrap();
+                            vec![message.received_at]
+                        }
+                        OP_MESSAGES_RECEIVED => {
+                            let batch: ADSBMessageBatch = codec::deserialize(buf).unwrap();
+                            batch.messages.iter().map(|m| m.received_at).collect()
+                        }
+                        _ => {
+                            let status: StationStatus = codec::deserialize(buf).unwrap();
+                            vec![status.reported_at]
+                        }
+                    };
+                    (op.clone(), ids)
+                })
+                .collect()
+        }
+    }
+
+    fn queue(policy: &str, size: usize) -> DispatchQueue {
+        let values = [("DROP_POLICY", policy), ("QUEUE_SIZE", &size.to_string())]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values,
+        });
+        DispatchQueue::new(&config, Arc::new(Statistics::new()))
+    }
+
+    /// A message told apart from others by its reception time
+    fn message(id: u64) -> ADSBMessage {
+        ADSBMessage {
+            station_id: "station001".to_string(),
+            station_name: "Station".to_string(),
+            received_at: id,
+            mlat_timestamp: None,
+            rssi: None,
+            header: MessageHeader {
+                downlink_format: 17,
+                capability: 5,
+                icao_address: "4840D6".to_string(),
+                type_code: 4,
+            },
+            payload: ADSBMessagePayload::AircraftIdentification {
+                emitter_category: 0,
+                callsign: "KLM1023".to_string(),
+            },
+            station_location: None,
+        }
+    }
+
+    /// A status report told apart from others by its report time
+    fn status(id: u64) -> StationStatus {
+        StationStatus {
+            station_id: "station001".to_string(),
+            station_name: "Station".to_string(),
+            state: ConnectionState::Connected,
+            upstream: None,
+            last_frame_at: None,
+            message_rate: 0.0,
+            reported_at: id,
+            location: None,
+        }
+    }
+
+    fn ids(messages: &[ADSBMessage]) -> Vec<u64> {
+        messages.iter().map(|m| m.received_at).collect()
+    }
+
+    fn dropped(queue: &DispatchQueue) -> u64 {
+        queue
+            .stats
+            .snapshot()
+            .stations
+            .iter()
+            .map(|s| s.dropped)
+            .sum()
+    }
+
+    fn dispatch_all(queue: &DispatchQueue, actor: &Actor, batch_size: usize) {
+        let dispatcher: Arc<RwLock<Box<dyn Dispatcher>>> =
+            Arc::new(RwLock::new(Box::new(actor.clone())));
+        queue.close();
+        dispatch_messages(queue, &dispatcher, "actor", batch_size);
+    }
+
+    #[test]
+    fn drops_the_oldest_messages_when_full() {
+        let queue = queue("oldest", 3);
+        for id in 1..=5 {
+            queue.push(message(id));
+        }
+        assert_eq!(ids(&queue.take_messages()), vec![3, 4, 5]);
+        assert_eq!(dropped(&queue), 2);
+    }
+
+    #[test]
+    fn drops_the_newest_messages_when_full() {
+        let queue = queue("newest", 3);
+        for id in 1..=5 {
+            queue.push(message(id));
+        }
+        assert_eq!(ids(&queue.take_messages()), vec![1, 2, 3]);
+        assert_eq!(dropped(&queue), 2);
+    }
+
+    #[test]
+    fn blocks_until_there_is_room() {
+        let queue = Arc::new(queue("block", 2));
+        queue.push(message(1));
+        queue.push(message(2));
+        let (pushed, done) = mpsc::channel();
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                queue.push(message(3));
+                let _ = pushed.send(());
+            })
+        };
+        assert!(done.recv_timeout(Duration::from_millis(50)).is_err());
+
+        assert_eq!(queue.pop(1).unwrap().len(), 1);
+        pusher.join().unwrap();
+        assert_eq!(ids(&queue.take_messages()), vec![2, 3]);
+        assert_eq!(dropped(&queue), 0);
+    }
+
+    #[test]
+    fn unblocks_pushes_when_closed() {
+        let queue = Arc::new(queue("block", 1));
+        queue.push(message(1));
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(message(2)))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close();
+        pusher.join().unwrap();
+        assert_eq!(ids(&queue.take_messages()), vec![1]);
+    }
+
+    #[test]
+    fn never_drops_status_reports() {
+        for policy in &["oldest", "newest"] {
+            let queue = queue(policy, 1);
+            queue.push(message(1));
+            queue.push_status(status(10));
+            queue.push_status(status(11));
+            queue.push(message(2));
+            let items = queue.pop(10).unwrap();
+            let statuses = items
+                .iter()
+                .filter(|item| matches!(item, Item::Status(_)))
+                .count();
+            assert_eq!(items.len(), 3, "{}", policy);
+            assert_eq!(statuses, 2, "{}", policy);
+            assert_eq!(dropped(&queue), 1, "{}", policy);
+        }
+    }
+
+    #[test]
+    fn dispatches_messages_individually() {
+        let queue = queue("oldest", 10);
+        let actor = Actor::default();
+        for id in 1..=3 {
+            queue.push(message(id));
+        }
+        dispatch_all(&queue, &actor, 1);
+        assert_eq!(
+            actor.calls(),
+            vec![
+                (OP_MESSAGE_RECEIVED.to_string(), vec![1]),
+                (OP_MESSAGE_RECEIVED.to_string(), vec![2]),
+                (OP_MESSAGE_RECEIVED.to_string(), vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dispatches_messages_in_batches() {
+        let queue = queue("oldest", 10);
+        let actor = Actor::default();
+        for id in 1..=5 {
+            queue.push(message(id));
+        }
+        dispatch_all(&queue, &actor, 2);
+        assert_eq!(
+            actor.calls(),
+            vec![
+                (OP_MESSAGES_RECEIVED.to_string(), vec![1, 2]),
+                (OP_MESSAGES_RECEIVED.to_string(), vec![3, 4]),
+                (OP_MESSAGES_RECEIVED.to_string(), vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dispatches_status_reports_after_the_messages_queued_before_them() {
+        let queue = queue("oldest", 10);
+        let actor = Actor::default();
+        queue.push(message(1));
+        queue.push(message(2));
+        queue.push_status(status(10));
+        queue.push(message(3));
+        dispatch_all(&queue, &actor, 10);
+        assert_eq!(
+            actor.calls(),
+            vec![
+                (OP_MESSAGES_RECEIVED.to_string(), vec![1, 2]),
+                (OP_STATION_STATUS_CHANGED.to_string(), vec![10]),
+                (OP_MESSAGES_RECEIVED.to_string(), vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_messages_the_actor_failed_to_accept() {
+        let queue = queue("oldest", 10);
+        let actor = Actor {
+            fail: true,
+            ..Actor::default()
+        };
+        for id in 1..=3 {
+            queue.push(message(id));
+        }
+        dispatch_all(&queue, &actor, 2);
+        let stations = queue.stats.snapshot().stations;
+        assert_eq!(stations[0].dispatch_failures, 3);
+    }
+
+    #[test]
+    fn discards_messages_after_closing() {
+        let closed = queue("oldest", 10);
+        closed.push(message(1));
+        closed.close();
+        closed.push(message(2));
+        closed.push_status(status(10));
+        assert_eq!(closed.pop(10).unwrap().len(), 1);
+        assert!(closed.pop(10).is_none());
+
+        let aborted = queue("oldest", 10);
+        aborted.push(message(1));
+        aborted.abort();
+        assert!(aborted.pop(10).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::avr::{self, AvrReader};
+use crate::beast::BeastReader;
+use crate::recorder::RecordingReader;
+use crate::sbs::SbsReader;
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// A unit of input read from an upstream feed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// Raw Mode S frame to be decoded by the provider
+    ModeS(ModeSFrame),
+    /// BaseStation record, already decoded by the upstream
+    Sbs(String),
+}
+
+/// Formats the frame as a single line of text: an AVR line for Mode S frames and the
+/// original record for SBS
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frame::ModeS(frame) => write!(f, "{}", avr::format_frame(frame)),
+            Frame::Sbs(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+/// A raw Mode S frame received from an upstream feed, along with any reception
+/// metadata the feed supplied
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeSFrame {
+    /// Frame contents, 7 bytes for short and 14 bytes for extended squitters
+    pub data: Vec<u8>,
+    /// 48-bit receiver timestamp, counting ticks of a 12MHz clock
+    pub mlat_timestamp: Option<u64>,
+    /// Signal level in dBFS
+    pub rssi: Option<f64>,
+}
+
+/// Wire format of an upstream feed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// AVR text lines, `dump1090` port 30002
+    Avr,
+    /// Beast binary frames, `dump1090` port 30005
+    Beast,
+    /// BaseStation (SBS-1) CSV records, `dump1090` port 30003
+    Sbs,
+    /// Files written by the provider's recorder
+    Recording,
+}
+
+impl Format {
+    /// The port on which `dump1090` serves this format by default
+    pub fn default_port(self) -> u16 {
+        match self {
+            Format::Avr => 30002,
+            Format::Beast => 30005,
+            Format::Sbs => 30003,
+            // Recordings are replayed from disk rather than served
+            Format::Recording => Format::Avr.default_port(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "avr" => Ok(Format::Avr),
+            "beast" => Ok(Format::Beast),
+            "sbs" => Ok(Format::Sbs),
+            "recording" => Ok(Format::Recording),
+            _ => Err(format!("Unknown feed format: {}", s)),
+        }
+    }
+}
+
+/// Creates an iterator over the frames read from the given source
+pub fn frames<R: Read + 'static>(
+    format: Format,
+    source: R,
+) -> Box<dyn Iterator<Item = io::Result<Frame>>> {
+    match format {
+        Format::Avr => Box::new(AvrReader::new(source).map(|f| f.map(Frame::ModeS))),
+        Format::Beast => Box::new(BeastReader::new(source).map(|f| f.map(Frame::ModeS))),
+        Format::Sbs => Box::new(SbsReader::new(source).map(|l| l.map(Frame::Sbs))),
+        Format::Recording => {
+            Box::new(RecordingReader::new(source).map(|r| r.map(|recorded| recorded.frame)))
+        }
+    }
+}
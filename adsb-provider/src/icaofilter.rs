@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Tracks the ICAO addresses announced in frames whose parity could be verified (DF11 and
+/// DF17/18). Address/parity replies only yield an address by assuming the frame is intact,
+/// so a corrupted reply produces a random one. Such replies are only trusted if their
+/// address was announced within the last `ttl`.
+pub struct IcaoFilter {
+    ttl: u64,
+    seen: HashMap<u32, u64>,
+    last_purge: u64,
+}
+
+impl IcaoFilter {
+    /// Creates a filter that remembers addresses for `ttl`. A zero `ttl` disables the
+    /// filter, accepting every address.
+    pub fn new(ttl: Duration) -> Self {
+        IcaoFilter {
+            ttl: ttl.as_millis() as u64,
+            seen: HashMap::new(),
+            last_purge: 0,
+        }
+    }
+
+    /// Records an address announced in a verified frame at `now` (milliseconds since the
+    /// UNIX epoch)
+    pub fn announce(&mut self, address: u32, now: u64) {
+        if self.ttl == 0 {
+            return;
+        }
+        self.seen.insert(address, now);
+        if now.saturating_sub(self.last_purge) > self.ttl {
+            let ttl = self.ttl;
+            self.seen.retain(|_, seen| now.saturating_sub(*seen) <= ttl);
+            self.last_purge = now;
+        }
+    }
+
+    /// Indicates whether a reply from the given address should be trusted
+    pub fn accepts(&self, address: u32, now: u64) -> bool {
+        self.ttl == 0
+            || self
+                .seen
+                .get(&address)
+                .map_or(false, |seen| now.saturating_sub(*seen) <= self.ttl)
+    }
+}
+
+/// The address announced in the AA field of a DF11 or DF17/18 frame
+pub fn announced_address(data: &[u8]) -> u32 {
+    u32::from(data[1]) << 16 | u32::from(data[2]) << 8 | u32::from(data[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn accepts_announced_addresses() {
+        let mut filter = IcaoFilter::new(TTL);
+        filter.announce(0x4840D6, 1_000);
+        assert!(filter.accepts(0x4840D6, 1_000));
+        assert!(filter.accepts(0x4840D6, 61_000));
+    }
+
+    #[test]
+    fn rejects_addresses_never_announced() {
+        let mut filter = IcaoFilter::new(TTL);
+        assert!(!filter.accepts(0x4840D6, 1_000));
+        filter.announce(0x485020, 1_000);
+        assert!(!filter.accepts(0x4840D6, 1_000));
+    }
+
+    #[test]
+    fn rejects_addresses_announced_longer_ago_than_the_ttl() {
+        let mut filter = IcaoFilter::new(TTL);
+        filter.announce(0x4840D6, 1_000);
+        assert!(!filter.accepts(0x4840D6, 61_001));
+
+        // Announcing the address again trusts it for another ttl
+        filter.announce(0x4840D6, 50_000);
+        assert!(filter.accepts(0x4840D6, 110_000));
+        assert!(!filter.accepts(0x4840D6, 110_001));
+    }
+
+    #[test]
+    fn accepts_every_address_with_a_zero_ttl() {
+        let mut filter = IcaoFilter::new(Duration::from_secs(0));
+        assert!(filter.accepts(0x4840D6, 1_000));
+        filter.announce(0x4840D6, 1_000);
+        assert!(filter.seen.is_empty());
+    }
+
+    #[test]
+    fn purges_only_expired_addresses() {
+        let mut filter = IcaoFilter::new(TTL);
+        filter.announce(0x4840D6, 1_000);
+        filter.announce(0x485020, 30_000);
+        filter.announce(0x3C6586, 80_000);
+        assert_eq!(filter.seen.len(), 2);
+        assert!(!filter.accepts(0x4840D6, 80_000));
+        assert!(filter.accepts(0x485020, 80_000));
+        assert!(filter.accepts(0x3C6586, 80_000));
+    }
+
+    #[test]
+    fn reads_the_announced_address() {
+        let frame = [0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3];
+        assert_eq!(announced_address(&frame), 0x4840D6);
+    }
+}
@@ -11,6 +11,7 @@ use codec::capabilities::{
 use codec::core::{CapabilityConfiguration, OP_BIND_ACTOR, OP_REMOVE_ACTOR};
 use codec::{deserialize, serialize};
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::{Arc, RwLock};
 
@@ -19,29 +20,52 @@ const CAPABILITY_ID: &str = "sdr:adsb";
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const REVISION: u32 = 0;
 
-use adsb::Message;
-use adsbtypes::ADSBMessage;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::net::{SocketAddr, TcpStream};
+use adsbtypes::ConnectionState;
+use binding::{Binding, CancelToken};
+use config::{BindingConfig, Source};
+use dispatch::DispatchQueue;
+use pipeline::Pipeline;
+use security::Security;
+use stats::Statistics;
+use status::StatusReporter;
 use std::thread;
-use std::{convert::TryFrom, time::Duration};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use upstream::{Connection, Failback};
 
 mod adsbtypes;
+mod avr;
+mod beast;
+mod binding;
+mod config;
+mod crc;
+mod dispatch;
+mod frame;
+mod icaofilter;
+mod listener;
+mod modes;
+mod pipeline;
+mod recorder;
+mod replay;
+mod sbs;
+mod security;
+mod simulator;
+mod stats;
+mod status;
+mod throttle;
+mod upstream;
 
-const OP_MESSAGE_RECEIVED: &str = "MessageReceived";
-
-const CONFIG_HOST: &str = "HOST";
-const CONFIG_PORT: &str = "PORT";
-const CONFIG_TIMEOUT: &str = "TIMEOUT";
-const CONFIG_STATION_ID: &str = "STATION_ID";
-const CONFIG_STATION_NAME: &str = "STATION_NAME";
+pub(crate) const OP_MESSAGE_RECEIVED: &str = "MessageReceived";
+pub(crate) const OP_MESSAGES_RECEIVED: &str = "MessagesReceived";
+pub(crate) const OP_STATION_STATUS_CHANGED: &str = "StationStatusChanged";
+const OP_QUERY_STATISTICS: &str = "QueryStatistics";
+const OP_QUERY_STATION_STATUS: &str = "QueryStationStatus";
 
 #[cfg(not(feature = "static_plugin"))]
 capability_provider!(AdsbProvider, AdsbProvider::new);
 
 pub struct AdsbProvider {
     dispatcher: Arc<RwLock<Box<dyn Dispatcher>>>,
+    bindings: Arc<RwLock<HashMap<String, Binding>>>,
 }
 
 impl AdsbProvider {
@@ -52,6 +76,7 @@ impl AdsbProvider {
         };
         AdsbProvider {
             dispatcher: Arc::new(RwLock::new(Box::new(NullDispatcher::new()))),
+            bindings: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -59,37 +84,76 @@ impl AdsbProvider {
         &self,
         config: CapabilityConfiguration,
     ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
-        let host = config.values.get(CONFIG_HOST).unwrap_or(&"127.0.0.1".to_string()).to_string();
-        let port: u16 = config.values[CONFIG_PORT].parse().unwrap_or(30002);
-        let timeout: u64 = config.values[CONFIG_TIMEOUT].parse().unwrap_or(30000);
-        let station_id = config.values.get(CONFIG_STATION_ID).unwrap_or(&"station001".to_string()).to_string();
-        let station_name = config.values.get(CONFIG_STATION_NAME).unwrap_or(&"Unnamed Station".to_string()).to_string();
+        let config = BindingConfig::from(&config);
 
-        info!("Bound actor {} to 1090Mhz station source {} ({})", &config.module, &station_name, &station_id);
+        info!(
+            "Bound actor {} to 1090Mhz station source {} ({})",
+            &config.actor, &config.station.name, &config.station.id
+        );
+        let actor = config.actor.to_string();
+        // The previous binding must release its sockets before the replacement listens on
+        // or connects from the same ports
+        let previous = self.bindings.write().unwrap().remove(&actor);
+        if let Some(previous) = previous {
+            warn!(
+                "Actor {} was already bound, replacing existing binding",
+                &actor
+            );
+            previous.stop();
+        }
         let d = self.dispatcher.clone();
-        thread::spawn(move || {
-            consume_adsb(
-                d,
-                host,
-                port,
-                timeout,
-                config.module.to_string(),
-                station_id,
-                station_name,
-            )
-        });
+        let cancel = Arc::new(CancelToken::new());
+        let c = cancel.clone();
+        let stats = Arc::new(Statistics::new());
+        let s = stats.clone();
+        let queue = Arc::new(DispatchQueue::new(&config, stats.clone()));
+        let q = queue.clone();
+        let status = Arc::new(StatusReporter::new(queue.clone()));
+        let r = status.clone();
+        let handle = thread::spawn(move || consume_adsb(d, config, c, s, q, r));
+
+        self.bindings.write().unwrap().insert(
+            actor.to_string(),
+            Binding::new(handle, cancel, stats, queue, status),
+        );
 
         Ok(vec![])
     }
 
     fn deconfigure(
         &self,
-        _config: CapabilityConfiguration,
+        config: CapabilityConfiguration,
     ) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
-        // Handle removal of resources claimed by an actor here
+        let binding = self.bindings.write().unwrap().remove(&config.module);
+        match binding {
+            Some(binding) => {
+                binding.stop();
+                info!("Removed binding for actor {}", &config.module);
+            }
+            None => warn!(
+                "Received remove request for actor {} which is not bound",
+                &config.module
+            ),
+        }
         Ok(vec![])
     }
 
+    fn query_statistics(&self, actor: &str) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+        let bindings = self.bindings.read().unwrap();
+        match bindings.get(actor) {
+            Some(binding) => Ok(serialize(binding.statistics().snapshot())?),
+            None => Err(format!("Actor {} is not bound", actor).into()),
+        }
+    }
+
+    fn query_station_status(&self, actor: &str) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
+        let bindings = self.bindings.read().unwrap();
+        match bindings.get(actor) {
+            Some(binding) => Ok(serialize(binding.status().statuses())?),
+            None => Err(format!("Actor {} is not bound", actor).into()),
+        }
+    }
+
     // Capability providers must provide a descriptor to the host containing metadata and a list of supported operations
     fn get_descriptor(&self) -> Result<Vec<u8>, Box<dyn Error + Sync + Send>> {
         Ok(serialize(
@@ -97,7 +161,7 @@ impl AdsbProvider {
                 .id(CAPABILITY_ID)
                 .name("ADS-B Broadcast Capability Provider")
                 .long_description(
-                    "Capability provider connects to a dump1090 or similar AVR broadcaster and delivers messages to bound actors")
+                    "Capability provider connects to a dump1090 or similar AVR, Beast or SBS broadcaster and delivers messages to bound actors")
                 .version(VERSION)
                 .revision(REVISION)
                 .with_operation(
@@ -105,69 +169,211 @@ impl AdsbProvider {
                     OperationDirection::ToActor,
                     "An AVR message from an ADS-B broadcaster was received",
                 )
+                .with_operation(
+                    OP_MESSAGES_RECEIVED,
+                    OperationDirection::ToActor,
+                    "A batch of messages from an ADS-B broadcaster was received, sent instead of individual messages when batching is enabled",
+                )
+                .with_operation(
+                    OP_STATION_STATUS_CHANGED,
+                    OperationDirection::ToActor,
+                    "The connection state of a station changed, also sent periodically with the station's message rate",
+                )
+                .with_operation(
+                    OP_QUERY_STATISTICS,
+                    OperationDirection::ToProvider,
+                    "Returns the frame counters of the station the actor is bound to",
+                )
+                .with_operation(
+                    OP_QUERY_STATION_STATUS,
+                    OperationDirection::ToProvider,
+                    "Returns the status of the stations fed through the actor's binding",
+                )
                 .build(),
         )?)
     }
 }
 
+/// Feeds a bound actor from the station's configured source until the binding is removed.
+/// Messages and status reports are dispatched to the actor from a separate thread, which
+/// finishes delivering whatever is still queued once the source is exhausted. Another
+/// thread reports the status of the binding's stations periodically.
 fn consume_adsb(
     dispatcher: Arc<RwLock<Box<dyn Dispatcher>>>,
-    host: String,
-    port: u16,
-    timeout: u64,
-    actor: String,
-    station_id: String,
-    station_name: String,
+    config: BindingConfig,
+    cancel: Arc<CancelToken>,
+    stats: Arc<Statistics>,
+    queue: Arc<DispatchQueue>,
+    status: Arc<StatusReporter>,
 ) {
-    let timeout = Duration::from_secs(timeout);
-    let addr = format!("{}:{}", &host, port).parse::<SocketAddr>().unwrap();
-    if let Ok(stream) = TcpStream::connect_timeout(&addr, timeout) {
-        info!("Connected to {}", &addr);
-        let reader = BufReader::new(stream);
-        for line in reader.lines() {
-            let frame = line.unwrap();
-            match adsb::parse_avr(&frame) {
-                Ok((message, _)) => deliver_message(
-                    message,
-                    &actor,
-                    dispatcher.clone(),
-                    &station_id,
-                    &station_name,
-                ),
-                Err(error) => error!("{} {:#?}", frame, error),
-            }
-        }
-    } else {
-        error!(
-            "Failed to connect to {}. No ADS-B messages will be delivered to {}",
-            &addr, actor
-        );
+    let dispatch = {
+        let queue = queue.clone();
+        let actor = config.actor.to_string();
+        let batch_size = config.batch_size;
+        thread::spawn(move || dispatch::dispatch_messages(&queue, &dispatcher, &actor, batch_size))
+    };
+    let finished = Arc::new(CancelToken::new());
+    let reporting = {
+        let status = status.clone();
+        let finished = finished.clone();
+        let interval = config.status_interval;
+        thread::spawn(move || status.run(interval, &finished))
+    };
+    match config.source {
+        Source::Network => consume_network(&queue, &status, &config, &cancel, &stats),
+        Source::File => replay::replay_file(&queue, &status, &config, &cancel, &stats),
+        Source::Listen => listener::listen_tcp(&queue, &status, &config, &cancel, &stats),
+        Source::Udp => listener::listen_udp(&queue, &status, &config, &cancel, &stats),
+        Source::Simulator => simulator::simulate(&queue, &status, &config, &cancel, &stats),
+    }
+    // A removed binding's actor is no longer interested, and a replaced binding's
+    // successor has already started reporting
+    if !cancel.is_cancelled() {
+        status.disconnect_all();
     }
+    // Status reports are queued too, so reporting must stop before the queue is closed
+    finished.cancel();
+    if reporting.join().is_err() {
+        error!("Status reporting thread panicked before shutdown");
+    }
+    queue.close();
+    if dispatch.join().is_err() {
+        error!("Dispatch thread panicked before shutdown");
+    }
+    info!(
+        "Stopped consuming station {} for actor {}",
+        &config.station.id, &config.actor
+    );
 }
 
-fn deliver_message(
-    message: Message,
-    actor: &str,
-    dispatcher: Arc<RwLock<Box<dyn Dispatcher>>>,
-    station_id: &str,
-    station_name: &str,
+/// Supervises the connection to a station's upstream feeds, preferring them in the
+/// configured order. Whenever a connection is lost every upstream is tried again, starting
+/// with the primary, with exponential backoff while none can be reached. While a backup is
+/// in use the primary is probed periodically, and the provider switches back as soon as it
+/// accepts connections again.
+fn consume_network(
+    queue: &DispatchQueue,
+    status: &StatusReporter,
+    config: &BindingConfig,
+    cancel: &CancelToken,
+    stats: &Statistics,
 ) {
-    if let Ok(intmessage) = adsbtypes::ADSBMessage::try_from(message) {
-        let intmessage = ADSBMessage {
-            station_id: station_id.to_string(),
-            station_name: station_name.to_string(),
-            ..intmessage
+    let security = match Security::new(config) {
+        Ok(security) => security,
+        Err(e) => {
+            error!(
+                "Failed to configure TLS for station {}: {}",
+                &config.station.id, e
+            );
+            return;
+        }
+    };
+    let mut backoff = config.reconnect_min;
+    let mut pipeline = Pipeline::new(queue, status, config, stats, config.station.clone());
+    let mut recovered = None;
+    status.set(&config.station, ConnectionState::Connecting, None);
+    while !cancel.is_cancelled() {
+        let connection = match recovered.take() {
+            Some(connection) => Some((0, connection)),
+            None => {
+                info!("Connecting to upstream for station {}", &config.station.id);
+                upstream::connect_any(&config.upstreams, config.timeout, &security)
+            }
         };
-        dispatcher
-            .read()
-            .unwrap()
-            .dispatch(
-                actor,
-                OP_MESSAGE_RECEIVED,
-                &wascc_codec::serialize(&intmessage).unwrap(),
-            )
-            .unwrap();
+        if let Some((index, connection)) = connection {
+            let upstream = &config.upstreams[index];
+            info!("Connected to {}", upstream);
+            // A read timeout lets us notice upstreams that vanished without closing the
+            // socket, while quiet feeds are given plenty of time
+            if let Err(e) = connection
+                .socket
+                .set_read_timeout(Some(config.idle_timeout))
+            {
+                warn!("Failed to set idle timeout for {}: {}", upstream, e);
+            }
+            status.set(
+                &config.station,
+                ConnectionState::Connected,
+                Some(upstream.to_string()),
+            );
+            let failback = if index > 0 && config.failback_interval > Duration::from_secs(0) {
+                Failback::start(
+                    &config.upstreams[0],
+                    &security,
+                    config.failback_interval,
+                    config.timeout,
+                    &connection.socket,
+                )
+                .map_err(|e| warn!("Failed to probe primary upstream: {}", e))
+                .ok()
+            } else {
+                None
+            };
+            let registration = cancel.register(&connection.socket);
+            let (frames, primary) = read_frames(connection, config, &mut pipeline, failback);
+            drop(registration);
+            if frames > 0 {
+                backoff = config.reconnect_min;
+            }
+            if cancel.is_cancelled() {
+                break;
+            }
+            if primary.is_some() {
+                info!(
+                    "Primary upstream {} recovered, switching back from {}",
+                    &config.upstreams[0], upstream
+                );
+                recovered = primary;
+                continue;
+            }
+            warn!("Connection to {} lost after {} frames", upstream, frames);
+        }
+        status.set(&config.station, ConnectionState::Reconnecting, None);
+        info!("Reconnecting in {}ms", backoff.as_millis());
+        if !cancel.sleep(backoff) {
+            break;
+        }
+        backoff = (backoff * 2).min(config.reconnect_max);
+    }
+}
+
+/// Reads frames in the configured format from the connection until it is closed or fails,
+/// or until the primary upstream recovers while reading from a backup, which shuts the
+/// connection down. Returns the number of frames received, along with the connection to
+/// the recovered primary.
+fn read_frames(
+    connection: Connection,
+    config: &BindingConfig,
+    pipeline: &mut Pipeline,
+    failback: Option<Failback>,
+) -> (u64, Option<Connection>) {
+    let mut frames = 0;
+    let mut failure = None;
+    for frame in frame::frames(config.format, connection.reader) {
+        match frame {
+            Ok(frame) => {
+                frames += 1;
+                pipeline.process(frame);
+            }
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
     }
+    let primary = failback.and_then(Failback::finish);
+    if let (Some(e), None) = (failure, &primary) {
+        error!("Failed to read from upstream: {}", e);
+    }
+    (frames, primary)
+}
+
+/// Current wall clock time in milliseconds since the UNIX epoch
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 impl CapabilityProvider for AdsbProvider {
@@ -200,6 +406,8 @@ impl CapabilityProvider for AdsbProvider {
             OP_BIND_ACTOR if actor == SYSTEM_ACTOR => self.configure(deserialize(msg)?),
             OP_REMOVE_ACTOR if actor == SYSTEM_ACTOR => self.deconfigure(deserialize(msg)?),
             OP_GET_CAPABILITY_DESCRIPTOR if actor == SYSTEM_ACTOR => self.get_descriptor(),
+            OP_QUERY_STATISTICS => self.query_statistics(actor),
+            OP_QUERY_STATION_STATUS => self.query_station_status(actor),
             _ => Err("bad dispatch".into()),
         }
     }
@@ -0,0 +1,654 @@
+//! Ingestion of frames pushed to the provider by feeders, for stations that can't be
+//! reached from the provider, e.g. because they sit behind NAT. Each feeder is mapped to a
+//! station either by its source address, via `STATION_MAP`, or by a handshake line of the
+//! form `STATION <id> [<name>]` sent before any frames. If the binding has an auth token,
+//! TCP feeders must present it as an `AUTH <token>` line before anything else, and UDP
+//! feeders on the line before their handshake. UDP feeds are not authenticated beyond that:
+//! datagrams are attributed to feeders by their source address, which is easily spoofed.
+//! A feeder claiming a station that another feeder already feeds replaces that feeder, as
+//! it is most likely the same feeder reconnecting from a new connection or address.
+
+use crate::adsbtypes::ConnectionState;
+use crate::binding::CancelToken;
+use crate::config::{BindingConfig, StationInfo};
+use crate::dispatch::DispatchQueue;
+use crate::frame;
+use crate::pipeline::Pipeline;
+use crate::stats::Statistics;
+use crate::status::StatusReporter;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HANDSHAKE_PREFIX: &str = "STATION ";
+const AUTH_PREFIX: &str = "AUTH ";
+const MAX_HANDSHAKE_LEN: usize = 256;
+/// How long a TCP feeder has to send its auth and handshake lines, far shorter than the
+/// idle timeout so that connections sending nothing can't hold on to feeder slots
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the listening sockets check for cancellation while idle
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_DATAGRAM_LEN: usize = 65_536;
+
+/// Accepts TCP connections from feeders on the configured host and port, reading each on
+/// its own thread. Connections beyond `max_feeders` are closed right away. Connection
+/// threads are not joined, cancellation shuts their sockets down so they exit on their own.
+pub fn listen_tcp(
+    queue: &Arc<DispatchQueue>,
+    status: &Arc<StatusReporter>,
+    config: &BindingConfig,
+    cancel: &Arc<CancelToken>,
+    stats: &Arc<Statistics>,
+) {
+    let address = format!("{}:{}", &config.host, config.port);
+    let listener = match TcpListener::bind(&address).and_then(|l| {
+        l.set_nonblocking(true)?;
+        Ok(l)
+    }) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to listen on {}: {}", &address, e);
+            return;
+        }
+    };
+    info!(
+        "Listening for feeders on {} for actor {}",
+        &address, &config.actor
+    );
+    let connections = Arc::new(AtomicUsize::new(0));
+    let claims = Arc::new(StationClaims::default());
+    while !cancel.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if connections.load(Ordering::SeqCst) >= config.max_feeders {
+                    warn!(
+                        "Rejecting feeder {}, already serving {} feeders",
+                        peer, config.max_feeders
+                    );
+                    continue;
+                }
+                connections.fetch_add(1, Ordering::SeqCst);
+                let connections = connections.clone();
+                let queue = queue.clone();
+                let status = status.clone();
+                let config = config.clone();
+                let cancel = cancel.clone();
+                let stats = stats.clone();
+                let claims = claims.clone();
+                thread::spawn(move || {
+                    let feeders = Feeders {
+                        queue: &queue,
+                        status: &status,
+                        config: &config,
+                        cancel: &cancel,
+                        stats: &stats,
+                        claims: &claims,
+                    };
+                    read_feeder(stream, peer, &feeders);
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                cancel.sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                error!("Failed to accept connection on {}: {}", &address, e);
+                cancel.sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// What the threads reading from TCP feeders share
+struct Feeders<'a> {
+    queue: &'a DispatchQueue,
+    status: &'a StatusReporter,
+    config: &'a BindingConfig,
+    cancel: &'a CancelToken,
+    stats: &'a Statistics,
+    claims: &'a StationClaims,
+}
+
+fn read_feeder(mut stream: TcpStream, peer: SocketAddr, feeders: &Feeders) {
+    let Feeders {
+        queue,
+        status,
+        config,
+        cancel,
+        stats,
+        claims,
+    } = *feeders;
+    if let Err(e) = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+    {
+        error!("Failed to configure connection from {}: {}", peer, e);
+        return;
+    }
+    let _registration = cancel.register(&stream);
+    let station = match identify(&mut stream, &peer, config) {
+        Ok(station) => station,
+        Err(e) => {
+            warn!("Rejecting feeder {}: {}", peer, e);
+            return;
+        }
+    };
+    if let Err(e) = stream.set_read_timeout(Some(config.idle_timeout)) {
+        error!("Failed to configure connection from {}: {}", peer, e);
+        return;
+    }
+    let claim = match claims.claim(&station.id, &stream) {
+        Ok(claim) => claim,
+        Err(e) => {
+            error!("Failed to configure connection from {}: {}", peer, e);
+            return;
+        }
+    };
+    info!(
+        "Feeder {} connected as station {} ({})",
+        peer, &station.name, &station.id
+    );
+    status.set(&station, ConnectionState::Connected, Some(peer.to_string()));
+    let mut pipeline = Pipeline::new(queue, status, config, stats, station.clone());
+    let mut frames = 0;
+    for frame in frame::frames(config.format, stream) {
+        match frame {
+            Ok(frame) => {
+                frames += 1;
+                pipeline.process(frame);
+            }
+            Err(e) => {
+                if !cancel.is_cancelled() && claim.is_current() {
+                    warn!("Failed to read from feeder {}: {}", peer, e);
+                }
+                break;
+            }
+        }
+    }
+    info!("Feeder {} disconnected after {} frames", peer, frames);
+    // A feeder that took over the station carries on reporting it
+    if !cancel.is_cancelled() && claim.is_current() {
+        status.remove(&station);
+    }
+}
+
+/// The stations fed by connected TCP feeders, by station id, with the connection feeding
+/// each and a serial number telling claims of the same station apart
+#[derive(Default)]
+struct StationClaims {
+    claims: Mutex<HashMap<String, (usize, TcpStream)>>,
+    next_serial: AtomicUsize,
+}
+
+/// A feeder's claim on a station, released when dropped
+struct Claim<'a> {
+    claims: &'a StationClaims,
+    station_id: String,
+    serial: usize,
+}
+
+impl StationClaims {
+    /// Claims a station for the feeder on a connection. A feeder that already feeds the
+    /// station is replaced, and its connection shut down.
+    fn claim(&self, station_id: &str, stream: &TcpStream) -> io::Result<Claim<'_>> {
+        let stream = stream.try_clone()?;
+        let serial = self.next_serial.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut claims) = self.claims.lock() {
+            if let Some((_, previous)) = claims.insert(station_id.to_string(), (serial, stream)) {
+                info!(
+                    "Replacing feeder {} of station {}",
+                    previous
+                        .peer_addr()
+                        .map_or_else(|_| "?".to_string(), |peer| peer.to_string()),
+                    station_id
+                );
+                let _ = previous.shutdown(Shutdown::Both);
+            }
+        }
+        Ok(Claim {
+            claims: self,
+            station_id: station_id.to_string(),
+            serial,
+        })
+    }
+
+    fn holds(&self, station_id: &str, serial: usize) -> bool {
+        self.claims.lock().map_or(false, |claims| {
+            claims
+                .get(station_id)
+                .map_or(false, |(current, _)| *current == serial)
+        })
+    }
+}
+
+impl Claim<'_> {
+    /// Whether the feeder still feeds the station, rather than having been replaced
+    fn is_current(&self) -> bool {
+        self.claims.holds(&self.station_id, self.serial)
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut claims) = self.claims.claims.lock() {
+            if claims
+                .get(&self.station_id)
+                .map_or(false, |(current, _)| *current == self.serial)
+            {
+                claims.remove(&self.station_id);
+            }
+        }
+    }
+}
+
+/// Authenticates a feeder and resolves its station, by its address or from its handshake
+fn identify(
+    stream: &mut TcpStream,
+    peer: &SocketAddr,
+    config: &BindingConfig,
+) -> io::Result<StationInfo> {
+    authenticate(stream, config)?;
+    match config.station_map.get(&peer.ip()) {
+        Some(station) => Ok(station.clone()),
+        None => read_handshake(stream),
+    }
+}
+
+/// Checks the `AUTH <token>` line a feeder must send first if the binding has a token
+fn authenticate(stream: &mut TcpStream, config: &BindingConfig) -> io::Result<()> {
+    if config.auth_token.is_empty() {
+        return Ok(());
+    }
+    if is_authorized(&read_line(stream)?, &config.auth_token) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "missing or invalid auth token",
+        ))
+    }
+}
+
+fn read_handshake(stream: &mut TcpStream) -> io::Result<StationInfo> {
+    parse_handshake(&read_line(stream)?).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing or invalid station handshake",
+        )
+    })
+}
+
+/// Reads a line one byte at a time, so that nothing following it is consumed
+fn read_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    while line.len() < MAX_HANDSHAKE_LEN {
+        stream.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        }
+        line.push(byte[0]);
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"))
+}
+
+/// Whether a line is `AUTH <token>` with the binding's token, compared in constant time
+fn is_authorized(line: &str, token: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(AUTH_PREFIX)
+        && ring::constant_time::verify_slices_are_equal(
+            line[AUTH_PREFIX.len()..].trim().as_bytes(),
+            token.as_bytes(),
+        )
+        .is_ok()
+}
+
+/// Parses a `STATION <id> [<name>]` handshake line, where the name defaults to the ID.
+/// Handshakes with an id that isn't made up of letters, digits, underscores and hyphens are
+/// rejected.
+fn parse_handshake(line: &str) -> Option<StationInfo> {
+    let line = line.trim();
+    if !line.starts_with(HANDSHAKE_PREFIX) {
+        return None;
+    }
+    let mut parts = line[HANDSHAKE_PREFIX.len()..].trim().splitn(2, ' ');
+    let id = parts.next().filter(|id| StationInfo::is_valid_id(id))?;
+    Some(StationInfo {
+        id: id.to_string(),
+        name: parts
+            .next()
+            .map(|name| name.trim())
+            .unwrap_or(id)
+            .to_string(),
+        location: None,
+    })
+}
+
+/// A UDP feeder, known from its handshake or its `STATION_MAP` entry
+struct Feeder<'a> {
+    station: StationInfo,
+    /// Created once the feeder sends its first frames
+    pipeline: Option<Pipeline<'a>>,
+    last_heard: Instant,
+}
+
+impl Feeder<'_> {
+    fn new(station: StationInfo) -> Self {
+        Feeder {
+            station,
+            pipeline: None,
+            last_heard: Instant::now(),
+        }
+    }
+}
+
+/// Receives datagrams from feeders on the configured host and port. Each datagram holds
+/// one or more frames in the configured format. Feeders without a `STATION_MAP` entry
+/// register by sending the handshake line as a datagram of its own, preceded by an auth
+/// line if the binding has a token, and datagrams from unknown feeders are dropped. A
+/// feeder that stays silent for the idle timeout is forgotten and its station reported as
+/// disconnected. Only the registration is checked against the token, after which the
+/// feeder's datagrams are accepted by source address alone, as are those of `STATION_MAP`
+/// addresses, so anyone able to send from those addresses can feed frames.
+pub fn listen_udp(
+    queue: &DispatchQueue,
+    status: &StatusReporter,
+    config: &BindingConfig,
+    cancel: &CancelToken,
+    stats: &Statistics,
+) {
+    let address = format!("{}:{}", &config.host, config.port);
+    let socket = match UdpSocket::bind(&address).and_then(|s| {
+        s.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(s)
+    }) {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!("Failed to listen on {}: {}", &address, e);
+            return;
+        }
+    };
+    info!(
+        "Listening for feeder datagrams on {} for actor {}",
+        &address, &config.actor
+    );
+    if !config.auth_token.is_empty() {
+        warn!(
+            "UDP feeders on {} are only authenticated when they register, \
+             use listen mode to authenticate feeders on untrusted networks",
+            &address
+        );
+    }
+    let mut feeders: HashMap<IpAddr, Feeder> = HashMap::new();
+    let mut buf = vec![0; MAX_DATAGRAM_LEN];
+    let mut last_sweep = Instant::now();
+    while !cancel.is_cancelled() {
+        if last_sweep.elapsed() >= POLL_INTERVAL {
+            expire_feeders(&mut feeders, status, config.idle_timeout);
+            last_sweep = Instant::now();
+        }
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(e) => {
+                error!("Failed to receive on {}: {}", &address, e);
+                cancel.sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let datagram = &buf[..len];
+        let known = feeders.contains_key(&peer.ip()) || config.station_map.contains_key(&peer.ip());
+        if !known && feeders.len() >= config.max_feeders {
+            debug!(
+                "Dropping datagram from {}, already serving {} feeders",
+                peer, config.max_feeders
+            );
+            continue;
+        }
+        if let Some(station) = std::str::from_utf8(datagram)
+            .ok()
+            .and_then(|text| parse_registration(text, config))
+        {
+            info!(
+                "Feeder {} registered as station {} ({})",
+                peer, &station.name, &station.id
+            );
+            replace_feeders(&mut feeders, &station.id, peer.ip());
+            let previous = feeders.insert(peer.ip(), Feeder::new(station));
+            if let Some(previous) = previous.filter(|previous| previous.pipeline.is_some()) {
+                status.remove(&previous.station);
+            }
+            continue;
+        }
+        if !feeders.contains_key(&peer.ip()) {
+            match config.station_map.get(&peer.ip()) {
+                Some(station) => {
+                    replace_feeders(&mut feeders, &station.id, peer.ip());
+                    feeders.insert(peer.ip(), Feeder::new(station.clone()));
+                }
+                None => {
+                    debug!("Dropping datagram from unknown feeder {}", peer);
+                    continue;
+                }
+            }
+        }
+        let feeder = match feeders.get_mut(&peer.ip()) {
+            Some(feeder) => feeder,
+            None => continue,
+        };
+        feeder.last_heard = Instant::now();
+        let station = &feeder.station;
+        let pipeline = feeder.pipeline.get_or_insert_with(|| {
+            status.set(station, ConnectionState::Connected, Some(peer.to_string()));
+            Pipeline::new(queue, status, config, stats, station.clone())
+        });
+        for frame in frame::frames(config.format, Cursor::new(datagram.to_vec())) {
+            match frame {
+                Ok(frame) => pipeline.process(frame),
+                Err(e) => {
+                    debug!("Failed to read datagram from {}: {}", peer, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Parses a registration datagram: the handshake line, preceded by an `AUTH <token>` line
+/// if the binding has a token
+fn parse_registration(text: &str, config: &BindingConfig) -> Option<StationInfo> {
+    let mut lines = text.trim().lines();
+    if !config.auth_token.is_empty() && !is_authorized(lines.next()?, &config.auth_token) {
+        return None;
+    }
+    let station = parse_handshake(lines.next()?)?;
+    match lines.next() {
+        Some(_) => None,
+        None => Some(station),
+    }
+}
+
+/// Forgets the feeders of a station other than the one at `address`, which takes over
+/// feeding it. The station stays connected, so their leaving isn't reported.
+fn replace_feeders(feeders: &mut HashMap<IpAddr, Feeder>, station_id: &str, address: IpAddr) {
+    feeders.retain(|previous, feeder| {
+        let replaced = *previous != address && feeder.station.id == station_id;
+        if replaced {
+            info!(
+                "Feeder {} replaces feeder {} of station {}",
+                address, previous, station_id
+            );
+        }
+        !replaced
+    });
+}
+
+/// Forgets the feeders that haven't sent anything for the idle timeout, reporting the
+/// stations of those that had sent frames as disconnected
+fn expire_feeders(feeders: &mut HashMap<IpAddr, Feeder>, status: &StatusReporter, idle: Duration) {
+    let expired: Vec<IpAddr> = feeders
+        .iter()
+        .filter(|(_, feeder)| feeder.last_heard.elapsed() >= idle)
+        .map(|(address, _)| *address)
+        .collect();
+    for address in expired {
+        if let Some(feeder) = feeders.remove(&address) {
+            info!(
+                "Feeder {} of station {} went silent",
+                address, &feeder.station.id
+            );
+            if feeder.pipeline.is_some() {
+                status.remove(&feeder.station);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::core::CapabilityConfiguration;
+
+    fn config(auth_token: &str) -> BindingConfig {
+        let mut values = HashMap::new();
+        values.insert("AUTH_TOKEN".to_string(), auth_token.to_string());
+        BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values,
+        })
+    }
+
+    fn station(id: &str) -> StationInfo {
+        StationInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            location: None,
+        }
+    }
+
+    /// Both ends of a local TCP connection
+    fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn parses_handshakes() {
+        let station = parse_handshake("STATION station002 Hartford, CT\r").unwrap();
+        assert_eq!(station.id, "station002");
+        assert_eq!(station.name, "Hartford, CT");
+
+        let station = parse_handshake("  STATION station002  ").unwrap();
+        assert_eq!(station.id, "station002");
+        assert_eq!(station.name, "station002");
+    }
+
+    #[test]
+    fn rejects_invalid_handshakes() {
+        assert!(parse_handshake("").is_none());
+        assert!(parse_handshake("STATION").is_none());
+        assert!(parse_handshake("STATION ").is_none());
+        assert!(parse_handshake("station station002").is_none());
+        assert!(parse_handshake("HELLO station002").is_none());
+        assert!(parse_handshake("STATION ../etc Hartford").is_none());
+        assert!(parse_handshake(&format!("STATION {}", "a".repeat(65))).is_none());
+    }
+
+    #[test]
+    fn authorizes_only_the_token() {
+        assert!(is_authorized("AUTH secret", "secret"));
+        assert!(is_authorized(" AUTH  secret \r", "secret"));
+        assert!(!is_authorized("AUTH secret2", "secret"));
+        assert!(!is_authorized("AUTH secre", "secret"));
+        assert!(!is_authorized("AUTH ", "secret"));
+        assert!(!is_authorized("auth secret", "secret"));
+        assert!(!is_authorized("secret", "secret"));
+        assert!(!is_authorized("STATION station002", "secret"));
+    }
+
+    #[test]
+    fn parses_registrations() {
+        let station = parse_registration("STATION station002 Hartford\n", &config("")).unwrap();
+        assert_eq!(station.id, "station002");
+        assert_eq!(station.name, "Hartford");
+
+        let config = config("secret");
+        let station = parse_registration("AUTH secret\nSTATION station002\n", &config).unwrap();
+        assert_eq!(station.id, "station002");
+    }
+
+    #[test]
+    fn rejects_invalid_registrations() {
+        let open = config("");
+        assert!(parse_registration("", &open).is_none());
+        assert!(parse_registration("*8D4840D6202CC371C32CE0576098;\n", &open).is_none());
+        assert!(parse_registration("STATION station002\nSTATION station003\n", &open).is_none());
+
+        let secured = config("secret");
+        assert!(parse_registration("STATION station002\n", &secured).is_none());
+        assert!(parse_registration("AUTH wrong\nSTATION station002\n", &secured).is_none());
+        assert!(parse_registration("AUTH secret\n", &secured).is_none());
+        assert!(parse_registration("AUTH secret\nSTATION station002\nx\n", &secured).is_none());
+    }
+
+    #[test]
+    fn replaces_the_feeder_of_a_claimed_station() {
+        let claims = StationClaims::default();
+        let (first, mut first_feeder) = connection();
+        let (second, _second_feeder) = connection();
+
+        let first_claim = claims.claim("station002", &first).unwrap();
+        assert!(first_claim.is_current());
+        let second_claim = claims.claim("station002", &second).unwrap();
+        assert!(!first_claim.is_current());
+        assert!(second_claim.is_current());
+
+        // The replaced feeder's connection is shut down
+        let mut buf = [0; 1];
+        assert_eq!(first_feeder.read(&mut buf).unwrap(), 0);
+
+        // Only the current feeder's claim releases the station
+        drop(first_claim);
+        assert!(second_claim.is_current());
+        drop(second_claim);
+        assert!(claims.claims.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn claims_stations_independently() {
+        let claims = StationClaims::default();
+        let (first, _first_feeder) = connection();
+        let (second, _second_feeder) = connection();
+        let first_claim = claims.claim("station002", &first).unwrap();
+        let second_claim = claims.claim("station003", &second).unwrap();
+        assert!(first_claim.is_current());
+        assert!(second_claim.is_current());
+    }
+
+    #[test]
+    fn replaces_udp_feeders_of_a_station() {
+        let address = |last| IpAddr::from([10, 0, 0, last]);
+        let mut feeders = HashMap::new();
+        feeders.insert(address(5), Feeder::new(station("station002")));
+        feeders.insert(address(6), Feeder::new(station("station003")));
+
+        replace_feeders(&mut feeders, "station002", address(7));
+        assert!(!feeders.contains_key(&address(5)));
+        assert!(feeders.contains_key(&address(6)));
+
+        // A feeder doesn't replace itself
+        feeders.insert(address(7), Feeder::new(station("station002")));
+        replace_feeders(&mut feeders, "station002", address(7));
+        assert_eq!(feeders.len(), 2);
+    }
+}
@@ -0,0 +1,381 @@
+//! Decoding of Mode S frames that the `adsb` crate does not support. Bit positions below
+//! count from the most significant bit of the first byte of the frame, and ME field
+//! positions count from the start of the 56-bit ME field of an extended squitter.
+
+use crate::adsbtypes::{
+    ADSBMessage, ADSBMessagePayload, CPRFrame, EmergencyState, MessageHeader, Parity, Position,
+};
+use crate::crc;
+
+const DF_SURVEILLANCE_ALTITUDE: u8 = 4;
+const DF_SURVEILLANCE_IDENTITY: u8 = 5;
+const DF_EXTENDED_SQUITTER: u8 = 17;
+const DF_EXTENDED_SQUITTER_NON_TRANSPONDER: u8 = 18;
+const DF_COMM_B_ALTITUDE: u8 = 20;
+const DF_COMM_B_IDENTITY: u8 = 21;
+/// Offset of the ME field within an extended squitter
+const ME_OFFSET: usize = 32;
+/// Offset of the 13-bit altitude or identity field within a surveillance reply
+const AC_ID_OFFSET: usize = 19;
+const FEET_PER_METER: f64 = 3.28084;
+
+/// Decodes the frames handled here rather than by the `adsb` crate: surveillance altitude
+/// and identity replies (DF4/5/20/21), and the extended squitter (DF17/18) message types
+/// surface position (TC 5-8), GNSS altitude airborne position (TC 20-22), aircraft status
+/// (TC 28), target state and status (TC 29) and operational status (TC 31). Returns `None`
+/// for any other frame.
+pub fn decode(data: &[u8]) -> Option<ADSBMessage> {
+    if data.is_empty() {
+        return None;
+    }
+    match data[0] >> 3 {
+        DF_SURVEILLANCE_ALTITUDE | DF_SURVEILLANCE_IDENTITY if data.len() == 7 => {
+            decode_surveillance_reply(data)
+        }
+        DF_COMM_B_ALTITUDE | DF_COMM_B_IDENTITY if data.len() == 14 => {
+            decode_surveillance_reply(data)
+        }
+        DF_EXTENDED_SQUITTER | DF_EXTENDED_SQUITTER_NON_TRANSPONDER if data.len() == 14 => {
+            decode_extended_squitter(data)
+        }
+        _ => None,
+    }
+}
+
+/// Surveillance replies carry no explicit address, it is recovered from the parity field.
+/// The Comm-B data in DF20/21 replies is not decoded.
+fn decode_surveillance_reply(data: &[u8]) -> Option<ADSBMessage> {
+    let downlink_format = data[0] >> 3;
+    let field = bits(data, AC_ID_OFFSET, 13);
+    let payload = match downlink_format {
+        DF_SURVEILLANCE_ALTITUDE | DF_COMM_B_ALTITUDE => ADSBMessagePayload::SurveillanceAltitude {
+            altitude: altitude(field)?,
+        },
+        _ => ADSBMessagePayload::SurveillanceIdentity {
+            squawk: squawk(field),
+        },
+    };
+    // The low bits of the first byte hold the flight status rather than a capability
+    Some(message(downlink_format, 0, crc::syndrome(data), 0, payload))
+}
+
+fn decode_extended_squitter(data: &[u8]) -> Option<ADSBMessage> {
+    let type_code = me(data, 0, 5) as u8;
+    let payload = match type_code {
+        5..=8 => surface_position(data),
+        20..=22 => gnss_position(data),
+        28 => aircraft_status(data),
+        29 => target_state(data),
+        31 => operational_status(data),
+        _ => None,
+    }?;
+    let icao_address = bits(data, 8, 24);
+    Some(message(
+        data[0] >> 3,
+        data[0] & 0x07,
+        icao_address,
+        type_code,
+        payload,
+    ))
+}
+
+fn message(
+    downlink_format: u8,
+    capability: u8,
+    icao_address: u32,
+    type_code: u8,
+    payload: ADSBMessagePayload,
+) -> ADSBMessage {
+    ADSBMessage {
+        station_id: "TBD".to_string(),
+        station_name: "TBD".to_string(),
+        received_at: 0,
+        mlat_timestamp: None,
+        rssi: None,
+        header: MessageHeader {
+            downlink_format,
+            capability,
+            icao_address: format!("{:06X}", icao_address),
+            type_code,
+        },
+        payload,
+        station_location: None,
+    }
+}
+
+fn surface_position(data: &[u8]) -> Option<ADSBMessagePayload> {
+    let track = if me(data, 12, 1) == 1 {
+        Some(f64::from(me(data, 13, 7)) * 360.0 / 128.0)
+    } else {
+        None
+    };
+    Some(ADSBMessagePayload::SurfacePosition {
+        ground_speed: ground_movement(me(data, 5, 7)),
+        track,
+        cpr_frame: cpr_frame(data),
+    })
+}
+
+fn gnss_position(data: &[u8]) -> Option<ADSBMessagePayload> {
+    let height = f64::from(me(data, 8, 12)) * FEET_PER_METER;
+    Some(ADSBMessagePayload::AirbornePositionGNSS {
+        altitude: height.min(f64::from(u16::MAX)) as u16,
+        cpr_frame: cpr_frame(data),
+    })
+}
+
+fn aircraft_status(data: &[u8]) -> Option<ADSBMessagePayload> {
+    // Subtype 2 carries TCAS resolution advisories, which we don't model
+    if me(data, 5, 3) != 1 {
+        return None;
+    }
+    Some(ADSBMessagePayload::AircraftStatus {
+        emergency_state: EmergencyState::from(me(data, 8, 3) as u8),
+        squawk: squawk(me(data, 11, 13)),
+    })
+}
+
+fn target_state(data: &[u8]) -> Option<ADSBMessagePayload> {
+    // Subtype 0 is the ADS-B version 1 format, which is rarely transmitted
+    if me(data, 5, 2) != 1 {
+        return None;
+    }
+    let selected_altitude = match me(data, 9, 11) {
+        0 => None,
+        v => Some(((v - 1) * 32) as u16),
+    };
+    let barometric_setting = match me(data, 20, 9) {
+        0 => None,
+        v => Some(800.0 + f64::from(v - 1) * 0.8),
+    };
+    let selected_heading = if me(data, 29, 1) == 1 {
+        Some(f64::from(me(data, 30, 9)) * 180.0 / 256.0)
+    } else {
+        None
+    };
+    Some(ADSBMessagePayload::TargetState {
+        selected_altitude,
+        barometric_setting,
+        selected_heading,
+    })
+}
+
+fn operational_status(data: &[u8]) -> Option<ADSBMessagePayload> {
+    let subtype = me(data, 5, 3);
+    if subtype > 1 {
+        return None;
+    }
+    Some(ADSBMessagePayload::OperationalStatus {
+        version: me(data, 40, 3) as u8,
+        on_ground: subtype == 1,
+        nac_p: me(data, 44, 4) as u8,
+        sil: me(data, 50, 2) as u8,
+    })
+}
+
+/// The CPR format bit and encoded coordinates share the same position in all
+/// position messages
+fn cpr_frame(data: &[u8]) -> CPRFrame {
+    CPRFrame {
+        position: Position {
+            latitude: f64::from(me(data, 22, 17)),
+            longitude: f64::from(me(data, 39, 17)),
+        },
+        parity: if me(data, 21, 1) == 1 {
+            Parity::Odd
+        } else {
+            Parity::Even
+        },
+    }
+}
+
+/// Decodes the non-linear surface movement field into ground speed in knots
+fn ground_movement(movement: u32) -> Option<f64> {
+    let m = f64::from(movement);
+    match movement {
+        1 => Some(0.0),
+        2..=8 => Some(0.125 + (m - 2.0) * 0.125),
+        9..=12 => Some(1.0 + (m - 9.0) * 0.25),
+        13..=38 => Some(2.0 + (m - 13.0) * 0.5),
+        39..=93 => Some(15.0 + (m - 39.0)),
+        94..=108 => Some(70.0 + (m - 94.0) * 2.0),
+        109..=123 => Some(100.0 + (m - 109.0) * 5.0),
+        124 => Some(175.0),
+        _ => None,
+    }
+}
+
+/// Decodes a 13-bit altitude code (C1 A1 C2 A2 C4 A4 M B1 Q B2 D2 B4 D4) into feet.
+/// Altitudes below sea level are clamped to zero.
+fn altitude(code: u32) -> Option<u16> {
+    let bit = |n: u32| (code >> (12 - n)) & 1;
+    // All zeros means the altitude is unavailable, and metric altitudes are not used in practice
+    if code == 0 || bit(6) == 1 {
+        return None;
+    }
+    let feet = if bit(8) == 1 {
+        // 25 ft increments: the remaining 11 bits form a binary number
+        let n = ((code & 0x1F80) >> 2) | ((code & 0x0020) >> 1) | (code & 0x000F);
+        n as i32 * 25 - 1000
+    } else {
+        gillham(code)? * 100
+    };
+    Some(feet.max(0).min(i32::from(u16::MAX)) as u16)
+}
+
+/// Decodes a Gillham (Gray code) altitude, in 100 ft increments
+fn gillham(code: u32) -> Option<i32> {
+    let bit = |n: u32| (code >> (12 - n)) & 1 == 1;
+    let (c1, a1, c2, a2, c4, a4, b1, d1, b2, d2, b4, d4) = (
+        bit(0),
+        bit(1),
+        bit(2),
+        bit(3),
+        bit(4),
+        bit(5),
+        bit(7),
+        bit(8),
+        bit(9),
+        bit(10),
+        bit(11),
+        bit(12),
+    );
+    // D1 is never set and at least one C bit always is
+    if d1 || !(c1 || c2 || c4) {
+        return None;
+    }
+
+    let mut hundreds = 0;
+    for (set, mask) in [(c1, 0x7), (c2, 0x3), (c4, 0x1)].iter() {
+        if *set {
+            hundreds ^= mask;
+        }
+    }
+    if hundreds & 5 == 5 {
+        hundreds ^= 2;
+    }
+    if hundreds > 5 {
+        return None;
+    }
+
+    let mut five_hundreds = 0;
+    let gray = [
+        (d2, 0xFF),
+        (d4, 0x7F),
+        (a1, 0x3F),
+        (a2, 0x1F),
+        (a4, 0x0F),
+        (b1, 0x07),
+        (b2, 0x03),
+        (b4, 0x01),
+    ];
+    for (set, mask) in gray.iter() {
+        if *set {
+            five_hundreds ^= mask;
+        }
+    }
+    if five_hundreds & 1 == 1 {
+        hundreds = 6 - hundreds;
+    }
+    Some(five_hundreds * 5 + hundreds - 13)
+}
+
+/// Decodes a 13-bit identity field (C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4) into a squawk code
+fn squawk(id: u32) -> String {
+    let bit = |n: u32| (id >> (12 - n)) & 1;
+    let a = bit(5) << 2 | bit(3) << 1 | bit(1);
+    let b = bit(11) << 2 | bit(9) << 1 | bit(7);
+    let c = bit(4) << 2 | bit(2) << 1 | bit(0);
+    let d = bit(12) << 2 | bit(10) << 1 | bit(8);
+    format!("{}{}{}{}", a, b, c, d)
+}
+
+/// Extracts `len` bits of the ME field starting at `start`
+fn me(data: &[u8], start: usize, len: usize) -> u32 {
+    bits(data, ME_OFFSET + start, len)
+}
+
+/// Extracts `len` bits of the frame starting at `start`
+fn bits(data: &[u8], start: usize, len: usize) -> u32 {
+    (start..start + len).fold(0, |acc, i| {
+        (acc << 1) | u32::from((data[i / 8] >> (7 - i % 8)) & 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(frame: &str) -> Vec<u8> {
+        (0..frame.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&frame[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_gillham_altitude() {
+        assert_eq!(altitude(0x06A2), Some(10_000));
+        assert_eq!(altitude(0x0520), Some(2_600));
+        // D1 set
+        assert_eq!(gillham(0x0010), None);
+        // No C bit set
+        assert_eq!(gillham(0x0002), None);
+    }
+
+    #[test]
+    fn decodes_altitude_in_25_foot_increments() {
+        assert_eq!(altitude(0x1838), Some(38_000));
+        assert_eq!(altitude(0x0010), Some(0));
+    }
+
+    #[test]
+    fn rejects_unavailable_and_metric_altitude() {
+        assert_eq!(altitude(0), None);
+        assert_eq!(altitude(0x1878), None);
+    }
+
+    #[test]
+    fn decodes_squawk() {
+        assert_eq!(squawk(0x0AAA), "7700");
+        assert_eq!(squawk(0x0808), "1200");
+        assert_eq!(squawk(0x092D), "1346");
+        assert_eq!(squawk(0), "0000");
+    }
+
+    #[test]
+    fn decodes_surveillance_altitude_reply() {
+        let message = decode(&hex("20001838CA3804")).unwrap();
+        assert_eq!(message.header.downlink_format, 4);
+        assert_eq!(
+            message.payload,
+            ADSBMessagePayload::SurveillanceAltitude { altitude: 38_000 }
+        );
+    }
+
+    #[test]
+    fn decodes_surface_position() {
+        let message = decode(&hex("8C4841753A9A153237AEF0F275BE")).unwrap();
+        assert_eq!(message.header.icao_address, "484175");
+        assert_eq!(message.header.type_code, 7);
+        assert_eq!(
+            message.payload,
+            ADSBMessagePayload::SurfacePosition {
+                ground_speed: Some(17.0),
+                track: Some(92.8125),
+                cpr_frame: CPRFrame {
+                    position: Position {
+                        latitude: 39195.0,
+                        longitude: 110_320.0,
+                    },
+                    parity: Parity::Odd,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_airborne_position_to_adsb_crate() {
+        assert_eq!(decode(&hex("8D40621D58C382D690C8AC2863A7")), None);
+    }
+}
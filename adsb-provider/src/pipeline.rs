@@ -0,0 +1,199 @@
+use crate::adsbtypes::ADSBMessage;
+use crate::config::{BindingConfig, StationInfo};
+use crate::crc::{self, Check};
+use crate::dispatch::DispatchQueue;
+use crate::frame::{Frame, ModeSFrame};
+use crate::icaofilter::{self, IcaoFilter};
+use crate::recorder::Recorder;
+use crate::stats::{Counters, Statistics};
+use crate::status::{Activity, StatusReporter};
+use crate::throttle::Throttle;
+use crate::{modes, now_millis, sbs};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Handling of the frames received from a single station, whatever their source: recording,
+/// parity checking, address filtering, decoding, throttling and queueing for delivery to the
+/// bound actor
+pub struct Pipeline<'a> {
+    queue: &'a DispatchQueue,
+    config: &'a BindingConfig,
+    stats: Arc<Counters>,
+    station: StationInfo,
+    activity: Arc<Activity>,
+    filter: IcaoFilter,
+    throttle: Throttle,
+    recorder: Option<Recorder>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(
+        queue: &'a DispatchQueue,
+        status: &StatusReporter,
+        config: &'a BindingConfig,
+        stats: &Statistics,
+        station: StationInfo,
+    ) -> Self {
+        Pipeline {
+            queue,
+            activity: status.activity(&station),
+            config,
+            stats: stats.station(&station),
+            filter: IcaoFilter::new(config.icao_filter_ttl),
+            throttle: Throttle::new(config.throttle),
+            recorder: Recorder::from_config(config, &station),
+            station,
+        }
+    }
+
+    /// Decodes a frame and queues the resulting message for the actor
+    pub fn process(&mut self, frame: Frame) {
+        let now = now_millis();
+        self.stats.frame_received();
+        self.activity.frame_received(now);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(now, &frame);
+        }
+        let message = match frame {
+            Frame::ModeS(frame) => self.decode_frame(frame, now),
+            Frame::Sbs(line) => {
+                let message = sbs::parse_line(&line);
+                if message.is_none() {
+                    self.stats.frame_unsupported();
+                }
+                message
+            }
+        };
+        if let Some(message) = message {
+            if !self.throttle.allows(&message, now) {
+                self.stats.message_throttled();
+                return;
+            }
+            self.queue.push(ADSBMessage {
+                station_id: self.station.id.to_string(),
+                station_name: self.station.name.to_string(),
+                station_location: self.station.location.clone(),
+                received_at: now,
+                ..message
+            });
+        }
+    }
+
+    /// Checks the parity of a raw Mode S frame and decodes it, returning `None` for frames
+    /// that are corrupt, that come from an address we haven't seen announced or that we
+    /// don't support
+    fn decode_frame(&mut self, mut frame: ModeSFrame, now: u64) -> Option<ADSBMessage> {
+        let check = crc::check(&mut frame.data, self.config.correct_errors);
+        match check {
+            Check::Valid => self
+                .filter
+                .announce(icaofilter::announced_address(&frame.data), now),
+            Check::Corrected => {
+                self.stats.frame_corrected();
+                self.filter
+                    .announce(icaofilter::announced_address(&frame.data), now);
+            }
+            Check::Unverifiable => {}
+            Check::Failed => {
+                trace!("Discarding frame with bad parity: {:02X?}", frame.data);
+                self.stats.crc_failed();
+                return None;
+            }
+        }
+        let message = match modes::decode(&frame.data) {
+            Some(message) => message,
+            None => match adsb::parse_binary(&frame.data)
+                .ok()
+                .and_then(|(message, _)| ADSBMessage::try_from(message).ok())
+            {
+                Some(message) => message,
+                None => {
+                    debug!("Unsupported frame: {:02X?}", frame.data);
+                    self.stats.frame_unsupported();
+                    return None;
+                }
+            },
+        };
+        if check == Check::Unverifiable && !self.filter.accepts(crc::syndrome(&frame.data), now) {
+            trace!("Discarding reply from unknown address: {:02X?}", frame.data);
+            self.stats.address_rejected();
+            return None;
+        }
+        Some(ADSBMessage {
+            mlat_timestamp: frame.mlat_timestamp,
+            rssi: frame.rssi,
+            ..message
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::core::CapabilityConfiguration;
+    use std::collections::HashMap;
+
+    /// Appends the parity field to a frame, overlaid with `address` (zero for extended
+    /// squitters and all-call replies)
+    fn with_parity(mut data: Vec<u8>, address: u32) -> Frame {
+        let parity = crc::checksum(&data) ^ address;
+        data.extend_from_slice(&[(parity >> 16) as u8, (parity >> 8) as u8, parity as u8]);
+        Frame::ModeS(ModeSFrame {
+            data,
+            mlat_timestamp: None,
+            rssi: None,
+        })
+    }
+
+    /// All-call reply announcing an address
+    fn all_call(address: u32) -> Frame {
+        with_parity(
+            vec![
+                0x5D,
+                (address >> 16) as u8,
+                (address >> 8) as u8,
+                address as u8,
+            ],
+            0,
+        )
+    }
+
+    /// Identity reply from an address only known through its parity
+    fn identity(address: u32) -> Frame {
+        with_parity(vec![0x28, 0x00, 0x1F, 0x80], address)
+    }
+
+    fn unknown_addresses(stats: &Statistics) -> u64 {
+        stats.snapshot().stations[0].unknown_address
+    }
+
+    #[test]
+    fn accepts_replies_only_from_announced_addresses() {
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: HashMap::new(),
+        });
+        let stats = Arc::new(Statistics::new());
+        let queue = DispatchQueue::new(&config, stats.clone());
+        let status = StatusReporter::new(Arc::new(DispatchQueue::new(&config, stats.clone())));
+        let station = StationInfo {
+            id: "station001".to_string(),
+            name: "Station".to_string(),
+            location: None,
+        };
+        let mut pipeline = Pipeline::new(&queue, &status, &config, &stats, station);
+
+        pipeline.process(identity(0x4840D6));
+        assert!(queue.take_messages().is_empty());
+        assert_eq!(unknown_addresses(&stats), 1);
+
+        pipeline.process(all_call(0x4840D6));
+        pipeline.process(identity(0x4840D6));
+        pipeline.process(identity(0x485020));
+        let messages = queue.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].header.icao_address, "4840D6");
+        assert_eq!(messages[0].header.downlink_format, 5);
+        assert_eq!(unknown_addresses(&stats), 2);
+    }
+}
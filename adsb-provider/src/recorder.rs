@@ -0,0 +1,322 @@
+//! Recording of received frames to disk, and reading those recordings back.
+//!
+//! Recordings are text files with one frame per line, in the form
+//! `<received_at>,<station_id>,<rssi>,<frame>`, where `received_at` is the wall clock receive
+//! time in milliseconds since the UNIX epoch, `rssi` is the signal level in dBFS or empty if
+//! the feed doesn't supply one and `frame` is an AVR line for Mode S frames or the original
+//! BaseStation record.
+
+use crate::avr;
+use crate::config::{BindingConfig, StationInfo};
+use crate::frame::Frame;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Read, Write};
+use std::path::PathBuf;
+
+const EXTENSION: &str = "rec";
+/// How often buffered frames are written out, so that a recording is never far behind the
+/// feed
+const FLUSH_INTERVAL_MS: u64 = 1_000;
+
+/// Writes frames to size-capped files in a directory, starting a new file whenever the
+/// current one is full and deleting the oldest files beyond the configured count
+pub struct Recorder {
+    dir: PathBuf,
+    station_id: String,
+    max_bytes: u64,
+    max_files: usize,
+    current: Option<BufWriter<File>>,
+    written: u64,
+    /// Wall clock time the current file was last flushed, in milliseconds since the UNIX
+    /// epoch
+    flushed_at: u64,
+}
+
+impl Recorder {
+    /// Creates a recorder for the given station if the binding asks for one
+    pub fn from_config(config: &BindingConfig, station: &StationInfo) -> Option<Self> {
+        if config.record_dir.is_empty() {
+            return None;
+        }
+        info!(
+            "Recording frames for station {} to {}",
+            &station.id, &config.record_dir
+        );
+        Some(Recorder {
+            dir: PathBuf::from(&config.record_dir),
+            station_id: station.id.to_string(),
+            max_bytes: config.record_max_bytes,
+            max_files: config.record_max_files.max(1),
+            current: None,
+            written: 0,
+            flushed_at: 0,
+        })
+    }
+
+    /// Appends a frame to the current recording. Failures are logged and the frame is
+    /// dropped, recording resumes with a fresh file on the next frame.
+    pub fn record(&mut self, received_at: u64, frame: &Frame) {
+        let rssi = match frame {
+            Frame::ModeS(frame) => frame.rssi.map(|rssi| format!("{:.1}", rssi)),
+            Frame::Sbs(_) => None,
+        };
+        let line = format!(
+            "{},{},{},{}\n",
+            received_at,
+            &self.station_id,
+            rssi.unwrap_or_default(),
+            frame
+        );
+        if self.current.is_none() || self.written + line.len() as u64 > self.max_bytes {
+            if let Err(e) = self.rotate(received_at) {
+                error!("Failed to start recording in {}: {}", self.dir.display(), e);
+                return;
+            }
+        }
+        if let Some(current) = self.current.as_mut() {
+            let flush = received_at.saturating_sub(self.flushed_at) >= FLUSH_INTERVAL_MS;
+            let result = current.write_all(line.as_bytes()).and_then(|_| {
+                if flush {
+                    current.flush()
+                } else {
+                    Ok(())
+                }
+            });
+            match result {
+                Ok(_) => {
+                    self.written += line.len() as u64;
+                    if flush {
+                        self.flushed_at = received_at;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to write recording: {}", e);
+                    self.current = None;
+                }
+            }
+        }
+    }
+
+    fn rotate(&mut self, now: u64) -> io::Result<()> {
+        if let Some(mut current) = self.current.take() {
+            current.flush()?;
+        }
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!(
+            "{}-{}.{}",
+            file_safe(&self.station_id),
+            now,
+            EXTENSION
+        ));
+        self.current = Some(BufWriter::new(File::create(&path)?));
+        self.written = 0;
+        self.flushed_at = now;
+        debug!("Recording to {}", path.display());
+        self.prune()
+    }
+
+    /// Deletes this station's oldest recordings beyond `max_files`. File names embed the
+    /// creation time, so they sort oldest first.
+    fn prune(&self) -> io::Result<()> {
+        let station_id = file_safe(&self.station_id);
+        let mut recordings: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| is_recording_of(name, &station_id))
+            })
+            .collect();
+        recordings.sort();
+        let excess = recordings.len().saturating_sub(self.max_files);
+        for path in recordings.iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces every character of a station id that isn't an ASCII letter, digit, underscore or
+/// hyphen, so that no id can name a file outside the recording directory
+fn file_safe(station_id: &str) -> String {
+    station_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Whether a file name is exactly `<station_id>-<timestamp>.rec`, so that pruning one
+/// station doesn't touch the recordings of another whose id merely starts the same way
+fn is_recording_of(name: &str, station_id: &str) -> bool {
+    let timestamp = name
+        .strip_prefix(station_id)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.strip_suffix(EXTENSION))
+        .and_then(|rest| rest.strip_suffix('.'));
+    match timestamp {
+        Some(timestamp) => !timestamp.is_empty() && timestamp.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// A frame read back from a recording
+pub struct Recorded {
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub received_at: u64,
+    pub frame: Frame,
+}
+
+/// Reads the frames of a recording. Malformed lines are skipped.
+pub struct RecordingReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(source: R) -> Self {
+        RecordingReader {
+            lines: BufReader::new(source).lines(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = io::Result<Recorded>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Skipping undecodable line: {}", e);
+                    continue;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            match parse_line(&line) {
+                Some(recorded) => return Some(Ok(recorded)),
+                None => warn!("Skipping malformed recording line: {}", line),
+            }
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<Recorded> {
+    let mut fields = line.trim().splitn(3, ',');
+    let received_at = fields.next()?.parse().ok()?;
+    let _station_id = fields.next()?;
+    let mut fields = fields.next()?.splitn(2, ',');
+    let rssi = match fields.next()? {
+        "" => None,
+        rssi => Some(rssi.parse().ok()?),
+    };
+    let frame = fields.next()?;
+    let frame = if frame.starts_with("MSG,") {
+        Frame::Sbs(frame.to_string())
+    } else {
+        let mut frame = avr::parse_line(frame)?;
+        frame.rssi = rssi;
+        Frame::ModeS(frame)
+    };
+    Some(Recorded { received_at, frame })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::ModeSFrame;
+    use codec::core::CapabilityConfiguration;
+
+    const LINE: &str = "1600000000000,station001,-12.3,@0123456789AB8D4840D6202CC371C32CE0576098;";
+
+    fn mode_s(frame: &Frame) -> &ModeSFrame {
+        match frame {
+            Frame::ModeS(frame) => frame,
+            Frame::Sbs(line) => panic!("Unexpected SBS record {}", line),
+        }
+    }
+
+    #[test]
+    fn parses_lines_with_and_without_signal_level() {
+        let recorded = parse_line(LINE).unwrap();
+        assert_eq!(recorded.received_at, 1_600_000_000_000);
+        assert_eq!(mode_s(&recorded.frame).rssi, Some(-12.3));
+        assert_eq!(
+            mode_s(&recorded.frame).mlat_timestamp,
+            Some(0x0123_4567_89AB)
+        );
+        let recorded = parse_line("1600000000000,station001,,*8D4840D6202CC371C32CE0576098;");
+        assert_eq!(mode_s(&recorded.unwrap().frame).rssi, None);
+    }
+
+    #[test]
+    fn parses_sbs_records() {
+        let record = "MSG,1,1,1,4840D6,1,,,,,KLM1023,,,,,,,,0,0,0,0";
+        let line = format!("1600000000000,station001,,{}", record);
+        assert_eq!(
+            parse_line(&line).unwrap().frame,
+            Frame::Sbs(record.to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("station001,*8D4840D6202CC371C32CE0576098;").is_none());
+        assert!(
+            parse_line("1600000000000,station001,loud,*8D4840D6202CC371C32CE0576098;").is_none()
+        );
+        assert!(parse_line("1600000000000,station001,,*8D4840D6;").is_none());
+        // Lines without the signal level field
+        assert!(parse_line("1600000000000,station001,*8D4840D6202CC371C32CE0576098;").is_none());
+    }
+
+    #[test]
+    fn matches_only_recordings_of_station() {
+        assert!(is_recording_of("station1-1600000000000.rec", "station1"));
+        assert!(!is_recording_of("station10-1600000000000.rec", "station1"));
+        assert!(!is_recording_of("station1-a-1600000000000.rec", "station1"));
+        assert!(!is_recording_of("station1-.rec", "station1"));
+        assert!(!is_recording_of("station1-1600000000000.txt", "station1"));
+    }
+
+    #[test]
+    fn file_names_stay_in_directory() {
+        assert_eq!(file_safe("../etc/passwd"), "___etc_passwd");
+        assert_eq!(file_safe("station_1-a"), "station_1-a");
+    }
+
+    #[test]
+    fn reads_back_recorded_frames() {
+        let dir = std::env::temp_dir().join(format!("adsb-recorder-{}", std::process::id()));
+        let values = [("RECORD_DIR", dir.to_str().unwrap())];
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+        let mut recorder = Recorder::from_config(&config, &config.station).unwrap();
+        let frame = parse_line(LINE).unwrap().frame;
+        let sbs = Frame::Sbs("MSG,1,1,1,4840D6,1,,,,,KLM1023,,,,,,,,0,0,0,0".to_string());
+        recorder.record(1_600_000_000_000, &frame);
+        recorder.record(1_600_000_000_100, &sbs);
+        drop(recorder);
+
+        let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let recorded: Vec<Recorded> = RecordingReader::new(File::open(&path).unwrap())
+            .collect::<io::Result<Vec<Recorded>>>()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].received_at, 1_600_000_000_000);
+        assert_eq!(recorded[0].frame, frame);
+        assert_eq!(recorded[1].frame, sbs);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::adsbtypes::ConnectionState;
+use crate::binding::CancelToken;
+use crate::config::BindingConfig;
+use crate::dispatch::DispatchQueue;
+use crate::frame::{self, Format, Frame};
+use crate::pipeline::Pipeline;
+use crate::recorder::RecordingReader;
+use crate::stats::Statistics;
+use crate::status::StatusReporter;
+use std::fs::File;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Receiver timestamps embedded in Beast and AVR frames: 48 bit counters of a 12MHz clock
+const RECEIVER_CLOCK: Clock = Clock {
+    ticks_per_second: 12_000_000,
+    mask: 0xFFFF_FFFF_FFFF,
+};
+/// Receive times in recordings: milliseconds since the UNIX epoch
+const RECORDING_CLOCK: Clock = Clock {
+    ticks_per_second: 1000,
+    mask: u64::MAX,
+};
+/// Gaps between consecutive timestamps longer than this are treated as a receiver restart or
+/// a break in the recording rather than waited out
+const MAX_GAP_SECONDS: u64 = 600;
+
+type Timestamped = io::Result<(Option<u64>, Frame)>;
+
+/// Replays the configured capture file through the same decoding and delivery path as a
+/// live feed. Frames are paced by their timestamps, scaled by the replay speed: recordings
+/// use the time each frame was received, other captures use the receiver timestamps
+/// embedded in the frames. Frames without a timestamp are delivered immediately.
+pub fn replay_file(
+    queue: &DispatchQueue,
+    status: &StatusReporter,
+    config: &BindingConfig,
+    cancel: &CancelToken,
+    stats: &Statistics,
+) {
+    let file = match File::open(&config.file) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open capture file '{}': {}", &config.file, e);
+            return;
+        }
+    };
+    info!(
+        "Replaying {} for station {}",
+        &config.file, &config.station.id
+    );
+    let (frames, clock): (Box<dyn Iterator<Item = Timestamped>>, Clock) = match config.format {
+        Format::Recording => (
+            Box::new(
+                RecordingReader::new(file)
+                    .map(|r| r.map(|recorded| (Some(recorded.received_at), recorded.frame))),
+            ),
+            RECORDING_CLOCK,
+        ),
+        format => (
+            Box::new(frame::frames(format, file).map(|f| f.map(|f| (timestamp(&f), f)))),
+            RECEIVER_CLOCK,
+        ),
+    };
+    let mut pipeline = Pipeline::new(queue, status, config, stats, config.station.clone());
+    let mut pacer = Pacer::new(config.replay_speed, clock);
+    status.set(
+        &config.station,
+        ConnectionState::Replaying,
+        Some(config.file.to_string()),
+    );
+    let mut count = 0;
+    for frame in frames {
+        let (timestamp, frame) = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                error!("Failed to read from {}: {}", &config.file, e);
+                break;
+            }
+        };
+        if let Some(delay) = pacer.delay(timestamp) {
+            if !cancel.sleep(delay) {
+                return;
+            }
+        }
+        if cancel.is_cancelled() {
+            return;
+        }
+        count += 1;
+        pipeline.process(frame);
+    }
+    info!("Finished replaying {} after {} frames", &config.file, count);
+}
+
+fn timestamp(frame: &Frame) -> Option<u64> {
+    match frame {
+        Frame::ModeS(frame) => frame.mlat_timestamp,
+        Frame::Sbs(_) => None,
+    }
+}
+
+/// Resolution and width of the timestamps used to pace a replay
+#[derive(Clone, Copy)]
+struct Clock {
+    ticks_per_second: u64,
+    /// Timestamps wrap around beyond this mask
+    mask: u64,
+}
+
+/// Maps timestamps onto the wall clock, relative to the first timestamp seen
+struct Pacer {
+    speed: f64,
+    clock: Clock,
+    start: Instant,
+    last: Option<u64>,
+    /// Ticks between the first and the last timestamp
+    elapsed: u64,
+}
+
+impl Pacer {
+    fn new(speed: f64, clock: Clock) -> Self {
+        Pacer {
+            speed,
+            clock,
+            start: Instant::now(),
+            last: None,
+            elapsed: 0,
+        }
+    }
+
+    /// How long to wait before delivering a frame with the given timestamp
+    fn delay(&mut self, timestamp: Option<u64>) -> Option<Duration> {
+        let timestamp = timestamp.filter(|_| self.speed > 0.0)?;
+        let mask = self.clock.mask;
+        let gap = self.last.map(|last| timestamp.wrapping_sub(last) & mask);
+        self.last = Some(timestamp);
+        match gap {
+            Some(gap) if gap <= MAX_GAP_SECONDS * self.clock.ticks_per_second => {
+                self.elapsed += gap;
+                let seconds = self.elapsed as f64 / self.clock.ticks_per_second as f64 / self.speed;
+                (self.start + Duration::from_secs_f64(seconds))
+                    .checked_duration_since(Instant::now())
+            }
+            _ => {
+                self.start = Instant::now();
+                self.elapsed = 0;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsbtypes::ADSBMessagePayload;
+    use codec::core::CapabilityConfiguration;
+    use std::sync::Arc;
+
+    #[test]
+    fn replays_recording_through_pipeline() {
+        let values = [
+            ("SOURCE", "file"),
+            ("FORMAT", "recording"),
+            (
+                "FILE",
+                concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/replay.rec"),
+            ),
+            ("REPLAY_SPEED", "0"),
+        ];
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+        let stats = Arc::new(Statistics::new());
+        let queue = Arc::new(DispatchQueue::new(&config, stats.clone()));
+        let status = StatusReporter::new(queue.clone());
+        replay_file(&queue, &status, &config, &CancelToken::new(), &stats);
+
+        // The corrupt frame and the malformed line are left out
+        let messages = queue.take_messages();
+        assert_eq!(messages.len(), 6);
+        assert!(messages.iter().all(|m| m.station_id == "station001"));
+        assert_eq!(messages[0].mlat_timestamp, Some(0x0123_4567_89AB));
+        assert_eq!(messages[0].rssi, Some(-12.3));
+        assert_eq!(messages[1].rssi, None);
+        assert_eq!(messages[2].rssi, Some(-15.0));
+        match &messages[0].payload {
+            ADSBMessagePayload::AircraftIdentification { callsign, .. } => {
+                assert_eq!(callsign, "KLM1023")
+            }
+            payload => panic!("Unexpected payload {:?}", payload),
+        }
+        assert!(matches!(
+            messages[4].payload,
+            ADSBMessagePayload::SurfacePosition { .. }
+        ));
+        assert!(matches!(
+            messages[5].payload,
+            ADSBMessagePayload::AircraftIdentification { .. }
+        ));
+
+        let stations = stats.snapshot().stations;
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].frames_received, 7);
+        assert_eq!(stations[0].crc_failures, 1);
+    }
+
+    #[test]
+    fn paces_by_timestamp_gaps() {
+        let mut pacer = Pacer::new(1.0, RECORDING_CLOCK);
+        assert_eq!(pacer.delay(Some(1_000)), None);
+        let delay = pacer.delay(Some(1_500)).unwrap();
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+        // A gap beyond the limit starts over rather than waiting
+        assert_eq!(pacer.delay(Some(1_500 + MAX_GAP_SECONDS * 1000 + 1)), None);
+        assert_eq!(pacer.delay(None), None);
+    }
+
+    #[test]
+    fn paces_across_receiver_clock_wrap() {
+        let mut pacer = Pacer::new(4.0, RECEIVER_CLOCK);
+        assert_eq!(pacer.delay(Some(RECEIVER_CLOCK.mask)), None);
+        // One second later, a quarter of a second at four times the speed
+        let delay = pacer.delay(Some(12_000_000 - 1)).unwrap();
+        assert!(delay > Duration::from_millis(200) && delay <= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn zero_speed_never_waits() {
+        let mut pacer = Pacer::new(0.0, RECORDING_CLOCK);
+        assert_eq!(pacer.delay(Some(1_000)), None);
+        assert_eq!(pacer.delay(Some(60_000)), None);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::adsbtypes::{ADSBMessage, ADSBMessagePayload, MessageHeader, Position};
+use std::io::{self, BufRead, BufReader, Lines, Read};
+
+const FIELD_TRANSMISSION_TYPE: usize = 1;
+const FIELD_HEX_IDENT: usize = 4;
+const FIELD_CALLSIGN: usize = 10;
+const FIELD_ALTITUDE: usize = 11;
+const FIELD_GROUND_SPEED: usize = 12;
+const FIELD_TRACK: usize = 13;
+const FIELD_LATITUDE: usize = 14;
+const FIELD_LONGITUDE: usize = 15;
+const FIELD_VERTICAL_RATE: usize = 16;
+const FIELD_SQUAWK: usize = 17;
+const FIELD_IS_ON_GROUND: usize = 21;
+
+/// Reads records from a BaseStation (SBS-1) feed, `dump1090` port 30003. These records
+/// have already been decoded by the upstream, so `parse_line` maps them directly onto
+/// `ADSBMessage`s. Lines other than `MSG` records are skipped.
+pub struct SbsReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+}
+
+impl<R: Read> SbsReader<R> {
+    pub fn new(source: R) -> Self {
+        SbsReader {
+            lines: BufReader::new(source).lines(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for SbsReader<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Skipping undecodable line: {}", e);
+                    continue;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            let line = line.trim();
+            if line.starts_with("MSG,") {
+                return Some(Ok(line.to_string()));
+            }
+        }
+    }
+}
+
+/// Parses a single `MSG` record, returning `None` for records without the fields required
+/// by their transmission type. Transmission types map onto payloads as follows:
+///
+/// * 1 - aircraft identification
+/// * 2, 3 - surface and airborne position (already decoded), with the altitude left out if
+///   the record doesn't carry one
+/// * 4 - airborne velocity
+/// * 5, 7 - surveillance and air-to-air altitude
+/// * 6 - surveillance identity (squawk)
+///
+/// Type 8 (all-call reply) carries nothing beyond the ICAO address and is skipped.
+pub fn parse_line(line: &str) -> Option<ADSBMessage> {
+    let fields: Vec<&str> = line.trim().split(',').map(|f| f.trim()).collect();
+    if fields.len() < 11 || fields[0] != "MSG" {
+        return None;
+    }
+    let field = |i: usize| fields.get(i).cloned().filter(|f| !f.is_empty());
+
+    let (downlink_format, payload) = match field(FIELD_TRANSMISSION_TYPE)? {
+        "1" => (
+            17,
+            ADSBMessagePayload::AircraftIdentification {
+                emitter_category: 0,
+                callsign: field(FIELD_CALLSIGN)?.to_string(),
+            },
+        ),
+        "2" | "3" => (
+            17,
+            ADSBMessagePayload::DecodedPosition {
+                altitude: field(FIELD_ALTITUDE).and_then(parse_altitude),
+                position: Position {
+                    latitude: field(FIELD_LATITUDE)?.parse().ok()?,
+                    longitude: field(FIELD_LONGITUDE)?.parse().ok()?,
+                },
+                on_ground: field(FIELD_IS_ON_GROUND).map(parse_flag).unwrap_or(false),
+            },
+        ),
+        "4" => (
+            17,
+            ADSBMessagePayload::AirborneVelocity {
+                heading: field(FIELD_TRACK)?.parse().ok()?,
+                ground_speed: field(FIELD_GROUND_SPEED)?.parse().ok()?,
+                vertical_rate: field(FIELD_VERTICAL_RATE)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            },
+        ),
+        "5" => (
+            20,
+            ADSBMessagePayload::SurveillanceAltitude {
+                altitude: parse_altitude(field(FIELD_ALTITUDE)?)?,
+            },
+        ),
+        "7" => (
+            16,
+            ADSBMessagePayload::SurveillanceAltitude {
+                altitude: parse_altitude(field(FIELD_ALTITUDE)?)?,
+            },
+        ),
+        "6" => (
+            21,
+            ADSBMessagePayload::SurveillanceIdentity {
+                squawk: field(FIELD_SQUAWK).filter(|s| is_squawk(s))?.to_string(),
+            },
+        ),
+        _ => return None,
+    };
+
+    Some(ADSBMessage {
+        station_id: "TBD".to_string(),
+        station_name: "TBD".to_string(),
+        received_at: 0,
+        mlat_timestamp: None,
+        rssi: None,
+        header: MessageHeader {
+            downlink_format,
+            capability: 0,
+            icao_address: field(FIELD_HEX_IDENT)?.to_uppercase(),
+            // SBS records don't carry the ADS-B type code
+            type_code: 0,
+        },
+        payload,
+        station_location: None,
+    })
+}
+
+/// Altitudes below sea level are clamped to zero
+fn parse_altitude(value: &str) -> Option<u16> {
+    let altitude: f64 = value.parse().ok()?;
+    Some(altitude.max(0.0).min(f64::from(u16::MAX)) as u16)
+}
+
+/// Mode A codes are four octal digits
+fn is_squawk(value: &str) -> bool {
+    value.len() == 4 && value.chars().all(|c| ('0'..='7').contains(&c))
+}
+
+/// SBS flags are `-1` when set and `0` when clear
+fn parse_flag(value: &str) -> bool {
+    value == "-1" || value == "1"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMES: &str = "2020/01/01,12:00:00.000,2020/01/01,12:00:00.000";
+
+    fn record(transmission_type: u8, icao_address: &str, fields: &str) -> String {
+        format!(
+            "MSG,{},1,1,{},1,{},{}",
+            transmission_type, icao_address, TIMES, fields
+        )
+    }
+
+    #[test]
+    fn parses_identification() {
+        let message = parse_line(&record(1, "4840d6", "KLM1023,,,,,,,,0,0,0,0")).unwrap();
+        assert_eq!(message.header.icao_address, "4840D6");
+        assert_eq!(
+            message.payload,
+            ADSBMessagePayload::AircraftIdentification {
+                emitter_category: 0,
+                callsign: "KLM1023".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_decoded_position() {
+        let line = record(3, "4840D6", ",38000,,,52.25720,3.91937,,,0,0,0,-1");
+        assert_eq!(
+            parse_line(&line).unwrap().payload,
+            ADSBMessagePayload::DecodedPosition {
+                altitude: Some(38000),
+                position: Position {
+                    latitude: 52.2572,
+                    longitude: 3.91937,
+                },
+                on_ground: true,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_missing_position_altitudes() {
+        let line = record(2, "4840D6", ",,,,52.25720,3.91937,,,0,0,0,0");
+        assert_eq!(
+            parse_line(&line).unwrap().payload,
+            ADSBMessagePayload::DecodedPosition {
+                altitude: None,
+                position: Position {
+                    latitude: 52.2572,
+                    longitude: 3.91937,
+                },
+                on_ground: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_velocity() {
+        let line = record(4, "485020", ",,159,182.88,,,-832,,0,0,0,0");
+        assert_eq!(
+            parse_line(&line).unwrap().payload,
+            ADSBMessagePayload::AirborneVelocity {
+                heading: 182.88,
+                ground_speed: 159.0,
+                vertical_rate: -832,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_surveillance_replies() {
+        let message = parse_line(&record(5, "4840D6", ",-200,,,,,,,0,0,0,0")).unwrap();
+        assert_eq!(message.header.downlink_format, 20);
+        assert_eq!(
+            message.payload,
+            ADSBMessagePayload::SurveillanceAltitude { altitude: 0 }
+        );
+        let message = parse_line(&record(6, "4840D6", ",,,,,,,7700,0,1,0,0")).unwrap();
+        assert_eq!(
+            message.payload,
+            ADSBMessagePayload::SurveillanceIdentity {
+                squawk: "7700".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_squawks() {
+        for squawk in &["770", "77000", "7800", "77A0", "-770"] {
+            let fields = format!(",,,,,,,{},0,1,0,0", squawk);
+            assert_eq!(
+                parse_line(&record(6, "4840D6", &fields)),
+                None,
+                "{}",
+                squawk
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_records_missing_required_fields() {
+        assert_eq!(
+            parse_line(&record(3, "4840D6", ",38000,,,,,,,0,0,0,0")),
+            None
+        );
+        assert_eq!(parse_line(&record(8, "4840D6", ",,,,,,,,0,0,0,0")), None);
+        assert_eq!(parse_line(&record(1, "", "KLM1023,,,,,,,,0,0,0,0")), None);
+        assert_eq!(
+            parse_line("SEL,,496,2286,4CA4E5,27215,,,,,,,,,,,,,,,"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_only_msg_records() {
+        let feed = format!(
+            "STA,,5,179,400AE7,10103,{}\n{}\n\n",
+            TIMES,
+            record(1, "4840D6", "KLM1023,,,,,,,,0,0,0,0")
+        );
+        let lines: Vec<String> = SbsReader::new(feed.as_bytes())
+            .collect::<io::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("MSG,1,"));
+    }
+}
@@ -0,0 +1,302 @@
+//! Transport security and authentication for upstream feed connections. Connections can be
+//! wrapped in TLS, validated against the built-in web PKI roots, a custom certificate
+//! authority or pinned certificate fingerprints, and a pre-shared token can be presented
+//! to the upstream before any frames are read.
+
+use crate::config::BindingConfig;
+use crate::upstream::Upstream;
+use rustls::{
+    Certificate, ClientConfig, ClientSession, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, Session, StreamOwned, TLSError,
+};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use webpki::DNSNameRef;
+
+/// Stands in for the server name of upstreams addressed by IP when certificates are pinned,
+/// as pinning ignores names and TLS requires one. It is never sent, SNI is disabled for
+/// these upstreams.
+const PINNED_SERVER_NAME: &str = "pinned.invalid";
+
+/// Security applied to every connection made to a binding's upstream feeds
+#[derive(Clone)]
+pub struct Security {
+    tls: Option<Arc<ClientConfig>>,
+    /// TLS configuration for upstreams without a valid server name, only available when
+    /// certificates are pinned
+    pinned_tls: Option<Arc<ClientConfig>>,
+    server_name: String,
+    auth_token: String,
+}
+
+impl Security {
+    /// Prepares the TLS configuration of a binding, failing if its CA file can't be loaded
+    pub fn new(config: &BindingConfig) -> io::Result<Self> {
+        let tls = if config.tls {
+            Some(client_config(config)?)
+        } else {
+            None
+        };
+        let pinned_tls = tls
+            .as_ref()
+            .filter(|_| !config.tls_pins.is_empty())
+            .map(|tls| {
+                let mut pinned = tls.clone();
+                pinned.enable_sni = false;
+                Arc::new(pinned)
+            });
+        let tls = tls.map(Arc::new);
+        if tls.is_none() && !config.auth_token.is_empty() {
+            warn!(
+                "Sending the auth token for station {} without TLS",
+                &config.station.id
+            );
+        }
+        Ok(Security {
+            tls,
+            pinned_tls,
+            server_name: config.tls_server_name.to_string(),
+            auth_token: config.auth_token.to_string(),
+        })
+    }
+
+    /// Completes the TLS handshake and authentication on a newly connected socket, so that
+    /// failures surface before the connection is used. Returns the stream frames are read
+    /// from.
+    pub fn establish(
+        &self,
+        upstream: &Upstream,
+        stream: TcpStream,
+    ) -> io::Result<Box<dyn Read + Send>> {
+        match &self.tls {
+            Some(tls) => {
+                let name = if self.server_name.is_empty() {
+                    &upstream.host
+                } else {
+                    &self.server_name
+                };
+                let (tls, dns_name) = match (DNSNameRef::try_from_ascii_str(name), &self.pinned_tls)
+                {
+                    (Ok(dns_name), _) => (tls, dns_name),
+                    (Err(_), Some(pinned_tls)) => (
+                        pinned_tls,
+                        DNSNameRef::try_from_ascii_str(PINNED_SERVER_NAME).unwrap(),
+                    ),
+                    (Err(_), None) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "{} is not a valid TLS server name, set TLS_SERVER_NAME or \
+                                 TLS_PIN for upstreams addressed by IP",
+                                name
+                            ),
+                        ))
+                    }
+                };
+                let mut stream = StreamOwned::new(ClientSession::new(tls, dns_name), stream);
+                while stream.sess.is_handshaking() {
+                    stream.sess.complete_io(&mut stream.sock)?;
+                }
+                self.authenticate(&mut stream)?;
+                Ok(Box::new(stream))
+            }
+            None => {
+                let mut stream = stream;
+                self.authenticate(&mut stream)?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    /// Presents the pre-shared token as an `AUTH <token>` line, if one is configured
+    fn authenticate<W: Write>(&self, stream: &mut W) -> io::Result<()> {
+        if self.auth_token.is_empty() {
+            return Ok(());
+        }
+        stream.write_all(format!("AUTH {}\n", self.auth_token).as_bytes())?;
+        stream.flush()
+    }
+}
+
+fn client_config(config: &BindingConfig) -> io::Result<ClientConfig> {
+    let mut client = ClientConfig::new();
+    if !config.tls_pins.is_empty() {
+        client
+            .dangerous()
+            .set_certificate_verifier(Arc::new(PinnedCertificates {
+                fingerprints: config.tls_pins.clone(),
+            }));
+    } else if !config.tls_ca_file.is_empty() {
+        let mut reader = BufReader::new(File::open(&config.tls_ca_file)?);
+        let (added, _) = client
+            .root_store
+            .add_pem_file(&mut reader)
+            .map_err(|_| invalid_ca_file(&config.tls_ca_file))?;
+        if added == 0 {
+            return Err(invalid_ca_file(&config.tls_ca_file));
+        }
+    } else {
+        client
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    }
+    Ok(client)
+}
+
+fn invalid_ca_file(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("No certificates found in {}", path),
+    )
+}
+
+/// Accepts an upstream whose certificate matches one of the configured fingerprints,
+/// regardless of who issued it or which names it covers. This suits stations behind
+/// self-signed certificates.
+struct PinnedCertificates {
+    fingerprints: Vec<String>,
+}
+
+impl ServerCertVerifier for PinnedCertificates {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let certificate = presented_certs
+            .first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+        let fingerprint = fingerprint(certificate);
+        if self.fingerprints.contains(&fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(TLSError::General(format!(
+                "Certificate {} is not pinned",
+                fingerprint
+            )))
+        }
+    }
+}
+
+/// SHA-256 fingerprint of a certificate in lowercase hex
+fn fingerprint(certificate: &Certificate) -> String {
+    ring::digest::digest(&ring::digest::SHA256, &certificate.0)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::core::CapabilityConfiguration;
+    use rustls::internal::pemfile;
+    use rustls::{NoClientAuth, ServerConfig, ServerSession};
+    use std::collections::HashMap;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    const FRAME: &str = "*8D4840D6202CC371C32CE0576098;\n";
+
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn server_certificates() -> Vec<Certificate> {
+        let mut reader = BufReader::new(File::open(testdata("server.pem")).unwrap());
+        pemfile::certs(&mut reader).unwrap()
+    }
+
+    /// Serves a single TLS connection with the test certificate, which is signed by the
+    /// test CA for `localhost`. Reads the auth line first if `auth` is set, then sends one
+    /// frame. Returns the port and the auth line received, if the handshake succeeded.
+    fn serve(auth: bool) -> (u16, JoinHandle<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let mut reader = BufReader::new(File::open(testdata("server-key.pem")).unwrap());
+            let key = pemfile::pkcs8_private_keys(&mut reader).unwrap().remove(0);
+            let mut config = ServerConfig::new(NoClientAuth::new());
+            config.set_single_cert(server_certificates(), key).unwrap();
+            let (socket, _) = listener.accept().unwrap();
+            let mut stream = StreamOwned::new(ServerSession::new(&Arc::new(config)), socket);
+            let mut line = String::new();
+            if auth {
+                BufReader::new(&mut stream).read_line(&mut line).ok()?;
+            }
+            stream.write_all(FRAME.as_bytes()).ok()?;
+            stream.flush().ok()?;
+            Some(line)
+        });
+        (port, handle)
+    }
+
+    fn connect(host: &str, port: u16, values: &[(&str, &str)]) -> io::Result<String> {
+        let mut values: HashMap<String, String> = values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        values.insert("HOST".to_string(), host.to_string());
+        values.insert("PORT".to_string(), port.to_string());
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values,
+        });
+        let security = Security::new(&config)?;
+        let mut connection = config.upstreams[0].connect(Duration::from_secs(5), &security)?;
+        let mut frame = vec![0; FRAME.len()];
+        connection.reader.read_exact(&mut frame)?;
+        Ok(String::from_utf8(frame).unwrap())
+    }
+
+    #[test]
+    fn accepts_upstream_signed_by_configured_ca() {
+        let (port, server) = serve(false);
+        let ca_file = testdata("ca.pem");
+        let frame = connect("localhost", port, &[("TLS_CA_FILE", &ca_file)]).unwrap();
+        assert_eq!(frame, FRAME);
+        assert!(server.join().unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_upstream_addressed_by_ip_without_server_name() {
+        let (port, _server) = serve(false);
+        let ca_file = testdata("ca.pem");
+        let error = connect("127.0.0.1", port, &[("TLS_CA_FILE", &ca_file)]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn accepts_pinned_upstream_addressed_by_ip() {
+        let (port, server) = serve(false);
+        let pin = fingerprint(&server_certificates()[0]);
+        let frame = connect("127.0.0.1", port, &[("TLS_PIN", &pin)]).unwrap();
+        assert_eq!(frame, FRAME);
+        assert!(server.join().unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_upstream_with_certificate_not_pinned() {
+        let (port, server) = serve(false);
+        let pin = "00".repeat(32);
+        assert!(connect("localhost", port, &[("TLS_PIN", &pin)]).is_err());
+        assert!(server.join().unwrap().is_none());
+    }
+
+    #[test]
+    fn presents_auth_token_after_handshake() {
+        let (port, server) = serve(true);
+        let ca_file = testdata("ca.pem");
+        let values = [("TLS_CA_FILE", ca_file.as_str()), ("AUTH_TOKEN", "secret")];
+        let frame = connect("localhost", port, &values).unwrap();
+        assert_eq!(frame, FRAME);
+        assert_eq!(server.join().unwrap().unwrap(), "AUTH secret\n");
+    }
+}
@@ -0,0 +1,497 @@
+//! Synthetic traffic for load testing and demos. Simulated aircraft fly great-circle routes
+//! between random waypoints around the station, climbing and descending between flight
+//! levels, and broadcast extended squitters that are encoded exactly like those of a real
+//! transponder, so they take the same decoding path as frames from a live feed. Bit
+//! positions below count from the most significant bit of the 56-bit ME field.
+
+use crate::adsbtypes::{ConnectionState, Position};
+use crate::binding::CancelToken;
+use crate::config::BindingConfig;
+use crate::crc;
+use crate::dispatch::DispatchQueue;
+use crate::frame::{Frame, ModeSFrame};
+use crate::now_millis;
+use crate::pipeline::Pipeline;
+use crate::stats::Statistics;
+use crate::status::StatusReporter;
+use std::collections::HashSet;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Time between position and velocity broadcasts of each aircraft
+const TICK: Duration = Duration::from_millis(500);
+/// Aircraft identify themselves once every this many ticks
+const IDENTIFICATION_TICKS: u64 = 10;
+/// Distance from a waypoint at which the aircraft heads for the next one. This is wider
+/// than the turn diameter at the highest speed, so aircraft can't end up circling a
+/// waypoint.
+const WAYPOINT_REACHED_NM: f64 = 6.0;
+/// Standard rate turn, in degrees per second
+const TURN_RATE: f64 = 3.0;
+const MIN_ALTITUDE: f64 = 2_000.0;
+const MAX_ALTITUDE: f64 = 39_000.0;
+const MIN_SPEED: f64 = 220.0;
+const MAX_SPEED: f64 = 480.0;
+/// Rate of climb or descent in feet per minute, chosen per leg
+const MIN_VERTICAL_RATE: f64 = 1_000.0;
+const MAX_VERTICAL_RATE: f64 = 3_000.0;
+const EARTH_RADIUS_NM: f64 = 3440.065;
+/// Receiver timestamps of the simulated frames count ticks of a 12MHz clock
+const CLOCK_HZ: f64 = 12_000_000.0;
+const CLOCK_MASK: u64 = 0xFFFF_FFFF_FFFF;
+/// Airline designators used for the simulated callsigns
+const AIRLINES: [&str; 8] = ["AAL", "DAL", "UAL", "SWA", "JBU", "ASA", "FFT", "SKW"];
+
+const DF_EXTENDED_SQUITTER: u8 = 17;
+/// Capability of a level 2 transponder that is airborne
+const CAPABILITY_AIRBORNE: u8 = 5;
+const TC_IDENTIFICATION: u64 = 4;
+/// Airborne position with barometric altitude and a containment radius below 0.1 NM
+const TC_AIRBORNE_POSITION: u64 = 11;
+const TC_AIRBORNE_VELOCITY: u64 = 19;
+/// Ground speed velocity subtype, for subsonic aircraft
+const VELOCITY_GROUND_SPEED: u64 = 1;
+/// Emitter category of large aircraft
+const CATEGORY_LARGE: u64 = 3;
+/// Number of latitude zones between the equator and a pole
+const NZ: f64 = 15.0;
+/// Scale of the 17-bit encoded latitude and longitude values
+const CPR_MAX: f64 = 131_072.0;
+const CALLSIGN_CHARSET: &[u8] = b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// Feeds the binding with frames broadcast by `SIM_AIRCRAFT` simulated aircraft flying
+/// within `SIM_RADIUS_NM` of the station, until the binding is removed. The station's
+/// position must be configured.
+pub fn simulate(
+    queue: &DispatchQueue,
+    status: &StatusReporter,
+    config: &BindingConfig,
+    cancel: &CancelToken,
+    stats: &Statistics,
+) {
+    let center = match config
+        .station
+        .location
+        .as_ref()
+        .and_then(|location| location.position.clone())
+    {
+        Some(center) => center,
+        None => {
+            error!(
+                "Station {} needs STATION_LAT and STATION_LON to simulate traffic around it",
+                &config.station.id
+            );
+            return;
+        }
+    };
+    let seed = if config.sim_seed != 0 {
+        config.sim_seed
+    } else {
+        now_millis()
+    };
+    info!(
+        "Simulating {} aircraft within {}NM of station {} (seed {})",
+        config.sim_aircraft, config.sim_radius, &config.station.id, seed
+    );
+    let mut rng = Rng::new(seed);
+    let mut addresses = HashSet::new();
+    let mut aircraft: Vec<Aircraft> = (0..config.sim_aircraft)
+        .map(|_| Aircraft::new(&mut rng, &mut addresses, &center, config.sim_radius))
+        .collect();
+
+    let mut pipeline = Pipeline::new(queue, status, config, stats, config.station.clone());
+    status.set(
+        &config.station,
+        ConnectionState::Connected,
+        Some(format!("simulator ({} aircraft)", aircraft.len())),
+    );
+    let start = Instant::now();
+    let mut tick: u64 = 0;
+    loop {
+        let elapsed = start.elapsed();
+        let timestamp = (elapsed.as_secs_f64() * CLOCK_HZ) as u64 & CLOCK_MASK;
+        for (index, plane) in aircraft.iter_mut().enumerate() {
+            if tick > 0 {
+                plane.advance(&mut rng, &center, config.sim_radius, TICK.as_secs_f64());
+            }
+            // Spread identifications over the interval rather than sending them all at once
+            if (tick + index as u64) % IDENTIFICATION_TICKS == 0 {
+                pipeline.process(plane.frame(plane.identification(), timestamp));
+            }
+            pipeline.process(plane.frame(plane.position(tick % 2 == 1), timestamp));
+            pipeline.process(plane.frame(plane.velocity(), timestamp));
+        }
+        tick += 1;
+        let next = start + TICK * tick as u32;
+        if let Some(delay) = next.checked_duration_since(Instant::now()) {
+            if !cancel.sleep(delay) {
+                return;
+            }
+        }
+        if cancel.is_cancelled() {
+            return;
+        }
+    }
+}
+
+struct Aircraft {
+    icao_address: u32,
+    callsign: String,
+    position: Position,
+    /// Track over ground in degrees
+    track: f64,
+    /// Ground speed in knots
+    ground_speed: f64,
+    /// Altitude in feet
+    altitude: f64,
+    /// Vertical rate in feet per minute
+    vertical_rate: f64,
+    waypoint: Position,
+    target_altitude: f64,
+    climb_rate: f64,
+}
+
+impl Aircraft {
+    fn new(rng: &mut Rng, addresses: &mut HashSet<u32>, center: &Position, radius: f64) -> Self {
+        let icao_address = loop {
+            let address = 0xA0_0000 | rng.below(0x10_0000) as u32;
+            if addresses.insert(address) {
+                break address;
+            }
+        };
+        let callsign = format!(
+            "{}{}",
+            AIRLINES[rng.below(AIRLINES.len() as u64) as usize],
+            1 + rng.below(9999)
+        );
+        let position = random_position(rng, center, radius);
+        let mut aircraft = Aircraft {
+            icao_address,
+            callsign,
+            position,
+            track: 0.0,
+            ground_speed: 0.0,
+            altitude: rng.range(MIN_ALTITUDE, MAX_ALTITUDE),
+            vertical_rate: 0.0,
+            waypoint: center.clone(),
+            target_altitude: 0.0,
+            climb_rate: 0.0,
+        };
+        aircraft.next_leg(rng, center, radius);
+        aircraft.track = bearing(&aircraft.position, &aircraft.waypoint);
+        aircraft
+    }
+
+    /// Picks the next waypoint along with the speed and altitude to fly there
+    fn next_leg(&mut self, rng: &mut Rng, center: &Position, radius: f64) {
+        self.waypoint = random_position(rng, center, radius);
+        self.ground_speed = rng.range(MIN_SPEED, MAX_SPEED).round();
+        self.target_altitude = (rng.range(MIN_ALTITUDE, MAX_ALTITUDE) / 1000.0).round() * 1000.0;
+        self.climb_rate = rng.range(MIN_VERTICAL_RATE, MAX_VERTICAL_RATE);
+    }
+
+    /// Moves the aircraft along its great-circle route for the given number of seconds,
+    /// turning towards the waypoint at a standard rate
+    fn advance(&mut self, rng: &mut Rng, center: &Position, radius: f64, seconds: f64) {
+        if distance_nm(&self.position, &self.waypoint) < WAYPOINT_REACHED_NM {
+            self.next_leg(rng, center, radius);
+        }
+        let turn = normalize(bearing(&self.position, &self.waypoint) - self.track);
+        let max_turn = TURN_RATE * seconds;
+        self.track = (self.track + turn.max(-max_turn).min(max_turn)).rem_euclid(360.0);
+        self.position = destination(
+            &self.position,
+            self.track,
+            self.ground_speed * seconds / 3600.0,
+        );
+
+        let climb = self.target_altitude - self.altitude;
+        let max_climb = self.climb_rate * seconds / 60.0;
+        if climb.abs() <= max_climb {
+            self.altitude = self.target_altitude;
+            self.vertical_rate = 0.0;
+        } else {
+            self.vertical_rate = self.climb_rate.copysign(climb);
+            self.altitude += max_climb.copysign(climb);
+        }
+    }
+
+    /// Aircraft identification and category message (TC 4)
+    fn identification(&self) -> u64 {
+        let mut me = 0;
+        put(&mut me, 0, 5, TC_IDENTIFICATION);
+        put(&mut me, 5, 3, CATEGORY_LARGE);
+        let callsign = format!("{:8}", self.callsign);
+        for (i, c) in callsign.bytes().take(8).enumerate() {
+            let code = CALLSIGN_CHARSET
+                .iter()
+                .position(|&b| b == c && c != b'#')
+                .unwrap_or(32);
+            put(&mut me, 8 + i * 6, 6, code as u64);
+        }
+        me
+    }
+
+    /// Airborne position message (TC 11) in the given CPR format
+    fn position(&self, odd: bool) -> u64 {
+        let (lat_cpr, lon_cpr) = encode_cpr(&self.position, odd);
+        let mut me = 0;
+        put(&mut me, 0, 5, TC_AIRBORNE_POSITION);
+        put(&mut me, 8, 12, encode_altitude(self.altitude));
+        put(&mut me, 21, 1, odd as u64);
+        put(&mut me, 22, 17, lat_cpr);
+        put(&mut me, 39, 17, lon_cpr);
+        me
+    }
+
+    /// Airborne velocity message (TC 19) with ground speed and barometric vertical rate
+    fn velocity(&self) -> u64 {
+        let track = self.track.to_radians();
+        let east = self.ground_speed * track.sin();
+        let north = self.ground_speed * track.cos();
+        let mut me = 0;
+        put(&mut me, 0, 5, TC_AIRBORNE_VELOCITY);
+        put(&mut me, 5, 3, VELOCITY_GROUND_SPEED);
+        put(&mut me, 13, 1, (east < 0.0) as u64);
+        put(&mut me, 14, 10, velocity_component(east));
+        put(&mut me, 24, 1, (north < 0.0) as u64);
+        put(&mut me, 25, 10, velocity_component(north));
+        put(&mut me, 35, 1, 1);
+        put(&mut me, 36, 1, (self.vertical_rate < 0.0) as u64);
+        let rate = (self.vertical_rate.abs() / 64.0).round() as u64 + 1;
+        put(&mut me, 37, 9, rate.min(511));
+        me
+    }
+
+    /// Wraps an ME field into an extended squitter from this aircraft
+    fn frame(&self, me: u64, timestamp: u64) -> Frame {
+        let mut data = Vec::with_capacity(14);
+        data.push((DF_EXTENDED_SQUITTER << 3) | CAPABILITY_AIRBORNE);
+        data.extend_from_slice(&self.icao_address.to_be_bytes()[1..]);
+        data.extend_from_slice(&me.to_be_bytes()[1..]);
+        let parity = crc::checksum(&data);
+        data.extend_from_slice(&parity.to_be_bytes()[1..]);
+        Frame::ModeS(ModeSFrame {
+            data,
+            mlat_timestamp: Some(timestamp),
+            rssi: None,
+        })
+    }
+}
+
+/// Writes a value into the given bits of an ME field
+fn put(me: &mut u64, offset: usize, len: usize, value: u64) {
+    let mask = (1 << len) - 1;
+    *me |= (value & mask) << (56 - offset - len);
+}
+
+/// Encodes an altitude in 25 foot increments, with the Q bit set
+fn encode_altitude(altitude: f64) -> u64 {
+    let n = (((altitude + 1000.0) / 25.0).round().max(0.0) as u64).min(0x7FF);
+    ((n & 0x7F0) << 1) | 0x10 | (n & 0x0F)
+}
+
+/// Encodes a velocity component in knots, where zero means unavailable
+fn velocity_component(knots: f64) -> u64 {
+    (knots.abs().round() as u64 + 1).min(1023)
+}
+
+/// Encodes a position in the even or odd CPR format of airborne position messages
+fn encode_cpr(position: &Position, odd: bool) -> (u64, u64) {
+    let i = if odd { 1.0 } else { 0.0 };
+    let d_lat = 360.0 / (4.0 * NZ - i);
+    let yz = (CPR_MAX * modulo(position.latitude, d_lat) / d_lat + 0.5).floor();
+    let r_lat = d_lat * (yz / CPR_MAX + (position.latitude / d_lat).floor());
+    let d_lon = 360.0 / (nl(r_lat) - i).max(1.0);
+    let xz = (CPR_MAX * modulo(position.longitude, d_lon) / d_lon + 0.5).floor();
+    (yz as u64 & 0x1FFFF, xz as u64 & 0x1FFFF)
+}
+
+/// The "NL" function: number of longitude zones at the given latitude
+fn nl(latitude: f64) -> f64 {
+    let latitude = latitude.abs();
+    if latitude < 1e-9 {
+        59.0
+    } else if latitude == 87.0 {
+        2.0
+    } else if latitude > 87.0 {
+        1.0
+    } else {
+        let a = 1.0 - (PI / (2.0 * NZ)).cos();
+        let b = latitude.to_radians().cos().powi(2);
+        (2.0 * PI / (1.0 - a / b).acos()).floor()
+    }
+}
+
+fn modulo(a: f64, b: f64) -> f64 {
+    a - b * (a / b).floor()
+}
+
+/// Wraps an angle in degrees into (-180, 180]
+fn normalize(angle: f64) -> f64 {
+    let angle = angle.rem_euclid(360.0);
+    if angle > 180.0 {
+        angle - 360.0
+    } else {
+        angle
+    }
+}
+
+/// A random position within the given distance of the center, spread evenly over the area
+fn random_position(rng: &mut Rng, center: &Position, radius: f64) -> Position {
+    let distance = radius * rng.range(0.0, 1.0).sqrt();
+    destination(center, rng.range(0.0, 360.0), distance)
+}
+
+/// Initial bearing in degrees of the great circle from one position to another
+fn bearing(from: &Position, to: &Position) -> f64 {
+    let lat1 = from.latitude.to_radians();
+    let lat2 = to.latitude.to_radians();
+    let d_lon = (to.longitude - from.longitude).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Position reached after travelling the given distance along a great circle
+fn destination(from: &Position, bearing: f64, distance_nm: f64) -> Position {
+    let lat1 = from.latitude.to_radians();
+    let lon1 = from.longitude.to_radians();
+    let bearing = bearing.to_radians();
+    let angle = distance_nm / EARTH_RADIUS_NM;
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+    let lon2 = lon1
+        + (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+    Position {
+        latitude: lat2.to_degrees(),
+        longitude: normalize(lon2.to_degrees()),
+    }
+}
+
+/// Great-circle distance between two positions in nautical miles
+fn distance_nm(a: &Position, b: &Position) -> f64 {
+    let lat_a = a.latitude.to_radians();
+    let lat_b = b.latitude.to_radians();
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_NM * h.sqrt().min(1.0).asin()
+}
+
+/// A small xorshift generator, which makes a simulation reproducible from its seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// A random number below the given bound
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// A random number in the range `[low, high)`
+    fn range(&mut self, low: f64, high: f64) -> f64 {
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        low + unit * (high - low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Globally decodes an even and odd pair the way the processor does, odd frame latest
+    fn decode_global(even: (u64, u64), odd: (u64, u64)) -> Position {
+        let lat_even = even.0 as f64 / CPR_MAX;
+        let lon_even = even.1 as f64 / CPR_MAX;
+        let lat_odd = odd.0 as f64 / CPR_MAX;
+        let lon_odd = odd.1 as f64 / CPR_MAX;
+        let j = (59.0 * lat_even - 60.0 * lat_odd + 0.5).floor();
+        let wrap = |latitude: f64| {
+            if latitude >= 270.0 {
+                latitude - 360.0
+            } else {
+                latitude
+            }
+        };
+        let rlat_even = wrap(360.0 / 60.0 * (modulo(j, 60.0) + lat_even));
+        let rlat_odd = wrap(360.0 / 59.0 * (modulo(j, 59.0) + lat_odd));
+        assert_eq!(nl(rlat_even), nl(rlat_odd));
+        let nl_lat = nl(rlat_odd);
+        let ni = (nl_lat - 1.0).max(1.0);
+        let m = (lon_even * (nl_lat - 1.0) - lon_odd * nl_lat + 0.5).floor();
+        Position {
+            latitude: rlat_odd,
+            longitude: normalize(360.0 / ni * (modulo(m, ni) + lon_odd)),
+        }
+    }
+
+    #[test]
+    fn encodes_known_cpr_vector() {
+        let position = Position {
+            latitude: 52.2572,
+            longitude: 3.9194,
+        };
+        assert_eq!(encode_cpr(&position, false), (93000, 51372));
+    }
+
+    #[test]
+    fn encoded_positions_decode_back() {
+        let positions = [
+            (52.2572, 3.9194),
+            (-33.9, 151.2),
+            (40.6, -73.8),
+            (0.3, -0.2),
+            (-54.8, -68.3),
+            (64.1, -21.9),
+            (86.5, 179.9),
+        ];
+        for &(latitude, longitude) in positions.iter() {
+            let position = Position {
+                latitude,
+                longitude,
+            };
+            let decoded = decode_global(encode_cpr(&position, false), encode_cpr(&position, true));
+            assert!(
+                distance_nm(&position, &decoded) < 0.01,
+                "{:?} decoded as {:?}",
+                position,
+                decoded
+            );
+        }
+    }
+
+    #[test]
+    fn nl_matches_zone_boundaries() {
+        assert_eq!(nl(0.0), 59.0);
+        assert_eq!(nl(10.0), 59.0);
+        assert_eq!(nl(10.5), 58.0);
+        assert_eq!(nl(-52.2572), 36.0);
+        assert_eq!(nl(87.0), 2.0);
+        assert_eq!(nl(89.0), 1.0);
+    }
+
+    #[test]
+    fn distance_of_one_degree_of_latitude() {
+        let a = Position {
+            latitude: 45.0,
+            longitude: 7.0,
+        };
+        let b = destination(&a, 0.0, 60.0);
+        assert!((b.latitude - 46.0).abs() < 0.01);
+        assert!((distance_nm(&a, &b) - 60.0).abs() < 1e-6);
+        assert!((bearing(&a, &b) - 0.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::adsbtypes::{ADSBMessage, StationStatistics, StationStatisticsList};
+use crate::config::StationInfo;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Frame counters for a single station, updated by the thread reading its frames and read
+/// when an actor queries them
+#[derive(Default)]
+pub struct Counters {
+    station_id: String,
+    station_name: String,
+    frames_received: AtomicU64,
+    crc_failures: AtomicU64,
+    corrected: AtomicU64,
+    unsupported: AtomicU64,
+    unknown_address: AtomicU64,
+    throttled: AtomicU64,
+    dropped: AtomicU64,
+    dispatch_failures: AtomicU64,
+}
+
+impl Counters {
+    fn new(station_id: &str, station_name: &str) -> Self {
+        Counters {
+            station_id: station_id.to_string(),
+            station_name: station_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn frame_received(&self) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn crc_failed(&self) {
+        self.crc_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_corrected(&self) {
+        self.corrected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_unsupported(&self) {
+        self.unsupported.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn address_rejected(&self) {
+        self.unknown_address.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message_throttled(&self) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> StationStatistics {
+        StationStatistics {
+            station_id: self.station_id.to_string(),
+            station_name: self.station_name.to_string(),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            crc_failures: self.crc_failures.load(Ordering::Relaxed),
+            corrected: self.corrected.load(Ordering::Relaxed),
+            unsupported: self.unsupported.load(Ordering::Relaxed),
+            unknown_address: self.unknown_address.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            dispatch_failures: self.dispatch_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Frame counters for every station fed through a binding, by station id. Feeders in listen
+/// and UDP modes are counted under the station resolved for each of them.
+#[derive(Default)]
+pub struct Statistics {
+    stations: Mutex<HashMap<String, Arc<Counters>>>,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counters of a station, starting them from zero if it wasn't known yet
+    pub fn station(&self, station: &StationInfo) -> Arc<Counters> {
+        self.counters(&station.id, &station.name)
+    }
+
+    /// Counts a message discarded because the queue to the actor was full
+    pub fn message_dropped(&self, message: &ADSBMessage) {
+        self.counters(&message.station_id, &message.station_name)
+            .dropped
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts messages the actor failed to accept, against the station of each
+    pub fn dispatch_failed(&self, messages: &[ADSBMessage]) {
+        for message in messages {
+            self.counters(&message.station_id, &message.station_name)
+                .dispatch_failures
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> StationStatisticsList {
+        let mut stations: Vec<StationStatistics> = self
+            .stations
+            .lock()
+            .unwrap()
+            .values()
+            .map(|counters| counters.snapshot())
+            .collect();
+        stations.sort_by(|a, b| a.station_id.cmp(&b.station_id));
+        StationStatisticsList { stations }
+    }
+
+    fn counters(&self, station_id: &str, station_name: &str) -> Arc<Counters> {
+        self.stations
+            .lock()
+            .unwrap()
+            .entry(station_id.to_string())
+            .or_insert_with(|| Arc::new(Counters::new(station_id, station_name)))
+            .clone()
+    }
+}
@@ -0,0 +1,329 @@
+use crate::adsbtypes::{ConnectionState, StationStatus, StationStatusList};
+use crate::binding::CancelToken;
+use crate::config::StationInfo;
+use crate::dispatch::DispatchQueue;
+use crate::now_millis;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Frame activity of a single station, updated by the thread reading its frames
+#[derive(Default)]
+pub struct Activity {
+    frames: AtomicU64,
+    /// Zero until the first frame is received
+    last_frame_at: AtomicU64,
+}
+
+impl Activity {
+    pub fn frame_received(&self, now: u64) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.last_frame_at.store(now, Ordering::Relaxed);
+    }
+}
+
+struct Entry {
+    station: StationInfo,
+    state: ConnectionState,
+    upstream: Option<String>,
+    activity: Arc<Activity>,
+    /// Frame count and time at which the message rate was last sampled
+    sampled_frames: u64,
+    sampled_at: u64,
+    message_rate: f64,
+}
+
+impl Entry {
+    fn new(station: &StationInfo) -> Self {
+        Entry {
+            station: station.clone(),
+            state: ConnectionState::Disconnected,
+            upstream: None,
+            activity: Arc::new(Activity::default()),
+            sampled_frames: 0,
+            sampled_at: now_millis(),
+            message_rate: 0.0,
+        }
+    }
+
+    fn status(&self, now: u64) -> StationStatus {
+        let last_frame_at = self.activity.last_frame_at.load(Ordering::Relaxed);
+        StationStatus {
+            station_id: self.station.id.to_string(),
+            station_name: self.station.name.to_string(),
+            state: self.state,
+            upstream: self.upstream.clone(),
+            last_frame_at: Some(last_frame_at).filter(|at| *at > 0),
+            message_rate: self.message_rate,
+            reported_at: now,
+            location: self.station.location.clone(),
+        }
+    }
+
+    fn sample(&mut self, now: u64) {
+        let frames = self.activity.frames.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(self.sampled_at);
+        if elapsed > 0 {
+            self.message_rate =
+                frames.saturating_sub(self.sampled_frames) as f64 * 1000.0 / elapsed as f64;
+        }
+        self.sampled_frames = frames;
+        self.sampled_at = now;
+    }
+}
+
+/// Tracks the connection state of the stations fed through a binding, reporting changes to
+/// the bound actor with the `StationStatusChanged` operation. Reports go through the
+/// binding's dispatch queue, so they reach the actor in order with its messages.
+pub struct StatusReporter {
+    queue: Arc<DispatchQueue>,
+    stations: Mutex<HashMap<String, Entry>>,
+}
+
+impl StatusReporter {
+    pub fn new(queue: Arc<DispatchQueue>) -> Self {
+        StatusReporter {
+            queue,
+            stations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the activity counters of a station, which starts out disconnected if it
+    /// wasn't known yet
+    pub fn activity(&self, station: &StationInfo) -> Arc<Activity> {
+        let mut stations = self.stations.lock().unwrap();
+        entry(&mut stations, station).activity.clone()
+    }
+
+    /// Records a station's connection state, reporting it to the actor if it changed
+    pub fn set(&self, station: &StationInfo, state: ConnectionState, upstream: Option<String>) {
+        let now = now_millis();
+        let status = {
+            let mut stations = self.stations.lock().unwrap();
+            let entry = entry(&mut stations, station);
+            if entry.state == state && entry.upstream == upstream {
+                return;
+            }
+            entry.station = station.clone();
+            entry.state = state;
+            entry.upstream = upstream;
+            entry.status(now)
+        };
+        info!("Station {} is now {:?}", &status.station_id, &status.state);
+        self.queue.push_status(status);
+    }
+
+    /// Reports a station fed by a feeder that went away as disconnected, and forgets it so
+    /// that it is no longer reported. The station starts over if a feeder brings it back.
+    pub fn remove(&self, station: &StationInfo) {
+        let now = now_millis();
+        let status = {
+            let mut stations = self.stations.lock().unwrap();
+            let mut entry = match stations.remove(&station.id) {
+                Some(entry) => entry,
+                None => return,
+            };
+            if entry.state == ConnectionState::Disconnected {
+                return;
+            }
+            entry.state = ConnectionState::Disconnected;
+            entry.upstream = None;
+            entry.status(now)
+        };
+        info!("Station {} is now {:?}", &status.station_id, &status.state);
+        self.queue.push_status(status);
+    }
+
+    /// Marks every station as disconnected, once its feed has ended
+    pub fn disconnect_all(&self) {
+        let stations: Vec<StationInfo> = self
+            .stations
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.station.clone())
+            .collect();
+        for station in stations {
+            self.set(&station, ConnectionState::Disconnected, None);
+        }
+    }
+
+    /// Current status of every station fed through the binding
+    pub fn statuses(&self) -> StationStatusList {
+        let now = now_millis();
+        StationStatusList {
+            stations: self
+                .stations
+                .lock()
+                .unwrap()
+                .values()
+                .map(|entry| entry.status(now))
+                .collect(),
+        }
+    }
+
+    /// Reports the status of every station with an updated message rate at the given
+    /// interval, until `stop` is cancelled. A zero interval only samples message rates,
+    /// once a minute, without reporting.
+    pub fn run(&self, interval: Duration, stop: &CancelToken) {
+        let periodic = interval > Duration::from_secs(0);
+        let interval = if periodic {
+            interval
+        } else {
+            Duration::from_secs(60)
+        };
+        while stop.sleep(interval) {
+            let now = now_millis();
+            let statuses: Vec<StationStatus> = {
+                let mut stations = self.stations.lock().unwrap();
+                stations
+                    .values_mut()
+                    .map(|entry| {
+                        entry.sample(now);
+                        entry.status(now)
+                    })
+                    .collect()
+            };
+            if periodic {
+                for status in statuses {
+                    self.queue.push_status(status);
+                }
+            }
+        }
+    }
+}
+
+fn entry<'a>(stations: &'a mut HashMap<String, Entry>, station: &StationInfo) -> &'a mut Entry {
+    stations
+        .entry(station.id.to_string())
+        .or_insert_with(|| Entry::new(station))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BindingConfig;
+    use crate::stats::Statistics;
+    use codec::core::CapabilityConfiguration;
+    use std::collections::HashMap;
+
+    fn reporter() -> (Arc<DispatchQueue>, StatusReporter) {
+        let config = BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: HashMap::new(),
+        });
+        let queue = Arc::new(DispatchQueue::new(&config, Arc::new(Statistics::new())));
+        let status = StatusReporter::new(queue.clone());
+        (queue, status)
+    }
+
+    fn station(id: &str) -> StationInfo {
+        StationInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            location: None,
+        }
+    }
+
+    fn states(queue: &DispatchQueue) -> Vec<(String, ConnectionState)> {
+        queue
+            .take_statuses()
+            .into_iter()
+            .map(|status| (status.station_id, status.state))
+            .collect()
+    }
+
+    fn ids(status: &StatusReporter) -> Vec<String> {
+        let mut ids: Vec<String> = status
+            .statuses()
+            .stations
+            .into_iter()
+            .map(|status| status.station_id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn reports_only_changes() {
+        let (queue, status) = reporter();
+        let feeder = Some("10.0.0.1:1234".to_string());
+        status.set(&station("a"), ConnectionState::Connected, feeder.clone());
+        status.set(&station("a"), ConnectionState::Connected, feeder);
+        status.set(&station("a"), ConnectionState::Connected, None);
+        assert_eq!(
+            states(&queue),
+            vec![
+                ("a".to_string(), ConnectionState::Connected),
+                ("a".to_string(), ConnectionState::Connected)
+            ]
+        );
+    }
+
+    #[test]
+    fn forgets_removed_stations() {
+        let (queue, status) = reporter();
+        status.set(&station("a"), ConnectionState::Connected, None);
+        status.set(&station("b"), ConnectionState::Connected, None);
+        queue.take_statuses();
+
+        status.remove(&station("a"));
+        assert_eq!(
+            states(&queue),
+            vec![("a".to_string(), ConnectionState::Disconnected)]
+        );
+        assert_eq!(ids(&status), vec!["b"]);
+
+        // Removing it again, or a station that was never fed, reports nothing
+        status.remove(&station("a"));
+        status.remove(&station("c"));
+        assert!(states(&queue).is_empty());
+    }
+
+    #[test]
+    fn removes_disconnected_stations_silently() {
+        let (queue, status) = reporter();
+        status.activity(&station("a"));
+        status.remove(&station("a"));
+        assert!(states(&queue).is_empty());
+        assert!(ids(&status).is_empty());
+    }
+
+    #[test]
+    fn starts_over_with_stations_brought_back() {
+        let (queue, status) = reporter();
+        status.activity(&station("a")).frame_received(now_millis());
+        status.set(&station("a"), ConnectionState::Connected, None);
+        status.remove(&station("a"));
+        status.set(&station("a"), ConnectionState::Connected, None);
+        assert_eq!(
+            states(&queue),
+            vec![
+                ("a".to_string(), ConnectionState::Connected),
+                ("a".to_string(), ConnectionState::Disconnected),
+                ("a".to_string(), ConnectionState::Connected)
+            ]
+        );
+        assert_eq!(status.statuses().stations[0].last_frame_at, None);
+    }
+
+    #[test]
+    fn disconnects_every_station() {
+        let (queue, status) = reporter();
+        status.set(&station("a"), ConnectionState::Connected, None);
+        status.set(&station("b"), ConnectionState::Reconnecting, None);
+        queue.take_statuses();
+        status.disconnect_all();
+        let mut states = states(&queue);
+        states.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            states,
+            vec![
+                ("a".to_string(), ConnectionState::Disconnected),
+                ("b".to_string(), ConnectionState::Disconnected)
+            ]
+        );
+        assert_eq!(ids(&status), vec!["a", "b"]);
+    }
+}
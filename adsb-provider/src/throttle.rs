@@ -0,0 +1,329 @@
+use crate::adsbtypes::{ADSBMessage, ADSBMessagePayload, Parity};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Altitude change in feet that is delivered regardless of the throttle
+const ALTITUDE_DELTA: u16 = 100;
+/// Heading or track change in degrees that is delivered regardless of the throttle
+const HEADING_DELTA: f64 = 3.0;
+/// Ground speed change in knots that is delivered regardless of the throttle
+const SPEED_DELTA: f64 = 5.0;
+/// Vertical rate change in feet per minute that is delivered regardless of the throttle
+const VERTICAL_RATE_DELTA: i32 = 256;
+
+/// Kinds of message throttled independently of each other. Even and odd CPR frames are
+/// throttled separately so that the processor keeps receiving pairs for global decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Identification,
+    AirbornePosition { odd: bool },
+    AirbornePositionGNSS { odd: bool },
+    SurfacePosition { odd: bool },
+    DecodedPosition,
+    Velocity,
+    SurveillanceAltitude,
+    SurveillanceIdentity,
+    AircraftStatus,
+    TargetState,
+    OperationalStatus,
+}
+
+impl Kind {
+    fn of(payload: &ADSBMessagePayload) -> Self {
+        match payload {
+            ADSBMessagePayload::AircraftIdentification { .. } => Kind::Identification,
+            ADSBMessagePayload::AirbornePosition { cpr_frame, .. } => Kind::AirbornePosition {
+                odd: cpr_frame.parity == Parity::Odd,
+            },
+            ADSBMessagePayload::AirbornePositionGNSS { cpr_frame, .. } => {
+                Kind::AirbornePositionGNSS {
+                    odd: cpr_frame.parity == Parity::Odd,
+                }
+            }
+            ADSBMessagePayload::SurfacePosition { cpr_frame, .. } => Kind::SurfacePosition {
+                odd: cpr_frame.parity == Parity::Odd,
+            },
+            ADSBMessagePayload::DecodedPosition { .. } => Kind::DecodedPosition,
+            ADSBMessagePayload::AirborneVelocity { .. } => Kind::Velocity,
+            ADSBMessagePayload::SurveillanceAltitude { .. } => Kind::SurveillanceAltitude,
+            ADSBMessagePayload::SurveillanceIdentity { .. } => Kind::SurveillanceIdentity,
+            ADSBMessagePayload::AircraftStatus { .. } => Kind::AircraftStatus,
+            ADSBMessagePayload::TargetState { .. } => Kind::TargetState,
+            ADSBMessagePayload::OperationalStatus { .. } => Kind::OperationalStatus,
+        }
+    }
+}
+
+/// Limits each aircraft to one message of each kind per interval. Messages whose values
+/// changed meaningfully since the last delivered message of their kind are delivered
+/// regardless, so discrete changes such as a new callsign or squawk are never held back.
+pub struct Throttle {
+    interval: u64,
+    delivered: HashMap<(String, Kind), (u64, ADSBMessagePayload)>,
+    last_purge: u64,
+}
+
+impl Throttle {
+    /// Creates a throttle with the given interval. A zero interval disables throttling.
+    pub fn new(interval: Duration) -> Self {
+        Throttle {
+            interval: interval.as_millis() as u64,
+            delivered: HashMap::new(),
+            last_purge: 0,
+        }
+    }
+
+    /// Indicates whether a message received at `now` (milliseconds since the UNIX epoch)
+    /// should be delivered, recording it as delivered if so
+    pub fn allows(&mut self, message: &ADSBMessage, now: u64) -> bool {
+        if self.interval == 0 {
+            return true;
+        }
+        if now.saturating_sub(self.last_purge) > self.interval {
+            let interval = self.interval;
+            self.delivered
+                .retain(|_, (at, _)| now.saturating_sub(*at) < interval);
+            self.last_purge = now;
+        }
+        let key = (
+            message.header.icao_address.to_string(),
+            Kind::of(&message.payload),
+        );
+        match self.delivered.get(&key) {
+            Some((at, previous))
+                if now.saturating_sub(*at) < self.interval
+                    && !changed(previous, &message.payload) =>
+            {
+                false
+            }
+            _ => {
+                self.delivered.insert(key, (now, message.payload.clone()));
+                true
+            }
+        }
+    }
+}
+
+/// Whether a payload differs meaningfully from the previously delivered one of its kind.
+/// Positions are only compared by altitude since the aircraft moves with every frame.
+fn changed(previous: &ADSBMessagePayload, current: &ADSBMessagePayload) -> bool {
+    match (previous, current) {
+        (
+            ADSBMessagePayload::AirborneVelocity {
+                heading: h1,
+                ground_speed: s1,
+                vertical_rate: v1,
+            },
+            ADSBMessagePayload::AirborneVelocity {
+                heading: h2,
+                ground_speed: s2,
+                vertical_rate: v2,
+            },
+        ) => {
+            heading_difference(*h1, *h2) >= HEADING_DELTA
+                || (s1 - s2).abs() >= SPEED_DELTA
+                || (i32::from(*v1) - i32::from(*v2)).abs() >= VERTICAL_RATE_DELTA
+        }
+        (
+            ADSBMessagePayload::AirbornePosition { altitude: a1, .. },
+            ADSBMessagePayload::AirbornePosition { altitude: a2, .. },
+        )
+        | (
+            ADSBMessagePayload::AirbornePositionGNSS { altitude: a1, .. },
+            ADSBMessagePayload::AirbornePositionGNSS { altitude: a2, .. },
+        )
+        | (
+            ADSBMessagePayload::SurveillanceAltitude { altitude: a1 },
+            ADSBMessagePayload::SurveillanceAltitude { altitude: a2 },
+        ) => altitude_changed(*a1, *a2),
+        (
+            ADSBMessagePayload::DecodedPosition {
+                altitude: a1,
+                on_ground: g1,
+                ..
+            },
+            ADSBMessagePayload::DecodedPosition {
+                altitude: a2,
+                on_ground: g2,
+                ..
+            },
+        ) => {
+            g1 != g2
+                || match (a1, a2) {
+                    (Some(a1), Some(a2)) => altitude_changed(*a1, *a2),
+                    (a1, a2) => a1.is_some() != a2.is_some(),
+                }
+        }
+        (
+            ADSBMessagePayload::SurfacePosition {
+                ground_speed: s1,
+                track: t1,
+                ..
+            },
+            ADSBMessagePayload::SurfacePosition {
+                ground_speed: s2,
+                track: t2,
+                ..
+            },
+        ) => {
+            optional_changed(*s1, *s2, |s1, s2| (s1 - s2).abs() >= SPEED_DELTA)
+                || optional_changed(*t1, *t2, |t1, t2| {
+                    heading_difference(t1, t2) >= HEADING_DELTA
+                })
+        }
+        // Everything else carries discrete values, any change counts
+        (previous, current) => previous != current,
+    }
+}
+
+fn altitude_changed(a1: u16, a2: u16) -> bool {
+    (i32::from(a1) - i32::from(a2)).abs() >= i32::from(ALTITUDE_DELTA)
+}
+
+/// Compares values that may be unavailable, where a value appearing or disappearing counts
+/// as a change
+fn optional_changed(v1: Option<f64>, v2: Option<f64>, differs: impl Fn(f64, f64) -> bool) -> bool {
+    match (v1, v2) {
+        (Some(v1), Some(v2)) => differs(v1, v2),
+        (v1, v2) => v1.is_some() != v2.is_some(),
+    }
+}
+
+/// Smallest angle between two headings in degrees
+fn heading_difference(h1: f64, h2: f64) -> f64 {
+    let difference = (h1 - h2).abs() % 360.0;
+    difference.min(360.0 - difference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsbtypes::{CPRFrame, MessageHeader, Position};
+
+    fn message(icao_address: &str, payload: ADSBMessagePayload) -> ADSBMessage {
+        ADSBMessage {
+            station_id: "station001".to_string(),
+            station_name: "Station".to_string(),
+            received_at: 0,
+            mlat_timestamp: None,
+            rssi: None,
+            header: MessageHeader {
+                downlink_format: 17,
+                capability: 5,
+                icao_address: icao_address.to_string(),
+                type_code: 0,
+            },
+            payload,
+            station_location: None,
+        }
+    }
+
+    fn velocity(heading: f64, ground_speed: f64) -> ADSBMessage {
+        message(
+            "4840D6",
+            ADSBMessagePayload::AirborneVelocity {
+                heading,
+                ground_speed,
+                vertical_rate: 0,
+            },
+        )
+    }
+
+    fn position(altitude: u16, parity: Parity) -> ADSBMessage {
+        message(
+            "4840D6",
+            ADSBMessagePayload::AirbornePosition {
+                altitude,
+                cpr_frame: CPRFrame {
+                    position: Position {
+                        latitude: 93000.0,
+                        longitude: 51372.0,
+                    },
+                    parity,
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn zero_interval_allows_everything() {
+        let mut throttle = Throttle::new(Duration::from_millis(0));
+        assert!(throttle.allows(&velocity(90.0, 400.0), 1000));
+        assert!(throttle.allows(&velocity(90.0, 400.0), 1000));
+    }
+
+    #[test]
+    fn holds_back_unchanged_messages_within_interval() {
+        let mut throttle = Throttle::new(Duration::from_millis(1000));
+        assert!(throttle.allows(&velocity(90.0, 400.0), 10_000));
+        assert!(!throttle.allows(&velocity(91.0, 402.0), 10_500));
+        assert!(throttle.allows(&velocity(91.0, 402.0), 11_000));
+    }
+
+    #[test]
+    fn allows_meaningful_changes_within_interval() {
+        let mut throttle = Throttle::new(Duration::from_millis(1000));
+        assert!(throttle.allows(&velocity(359.0, 400.0), 10_000));
+        assert!(throttle.allows(&velocity(3.0, 400.0), 10_100));
+        assert!(throttle.allows(&velocity(3.0, 410.0), 10_200));
+        assert!(throttle.allows(&position(38000, Parity::Even), 10_000));
+        assert!(!throttle.allows(&position(38050, Parity::Even), 10_100));
+        assert!(throttle.allows(&position(38100, Parity::Even), 10_200));
+    }
+
+    #[test]
+    fn counts_decoded_altitudes_appearing_as_changes() {
+        let mut throttle = Throttle::new(Duration::from_millis(1000));
+        let decoded = |altitude: Option<u16>| {
+            message(
+                "4840D6",
+                ADSBMessagePayload::DecodedPosition {
+                    altitude,
+                    position: Position {
+                        latitude: 52.2572,
+                        longitude: 3.91937,
+                    },
+                    on_ground: false,
+                },
+            )
+        };
+        assert!(throttle.allows(&decoded(None), 10_000));
+        assert!(!throttle.allows(&decoded(None), 10_100));
+        assert!(throttle.allows(&decoded(Some(38000)), 10_200));
+        assert!(!throttle.allows(&decoded(Some(38050)), 10_300));
+        assert!(throttle.allows(&decoded(None), 10_400));
+    }
+
+    #[test]
+    fn throttles_kinds_and_aircraft_independently() {
+        let mut throttle = Throttle::new(Duration::from_millis(1000));
+        assert!(throttle.allows(&position(38000, Parity::Even), 10_000));
+        assert!(throttle.allows(&position(38000, Parity::Odd), 10_100));
+        assert!(throttle.allows(&velocity(90.0, 400.0), 10_200));
+        let other = message(
+            "485020",
+            ADSBMessagePayload::AirborneVelocity {
+                heading: 90.0,
+                ground_speed: 400.0,
+                vertical_rate: 0,
+            },
+        );
+        assert!(throttle.allows(&other, 10_300));
+    }
+
+    #[test]
+    fn compares_discrete_values_exactly() {
+        let mut throttle = Throttle::new(Duration::from_millis(1000));
+        let squawk = |squawk: &str| {
+            message(
+                "4840D6",
+                ADSBMessagePayload::SurveillanceIdentity {
+                    squawk: squawk.to_string(),
+                },
+            )
+        };
+        assert!(throttle.allows(&squawk("1200"), 10_000));
+        assert!(!throttle.allows(&squawk("1200"), 10_100));
+        assert!(throttle.allows(&squawk("7700"), 10_200));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::security::Security;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Upper bound on the time spent connecting to the primary upstream in a probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A feed the provider connects to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upstream {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Upstream {
+    /// Parses `<host>[:<port>]`, where IPv6 addresses with a port are enclosed in brackets
+    pub fn parse(value: &str, default_port: u16) -> Option<Upstream> {
+        let value = value.trim();
+        let (host, port) = match value.rfind(':') {
+            Some(i) if value[..i].ends_with(']') || !value[..i].contains(':') => {
+                (&value[..i], value[i + 1..].parse().ok()?)
+            }
+            _ => (value, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return None;
+        }
+        Some(Upstream {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Connects to the upstream, completing the TLS handshake and authentication if the
+    /// binding is configured for them
+    pub fn connect(&self, timeout: Duration, security: &Security) -> io::Result<Connection> {
+        let stream = self.open(timeout)?;
+        let socket = stream.try_clone()?;
+        let reader = security.establish(self, stream)?;
+        Ok(Connection { socket, reader })
+    }
+
+    fn open(&self, timeout: Duration) -> io::Result<TcpStream> {
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "host did not resolve");
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    // Bounds the TLS handshake and authentication until the caller switches
+                    // to the idle timeout
+                    stream.set_read_timeout(Some(timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// An established connection to an upstream feed
+pub struct Connection {
+    /// The underlying socket, which can be shut down to interrupt a blocking read
+    pub socket: TcpStream,
+    /// The stream frames are read from
+    pub reader: Box<dyn Read + Send>,
+}
+
+/// Connects to the first reachable upstream, in order of preference, returning its index
+/// along with the connection
+pub fn connect_any(
+    upstreams: &[Upstream],
+    timeout: Duration,
+    security: &Security,
+) -> Option<(usize, Connection)> {
+    for (index, upstream) in upstreams.iter().enumerate() {
+        match upstream.connect(timeout, security) {
+            Ok(stream) => return Some((index, stream)),
+            Err(e) => error!("Failed to connect to {}: {}", upstream, e),
+        }
+    }
+    None
+}
+
+/// Probes the primary upstream every interval while a backup is in use. Probes run on a
+/// thread of their own, so that they don't wait for frames from a quiet backup. Once the
+/// primary accepts a connection again, the backup's socket is shut down to end reading from
+/// it.
+pub struct Failback {
+    recovered: Arc<Mutex<Option<Connection>>>,
+    /// Dropped to stop probing
+    stop: Option<Sender<()>>,
+    prober: Option<JoinHandle<()>>,
+}
+
+impl Failback {
+    pub fn start(
+        primary: &Upstream,
+        security: &Security,
+        interval: Duration,
+        timeout: Duration,
+        backup: &TcpStream,
+    ) -> io::Result<Failback> {
+        let backup = backup.try_clone()?;
+        let primary = primary.clone();
+        let security = security.clone();
+        let timeout = timeout.min(PROBE_TIMEOUT);
+        let recovered = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let slot = recovered.clone();
+        let prober = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match primary.connect(timeout, &security) {
+                    Ok(connection) => {
+                        if let Ok(mut slot) = slot.lock() {
+                            *slot = Some(connection);
+                        }
+                        let _ = backup.shutdown(Shutdown::Both);
+                        return;
+                    }
+                    Err(e) => debug!("Primary upstream {} still unavailable: {}", primary, e),
+                }
+            }
+        });
+        Ok(Failback {
+            recovered,
+            stop: Some(stop),
+            prober: Some(prober),
+        })
+    }
+
+    /// Stops probing, returning a connection to the primary if it recovered
+    pub fn finish(mut self) -> Option<Connection> {
+        self.stop();
+        self.recovered.lock().ok()?.take()
+    }
+
+    fn stop(&mut self) {
+        drop(self.stop.take());
+        if let Some(prober) = self.prober.take() {
+            let _ = prober.join();
+        }
+    }
+}
+
+impl Drop for Failback {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BindingConfig;
+    use codec::core::CapabilityConfiguration;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+
+    fn upstream(host: &str, port: u16) -> Upstream {
+        Upstream {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    fn config(values: &[(&str, &str)]) -> BindingConfig {
+        BindingConfig::from(&CapabilityConfiguration {
+            module: "test".to_string(),
+            values: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        })
+    }
+
+    fn security() -> Security {
+        Security::new(&config(&[])).unwrap()
+    }
+
+    /// Returns a local port nothing listens on
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn listen() -> (TcpListener, Upstream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, upstream("127.0.0.1", port))
+    }
+
+    #[test]
+    fn parses_hosts_and_ports() {
+        assert_eq!(
+            Upstream::parse("receiver", 30002),
+            Some(upstream("receiver", 30002))
+        );
+        assert_eq!(
+            Upstream::parse("receiver:30005", 30002),
+            Some(upstream("receiver", 30005))
+        );
+        assert_eq!(
+            Upstream::parse(" 10.0.0.1:30003 ", 30002),
+            Some(upstream("10.0.0.1", 30003))
+        );
+    }
+
+    #[test]
+    fn parses_ipv6_addresses() {
+        assert_eq!(
+            Upstream::parse("[::1]:30005", 30002),
+            Some(upstream("::1", 30005))
+        );
+        assert_eq!(
+            Upstream::parse("[fe80::1]", 30002),
+            Some(upstream("fe80::1", 30002))
+        );
+        // Without brackets the last group is part of the address, not a port
+        assert_eq!(Upstream::parse("::1", 30002), Some(upstream("::1", 30002)));
+        assert_eq!(
+            Upstream::parse("2001:db8::30005", 30002),
+            Some(upstream("2001:db8::30005", 30002))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_upstreams() {
+        assert_eq!(Upstream::parse("", 30002), None);
+        assert_eq!(Upstream::parse(":30005", 30002), None);
+        assert_eq!(Upstream::parse("[]:30005", 30002), None);
+        assert_eq!(Upstream::parse("receiver:", 30002), None);
+        assert_eq!(Upstream::parse("receiver:http", 30002), None);
+        assert_eq!(Upstream::parse("receiver:70000", 30002), None);
+        assert_eq!(Upstream::parse("[::1]:port", 30002), None);
+    }
+
+    #[test]
+    fn displays_parseable_upstreams() {
+        for value in &["receiver:30005", "10.0.0.1:30002", "[::1]:30005"] {
+            let upstream = Upstream::parse(value, 30002).unwrap();
+            assert_eq!(upstream.to_string(), *value);
+            assert_eq!(Upstream::parse(&upstream.to_string(), 0), Some(upstream));
+        }
+    }
+
+    #[test]
+    fn keeps_the_configured_order_of_upstreams() {
+        let config = config(&[
+            ("FORMAT", "beast"),
+            (
+                "UPSTREAMS",
+                "primary, [::1]:30005, invalid:port,backup:30002",
+            ),
+        ]);
+        assert_eq!(
+            config.upstreams,
+            vec![
+                upstream("primary", 30005),
+                upstream("::1", 30005),
+                upstream("backup", 30002)
+            ]
+        );
+    }
+
+    #[test]
+    fn connects_to_the_first_reachable_upstream() {
+        let (_first, first) = listen();
+        let (_second, second) = listen();
+        let unreachable = upstream("127.0.0.1", closed_port());
+        let timeout = Duration::from_secs(1);
+
+        let upstreams = [unreachable.clone(), second.clone(), first];
+        let (index, _) = connect_any(&upstreams, timeout, &security()).unwrap();
+        assert_eq!(index, 1);
+
+        let upstreams = [second, unreachable.clone()];
+        let (index, _) = connect_any(&upstreams, timeout, &security()).unwrap();
+        assert_eq!(index, 0);
+
+        assert!(connect_any(&[unreachable], timeout, &security()).is_none());
+    }
+
+    #[test]
+    fn fails_back_once_the_primary_accepts_connections() {
+        let (backup_listener, backup) = listen();
+        let connection = backup.connect(Duration::from_secs(1), &security()).unwrap();
+        let _feed = backup_listener.accept().unwrap();
+        let port = closed_port();
+        let primary = upstream("127.0.0.1", port);
+
+        let failback = Failback::start(
+            &primary,
+            &security(),
+            Duration::from_millis(20),
+            Duration::from_secs(1),
+            &connection.socket,
+        )
+        .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let primary_listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+        // The backup stays quiet, yet its read ends once the primary is back
+        let mut reader = connection.reader;
+        let mut buf = [0; 1];
+        assert_eq!(reader.read(&mut buf).unwrap_or(0), 0);
+        assert!(failback.finish().is_some());
+        assert!(primary_listener.accept().is_ok());
+    }
+
+    #[test]
+    fn stops_probing_when_finished() {
+        let (_backup_listener, backup) = listen();
+        let connection = backup.connect(Duration::from_secs(1), &security()).unwrap();
+        let primary = upstream("127.0.0.1", closed_port());
+        let failback = Failback::start(
+            &primary,
+            &security(),
+            Duration::from_secs(60),
+            Duration::from_secs(1),
+            &connection.socket,
+        )
+        .unwrap();
+        assert!(failback.finish().is_none());
+    }
+}
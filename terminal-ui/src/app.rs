@@ -1,9 +1,14 @@
 use crate::util::StatefulTable;
+use crate::StationLocation;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tui::widgets::TableState;
 
+/// Stations whose provider hasn't reported for this long are shown as stale
+const STATUS_TIMEOUT_MS: u64 = 60_000;
+
 pub struct App {
     pub stations_state: TableState,
     pub current_station: Arc<RwLock<Option<String>>>,
@@ -18,8 +23,10 @@ pub struct App {
 pub struct Station {
     pub name: String,
     pub location: String,
-    pub coords: (f64, f64),
+    pub coords: Option<(f64, f64)>,
     pub status: String,
+    /// Time of the provider's most recent status report, in milliseconds since the UNIX epoch
+    pub reported_at: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -30,36 +37,17 @@ pub struct Flight {
     pub heading: String,
     pub altitude: String,
     pub last_seen: String,
+    /// Time of the most recent message, in milliseconds since the UNIX epoch
+    pub seen_at: u64,
 }
 
 impl App {
     pub fn new() -> App {
-        let mut stations = HashMap::new();
-        stations.insert(
-            "kevin_lab".to_string(),
-            Station {
-                name: "kevin_lab".to_string(),
-                location: "Windsor, CT".to_string(),
-                coords: (41.85278, -72.64306),
-                status: "Up".to_string(),
-            },
-        );
-
-        stations.insert(
-            "liam_lab".to_string(),
-            Station {
-                name: "liam_lab".to_string(),
-                location: "Washington, DC".to_string(),
-                coords: (38.8951, -77.0364),
-                status: "Up".to_string(),
-            },
-        );
-
         App {
             stations_state: TableState::default(),
-            stations: Arc::new(RwLock::new(stations)),
+            stations: Arc::new(RwLock::new(load_stations())),
             flights: Arc::new(RwLock::new(HashMap::new())),
-            current_station: Arc::new(RwLock::new(Some("kevin_lab".to_string()))),
+            current_station: Arc::new(RwLock::new(None)),
             render_stations: vec![],
             render_flights: vec![],
             render_station: "...".to_string(),
@@ -116,9 +104,13 @@ impl App {
 
     pub fn update(&mut self) {
         // update state over time
+        let now = now_millis();
         self.render_flights.clear();
         self.render_flights
             .clone_from(&self.flights.read().unwrap().values().cloned().collect());
+        for flight in self.render_flights.iter_mut() {
+            flight.last_seen = format!("{}s", now.saturating_sub(flight.seen_at) / 1000);
+        }
         self.render_flights
             .sort_by(|a, b| a.callsign.cmp(&b.callsign));
 
@@ -127,7 +119,15 @@ impl App {
             .read()
             .unwrap()
             .values()
-            .map(|v| v.clone())
+            .map(|v| {
+                let mut station = v.clone();
+                if station.reported_at > 0
+                    && now.saturating_sub(station.reported_at) > STATUS_TIMEOUT_MS
+                {
+                    station.status = "Stale".to_string();
+                }
+                station
+            })
             .collect();
         self.render_stations.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -146,6 +146,69 @@ impl App {
     }
 }
 
+// Records the status reported by a station's provider, adding stations we didn't know about
+pub fn update_station_status(
+    stations: &RwLock<HashMap<String, Station>>,
+    id: &str,
+    location: Option<&StationLocation>,
+    state: &str,
+    reported_at: u64,
+) {
+    let mut lock = stations.write().unwrap();
+    let station = lock
+        .entry(id.to_string())
+        .or_insert_with(|| new_station(id, None));
+    if let Some(location) = location {
+        set_location(station, location);
+    }
+    station.status = state.to_string();
+    station.reported_at = reported_at;
+}
+
+// Retrieves the known stations from the RESTful service, starting out empty if it isn't
+// reachable since stations are also added as their status is reported
+fn load_stations() -> HashMap<String, Station> {
+    let url = "http://localhost:8081/stations".to_string();
+    let list: RestStationList = match reqwest::blocking::get(&url)
+        .and_then(|resp| resp.text())
+        .map(|text| serde_json::from_str(&text))
+    {
+        Ok(Ok(list)) => list,
+        _ => return HashMap::new(),
+    };
+    list.stations
+        .into_iter()
+        .map(|(id, station)| {
+            let station = new_station(&id, station.location.as_ref());
+            (id, station)
+        })
+        .collect()
+}
+
+fn new_station(id: &str, location: Option<&StationLocation>) -> Station {
+    let mut station = Station {
+        name: id.to_string(),
+        location: "Unknown".to_string(),
+        coords: None,
+        status: "Unknown".to_string(),
+        reported_at: 0,
+    };
+    if let Some(location) = location {
+        set_location(&mut station, location);
+    }
+    station
+}
+
+fn set_location(station: &mut Station, location: &StationLocation) {
+    if !location.label.is_empty() {
+        station.location = location.label.to_string();
+    }
+    station.coords = location
+        .position
+        .as_ref()
+        .map(|position| (position.latitude, position.longitude));
+}
+
 // Retrieves the current known state of aircraft from the RESTful service
 fn load_flights(flights: Arc<RwLock<HashMap<String, Flight>>>, station: &str) {
     let url = "http://localhost:8081/aircraft".to_string();
@@ -165,6 +228,7 @@ fn load_flights(flights: Arc<RwLock<HashMap<String, Flight>>>, station: &str) {
                     heading: (craft.heading.floor() as i64).to_string(),
                     altitude: format!("{}ft", craft.altitude.to_string()),
                     last_seen: "0s".to_string(),
+                    seen_at: craft.last_seen,
                 },
             );
         }
@@ -182,10 +246,30 @@ pub struct RestAircraft {
     pub heading: f64,
     pub ground_speed: f64,
     pub vertical_rate: i16,
+    #[serde(default)]
+    pub last_seen: u64,
     pub last_reporting_station_id: String,
 }
 
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct RestAircraftList {
     pub aircraft: Vec<RestAircraft>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RestStation {
+    #[serde(default)]
+    pub location: Option<StationLocation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RestStationList {
+    pub stations: HashMap<String, RestStation>,
+}
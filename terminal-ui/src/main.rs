@@ -17,7 +17,7 @@ use tui::{
     Terminal,
 };
 
-use crate::app::{App, Flight, Station};
+use crate::app::{update_station_status, App, Flight, Station};
 use crate::util::event::{Config, Event, Events};
 use serde::{Deserialize, Serialize};
 
@@ -45,21 +45,53 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut app = App::new();
     let flights = app.flights.clone();
     let cs = app.current_station.clone();
+    let stations = app.stations.clone();
 
     let nc = nats::connect("nats://127.0.0.1")?;
     // Using a threaded handler.
     let sub = nc.subscribe(EVENTS_SUBJECT)?.with_handler(move |msg| {
-        let evt: AdsbUpdateEvent = serde_json::from_slice(&msg.data).unwrap();
-        let station = match &evt {
-            AdsbUpdateEvent::AircraftIdentified { source_station, .. }
-            | AdsbUpdateEvent::PositionUpdated { source_station, .. }
-            | AdsbUpdateEvent::VelocityUpdated { source_station, .. } => source_station,
+        if let Ok(StationEvent::StationStatusChanged {
+            source_station,
+            status,
+        }) = serde_json::from_slice(&msg.data)
+        {
+            update_station_status(
+                &stations,
+                &source_station.id,
+                source_station.location.as_ref(),
+                &status.state,
+                status.reported_at,
+            );
+            return Ok(());
+        }
+        // Events this UI doesn't render (e.g. raw CPR frames) are skipped
+        let evt: AdsbUpdateEvent = match serde_json::from_slice(&msg.data) {
+            Ok(evt) => evt,
+            Err(_) => return Ok(()),
+        };
+        let (station, reception) = match &evt {
+            AdsbUpdateEvent::AircraftIdentified {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::PositionUpdated {
+                source_station,
+                reception,
+                ..
+            }
+            | AdsbUpdateEvent::VelocityUpdated {
+                source_station,
+                reception,
+                ..
+            } => (source_station, reception),
         };
         if station.id == *cs.read().unwrap().as_ref().unwrap_or(&"none".to_string()) {
             let mut lock = flights.write().unwrap();
             let entry = lock
                 .entry(evt.key())
                 .and_modify(|f| {
+                    f.seen_at = reception.received_at;
                     match &evt {
                         AdsbUpdateEvent::AircraftIdentified { callsign, .. } => {
                             f.callsign = callsign.to_string();
@@ -83,6 +115,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .or_insert(match &evt {
                     AdsbUpdateEvent::AircraftIdentified { callsign, .. } => Flight {
                         callsign: callsign.to_string(),
+                        seen_at: reception.received_at,
                         ..Default::default()
                     },
                     AdsbUpdateEvent::VelocityUpdated {
@@ -92,6 +125,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     } => Flight {
                         heading: (heading.floor() as i64).to_string(),
                         speed: (ground_speed * 1.852).to_string(),
+                        seen_at: reception.received_at,
                         ..Default::default()
                     },
                     AdsbUpdateEvent::PositionUpdated {
@@ -99,6 +133,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     } => Flight {
                         position: position.to_string(),
                         altitude: format!("{}ft", altitude),
+                        seen_at: reception.received_at,
                         ..Default::default()
                     },
                 });
@@ -141,10 +176,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                     Constraint::Length(10),
                 ]);
 
-            let flights_header = ["Flight", "Position", "KPH", "Heading", "Altitude"];
+            let flights_header = ["Flight", "Position", "KPH", "Heading", "Altitude", "Seen"];
             let flights_rows = app.render_flights.iter().map(|f| {
                 Row::StyledData(
-                    vec![&f.callsign, &f.position, &f.speed, &f.heading, &f.altitude].into_iter(),
+                    vec![
+                        &f.callsign,
+                        &f.position,
+                        &f.speed,
+                        &f.heading,
+                        &f.altitude,
+                        &f.last_seen,
+                    ]
+                    .into_iter(),
                     normal_style,
                 )
             });
@@ -161,6 +204,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     Constraint::Length(10),
                     Constraint::Length(10),
                     Constraint::Length(10),
+                    Constraint::Length(6),
                 ]);
 
             f.render_stateful_widget(table, lefts[0], &mut app.stations_state);
@@ -180,12 +224,16 @@ fn main() -> Result<(), Box<dyn Error>> {
                     ctx.layer();
 
                     for station in app.render_stations.iter() {
-                        let color = if station.status == "Up" {
+                        let (latitude, longitude) = match station.coords {
+                            Some(coords) => coords,
+                            None => continue,
+                        };
+                        let color = if station.status == "Connected" {
                             Color::Green
                         } else {
                             Color::Red
                         };
-                        ctx.print(station.coords.1, station.coords.0, "📡", color);
+                        ctx.print(longitude, latitude, "📡", color);
                     }
                 })
                 .marker(symbols::Marker::Braille)
@@ -224,18 +272,21 @@ pub enum AdsbUpdateEvent {
     AircraftIdentified {
         icao_address: String,
         source_station: EventStation,
+        reception: Reception,
         emitter_category: u8,
         callsign: String,
     },
     PositionUpdated {
         icao_address: String,
         source_station: EventStation,
+        reception: Reception,
         altitude: u16,
         position: Position,
     },
     VelocityUpdated {
         icao_address: String,
         source_station: EventStation,
+        reception: Reception,
         heading: f64,
         ground_speed: f64,
         vertical_rate: i16,
@@ -252,10 +303,40 @@ impl AdsbUpdateEvent {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum StationEvent {
+    StationStatusChanged {
+        source_station: EventStation,
+        status: EventStatus,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventStatus {
+    pub state: String,
+    pub reported_at: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EventStation {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub location: Option<StationLocation>,
+}
+
+/// Location of a station's antenna as configured in its provider
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StationLocation {
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub position: Option<Position>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Reception {
+    pub received_at: u64,
 }
 /// Horizontal coordinates in the geographic coordinate system.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
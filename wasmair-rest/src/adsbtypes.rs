@@ -6,11 +6,80 @@ pub struct Aircraft {
     pub emitter_category: u8,
     pub callsign: String,
     pub altitude: u16,
+    /// Height above the WGS-84 ellipsoid in feet, if the aircraft sends GNSS position
+    /// messages
+    #[serde(default)]
+    pub gnss_height: Option<u16>,
     pub position: Position,
     pub heading: f64,
     pub ground_speed: f64,
     pub vertical_rate: i16,
+    #[serde(default)]
+    pub on_ground: bool,
+    /// Distance from the last station that reported the position in nautical miles
+    #[serde(default)]
+    pub range: Option<f64>,
+    #[serde(default)]
+    pub squawk: String,
+    #[serde(default)]
+    pub emergency_state: EmergencyState,
+    #[serde(default)]
+    pub selected_altitude: Option<u16>,
+    #[serde(default)]
+    pub selected_heading: Option<f64>,
+    #[serde(default)]
+    pub barometric_setting: Option<f64>,
+    #[serde(default)]
+    pub adsb_version: u8,
+    #[serde(default)]
+    pub nac_p: u8,
+    #[serde(default)]
+    pub sil: u8,
+    /// Wall clock time of the most recent message, in milliseconds since the UNIX epoch
+    #[serde(default)]
+    pub last_seen: u64,
     pub last_reporting_station_id: String,
+    /// The flight in progress, if the aircraft is airborne
+    #[serde(default)]
+    pub flight: Option<FlightSession>,
+    /// Stations that currently receive the aircraft, by station id
+    #[serde(default)]
+    pub seen_by: HashMap<String, StationReception>,
+}
+
+/// How a station last received an aircraft
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationReception {
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub last_seen: u64,
+    /// Signal level in dBFS, if supplied by the station's feed
+    pub rssi: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlightSession {
+    pub flight_id: String,
+    /// Wall clock start of the flight, in milliseconds since the UNIX epoch
+    pub started_at: u64,
+}
+
+/// Emergency/priority status broadcast in aircraft status messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EmergencyState {
+    None,
+    General,
+    Medical,
+    MinimumFuel,
+    NoCommunications,
+    UnlawfulInterference,
+    DownedAircraft,
+    Reserved,
+}
+
+impl Default for EmergencyState {
+    fn default() -> Self {
+        EmergencyState::None
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -34,4 +103,162 @@ pub struct StationList {
 pub struct Station {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub status: Option<FeedStatus>,
+    #[serde(default)]
+    pub location: Option<StationLocation>,
+}
+
+/// Location of a station's antenna as configured in its provider
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StationLocation {
+    pub label: String,
+    pub position: Option<Position>,
+    /// Antenna altitude in feet above mean sea level
+    pub altitude: Option<f64>,
+}
+
+/// Health of a station's feed as last reported by its provider
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedStatus {
+    pub state: ConnectionState,
+    pub upstream: Option<String>,
+    pub last_frame_at: Option<u64>,
+    pub message_rate: f64,
+    pub reported_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Replaying,
+    Disconnected,
+}
+
+/// Positions recently decoded for an aircraft, oldest first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Track {
+    pub icao_address: String,
+    pub points: Vec<TrackPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackPoint {
+    /// Wall clock receive time in milliseconds since the UNIX epoch
+    pub time: u64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: u16,
+    pub ground_speed: f64,
+    pub heading: f64,
+    pub station_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GeofenceList {
+    pub geofences: HashMap<String, Geofence>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Geofence {
+    /// Taken from the request path when a fence is stored
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// Vertices in order, the last one being joined back to the first
+    pub polygon: Vec<Position>,
+    /// Lower bound of the altitude band in feet, if any
+    #[serde(default)]
+    pub min_altitude: Option<u16>,
+    /// Upper bound of the altitude band in feet, if any
+    #[serde(default)]
+    pub max_altitude: Option<u16>,
+}
+
+impl Geofence {
+    /// A fence needs at least three vertices with valid coordinates, and an altitude band
+    /// that isn't inverted
+    pub fn is_valid(&self) -> bool {
+        let band_valid = match (self.min_altitude, self.max_altitude) {
+            (Some(min), Some(max)) => min <= max,
+            _ => true,
+        };
+        band_valid
+            && self.polygon.len() >= 3
+            && self
+                .polygon
+                .iter()
+                .all(|vertex| vertex.latitude.abs() <= 90.0 && vertex.longitude.abs() <= 180.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geofence(polygon: &[(f64, f64)]) -> Geofence {
+        Geofence {
+            id: "bdl".to_string(),
+            name: "Bradley".to_string(),
+            polygon: polygon
+                .iter()
+                .map(|(latitude, longitude)| Position {
+                    latitude: *latitude,
+                    longitude: *longitude,
+                })
+                .collect(),
+            min_altitude: None,
+            max_altitude: None,
+        }
+    }
+
+    fn triangle() -> Geofence {
+        geofence(&[(41.0, -73.0), (42.0, -73.0), (42.0, -72.0)])
+    }
+
+    #[test]
+    fn accepts_polygons_of_three_or_more_vertices() {
+        assert!(triangle().is_valid());
+        assert!(geofence(&[(41.0, -73.0), (42.0, -73.0), (42.0, -72.0), (41.0, -72.0)]).is_valid());
+        assert!(!geofence(&[(41.0, -73.0), (42.0, -73.0)]).is_valid());
+        assert!(!geofence(&[]).is_valid());
+    }
+
+    #[test]
+    fn rejects_coordinates_out_of_range() {
+        assert!(geofence(&[(-90.0, 180.0), (90.0, -180.0), (0.0, 0.0)]).is_valid());
+        assert!(!geofence(&[(90.5, -73.0), (42.0, -73.0), (42.0, -72.0)]).is_valid());
+        assert!(!geofence(&[(41.0, -180.5), (42.0, -73.0), (42.0, -72.0)]).is_valid());
+        assert!(!geofence(&[(41.0, std::f64::NAN), (42.0, -73.0), (42.0, -72.0)]).is_valid());
+    }
+
+    #[test]
+    fn rejects_inverted_altitude_bands() {
+        let band = |min_altitude, max_altitude| Geofence {
+            min_altitude,
+            max_altitude,
+            ..triangle()
+        };
+        assert!(band(Some(1000), Some(5000)).is_valid());
+        assert!(band(Some(5000), Some(5000)).is_valid());
+        assert!(band(Some(1000), None).is_valid());
+        assert!(band(None, Some(5000)).is_valid());
+        assert!(!band(Some(5001), Some(5000)).is_valid());
+    }
+
+    #[test]
+    fn parses_fences_without_id_or_band() {
+        let geofence: Geofence = serde_json::from_str(
+            r#"{"polygon": [{"latitude": 41.0, "longitude": -73.0},
+                            {"latitude": 42.0, "longitude": -73.0},
+                            {"latitude": 42.0, "longitude": -72.0}]}"#,
+        )
+        .unwrap();
+        assert!(geofence.is_valid());
+        assert_eq!(geofence.id, "");
+        assert_eq!(geofence.min_altitude, None);
+    }
 }
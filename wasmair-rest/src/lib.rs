@@ -2,10 +2,11 @@ extern crate wascc_actor as actor;
 
 const STATION_LIST_KEY: &str = "adsb:stations";
 const AIRCRAFT_SET_KEY: &str = "adsb:aircraft";
+const GEOFENCES_KEY: &str = "adsb:geofences";
 
 mod adsbtypes;
 use actor::prelude::*;
-use adsbtypes::{AircraftList, StationList};
+use adsbtypes::{AircraftList, Geofence, GeofenceList, StationList, Track};
 
 actor_handlers! {
     codec::http::OP_HANDLE_REQUEST => handle_http,
@@ -13,9 +14,20 @@ actor_handlers! {
 }
 
 fn handle_http(payload: codec::http::Request) -> HandlerResult<codec::http::Response> {
-    match payload.path.to_lowercase().as_ref() {
-        "/stations" => query_stations(),
-        "/aircraft" => query_aircraft(),
+    let path = payload.path.to_lowercase();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["stations"] => query_stations(),
+        ["aircraft"] => query_aircraft(),
+        // ICAO addresses are stored in upper case
+        ["aircraft", icao_address, "track"] => query_track(&icao_address.to_uppercase()),
+        ["geofences"] => query_geofences(),
+        ["geofences", id] => match payload.method.as_str() {
+            "GET" => query_geofence(id),
+            "PUT" => put_geofence(id, &payload.body),
+            "DELETE" => delete_geofence(id),
+            _ => Ok(codec::http::Response::bad_request()),
+        },
         _ => Ok(codec::http::Response::bad_request()),
     }
 }
@@ -47,3 +59,63 @@ fn query_aircraft() -> HandlerResult<codec::http::Response> {
     let res = AircraftList { aircraft: planes };
     Ok(codec::http::Response::json(res, 200, "OK"))
 }
+
+fn query_track(icao_address: &str) -> HandlerResult<codec::http::Response> {
+    let key = format!("{}:{}:track", AIRCRAFT_SET_KEY, icao_address);
+    match keyvalue::default().get(&key) {
+        Ok(Some(s)) => {
+            let track: Track = serde_json::from_str(&s)?;
+            Ok(codec::http::Response::json(track, 200, "OK"))
+        }
+        _ => Ok(codec::http::Response::not_found()),
+    }
+}
+
+fn query_geofences() -> HandlerResult<codec::http::Response> {
+    Ok(codec::http::Response::json(get_geofences()?, 200, "OK"))
+}
+
+fn query_geofence(id: &str) -> HandlerResult<codec::http::Response> {
+    match get_geofences()?.geofences.remove(id) {
+        Some(geofence) => Ok(codec::http::Response::json(geofence, 200, "OK")),
+        None => Ok(codec::http::Response::not_found()),
+    }
+}
+
+/// Creates or replaces a geofence. Its id is taken from the path, which is matched in
+/// lower case.
+fn put_geofence(id: &str, body: &[u8]) -> HandlerResult<codec::http::Response> {
+    let geofence = match serde_json::from_slice::<Geofence>(body) {
+        Ok(geofence) if geofence.is_valid() => Geofence {
+            id: id.to_string(),
+            ..geofence
+        },
+        _ => return Ok(codec::http::Response::bad_request()),
+    };
+    let mut list = get_geofences()?;
+    list.geofences.insert(id.to_string(), geofence.clone());
+    put_geofences(&list)?;
+    Ok(codec::http::Response::json(geofence, 200, "OK"))
+}
+
+fn delete_geofence(id: &str) -> HandlerResult<codec::http::Response> {
+    let mut list = get_geofences()?;
+    if list.geofences.remove(id).is_none() {
+        return Ok(codec::http::Response::not_found());
+    }
+    put_geofences(&list)?;
+    Ok(codec::http::Response::ok())
+}
+
+fn get_geofences() -> HandlerResult<GeofenceList> {
+    Ok(match keyvalue::default().get(GEOFENCES_KEY) {
+        Ok(Some(s)) => serde_json::from_str(&s)?,
+        Ok(None) => GeofenceList::default(),
+        Err(_) => GeofenceList::default(),
+    })
+}
+
+fn put_geofences(list: &GeofenceList) -> HandlerResult<()> {
+    keyvalue::default().set(GEOFENCES_KEY, &serde_json::to_string(list)?, None)?;
+    Ok(())
+}